@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use anyhow::{Error, Result};
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Transaction, TxMerkleNode, TxOut,
+    Txid, absolute::Height, block, consensus::encode::deserialize_hex, hashes::Hash,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+pub struct RpcRequest<'a> {
+    jsonrpc: &'static str,
+    id: &'static str,
+    method: &'a str,
+    params: &'a [Value],
+}
+
+impl<'a> RpcRequest<'a> {
+    pub fn new(method: &'a str, params: &'a [Value]) -> Self {
+        Self {
+            jsonrpc: "1.0",
+            id: "spdk",
+            method,
+            params,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcResponse<T> {
+    pub result: Option<T>,
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+/// The result of `getblock` with verbosity 3.
+#[derive(Debug, Deserialize)]
+pub struct BlockResponse {
+    pub hash: BlockHash,
+    pub height: Height,
+    pub version: i32,
+    #[serde(rename = "previousblockhash")]
+    pub previous_block_hash: Option<BlockHash>,
+    #[serde(rename = "merkleroot")]
+    pub merkle_root: TxMerkleNode,
+    pub time: u32,
+    pub bits: String,
+    pub nonce: u32,
+    pub tx: Vec<BlockTxResponse>,
+}
+
+/// A transaction of `getblock` with verbosity 3, or the result of `getrawtransaction` with verbosity 2.
+#[derive(Debug, Deserialize)]
+pub struct BlockTxResponse {
+    pub hex: String,
+    pub vin: Vec<TxInResponse>,
+}
+
+/// An input of a transaction, the coinbase input has no txid, vout or prevout.
+#[derive(Debug, Deserialize)]
+pub struct TxInResponse {
+    pub txid: Option<Txid>,
+    pub vout: Option<u32>,
+    pub prevout: Option<PrevoutResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrevoutResponse {
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    pub value: Amount,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptPubKeyResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScriptPubKeyResponse {
+    pub hex: ScriptBuf,
+}
+
+impl BlockTxResponse {
+    /// Decode the transaction, and collect the outputs spent by its inputs in the same order.
+    pub fn into_tx_and_prevouts(self) -> Result<(Transaction, Vec<TxOut>)> {
+        let tx = deserialize_hex::<Transaction>(&self.hex)?;
+
+        let prevouts = self
+            .vin
+            .into_iter()
+            .map(|txin| {
+                txin.prevout
+                    .map(|prevout| TxOut {
+                        value: prevout.value,
+                        script_pubkey: prevout.script_pubkey.hex,
+                    })
+                    .ok_or_else(|| {
+                        Error::msg(format!(
+                            "Missing prevout for an input of {}",
+                            tx.compute_txid()
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<TxOut>>>()?;
+
+        Ok((tx, prevouts))
+    }
+}
+
+impl BlockResponse {
+    /// Decode the block, and collect the outputs spent by its inputs.
+    pub fn into_block_and_prevouts(self) -> Result<(Block, HashMap<OutPoint, TxOut>)> {
+        let header = block::Header {
+            version: block::Version::from_consensus(self.version),
+            prev_blockhash: self.previous_block_hash.unwrap_or(BlockHash::all_zeros()),
+            merkle_root: self.merkle_root,
+            time: self.time,
+            bits: CompactTarget::from_unprefixed_hex(&self.bits)?,
+            nonce: self.nonce,
+        };
+
+        let mut txdata = vec![];
+        let mut prevouts = HashMap::new();
+
+        for tx in self.tx {
+            txdata.push(deserialize_hex::<Transaction>(&tx.hex)?);
+
+            for txin in tx.vin {
+                if let (Some(txid), Some(vout), Some(prevout)) =
+                    (txin.txid, txin.vout, txin.prevout)
+                {
+                    prevouts.insert(
+                        OutPoint::new(txid, vout),
+                        TxOut {
+                            value: prevout.value,
+                            script_pubkey: prevout.script_pubkey.hex,
+                        },
+                    );
+                }
+            }
+        }
+
+        let block = Block { header, txdata };
+
+        if block.block_hash() != self.hash {
+            return Err(Error::msg(format!(
+                "Decoded block does not match block hash {}",
+                self.hash
+            )));
+        }
+
+        if !block.check_merkle_root() {
+            return Err(Error::msg(format!(
+                "Transactions do not match the merkle root of block {}",
+                self.hash
+            )));
+        }
+
+        Ok((block, prevouts))
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use bitcoin::{Amount, BlockHash, absolute::Height};
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+
+use spdk_core::chain::{BlockData, ChainBackend, MempoolTxData, SpentIndexData, UtxoData};
+use spdk_indexer::{BlockIndex, mempool_tx_data};
+
+use crate::BitcoindRpcClient;
+
+// bitcoind handles 16 requests at a time by default, see `-rpcworkqueue`
+const CONCURRENT_BLOCK_REQUESTS: usize = 8;
+
+// the blocks that the range stream fetched ahead must still be cached when the scanner gets to them
+const CACHED_BLOCKS: usize = CONCURRENT_BLOCK_REQUESTS + 1;
+
+type BlockCache = Arc<Mutex<VecDeque<Arc<BlockIndex>>>>;
+
+/// A [ChainBackend] that computes the tweaks and filters from the blocks of a bitcoind node.
+///
+/// Every block is fetched with `getblock` at verbosity 3, which includes the prevouts that are needed to compute the tweaks.
+/// The last blocks that were fetched are cached, so the [spent_index](ChainBackend::spent_index) and [utxos](ChainBackend::utxos)
+/// of a block from [get_block_data_for_range](ChainBackend::get_block_data_for_range) don't fetch it again.
+/// Older blocks are not kept, so the backend does not know which outputs are spent by later blocks.
+/// As a result, the cut-through variant only leaves out outputs that are spent in the same block,
+/// and [utxos](ChainBackend::utxos) only marks those outputs as spent.
+///
+/// The [mempool](ChainBackend::mempool) is fetched with `getrawmempool` and `getrawtransaction` at verbosity 2,
+/// which needs bitcoind v25.0 or later.
+pub struct BitcoindRpcBackend {
+    client: BitcoindRpcClient,
+    recent_blocks: BlockCache,
+}
+
+impl fmt::Debug for BitcoindRpcBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitcoindRpcBackend")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BitcoindRpcBackend {
+    pub fn new(client: BitcoindRpcClient) -> Self {
+        Self {
+            client,
+            recent_blocks: Arc::new(Mutex::new(VecDeque::with_capacity(CACHED_BLOCKS))),
+        }
+    }
+
+    /// The block at the given height, from the cache if the hash at that height didn't change.
+    async fn cached_block_index(&self, block_height: Height) -> Result<Arc<BlockIndex>> {
+        let blkhash = self.client.block_hash(block_height).await?;
+
+        if let Some(block) = lock_blocks(&self.recent_blocks)?
+            .iter()
+            .find(|block| block.height() == block_height && block.hash() == blkhash)
+        {
+            return Ok(block.clone());
+        }
+
+        let block =
+            Arc::new(Self::block_index_for_hash(&self.client, block_height, blkhash).await?);
+        cache_block(&self.recent_blocks, block.clone())?;
+
+        Ok(block)
+    }
+
+    async fn block_index(client: &BitcoindRpcClient, block_height: Height) -> Result<BlockIndex> {
+        let blkhash = client.block_hash(block_height).await?;
+
+        Self::block_index_for_hash(client, block_height, blkhash).await
+    }
+
+    async fn block_index_for_hash(
+        client: &BitcoindRpcClient,
+        block_height: Height,
+        blkhash: BlockHash,
+    ) -> Result<BlockIndex> {
+        let res = client.block(blkhash).await?;
+
+        if res.height != block_height {
+            return Err(Error::msg(format!(
+                "Expected block at height {}, got {}",
+                block_height, res.height
+            )));
+        }
+
+        let (block, prevouts) = res.into_block_and_prevouts()?;
+
+        BlockIndex::new(block_height, &block, &prevouts)
+    }
+}
+
+fn lock_blocks(blocks: &BlockCache) -> Result<MutexGuard<'_, VecDeque<Arc<BlockIndex>>>> {
+    blocks
+        .lock()
+        .map_err(|_| Error::msg("Block cache lock is poisoned"))
+}
+
+/// Add a block to the cache, in place of an older block at the same height.
+fn cache_block(blocks: &BlockCache, block: Arc<BlockIndex>) -> Result<()> {
+    let mut blocks = lock_blocks(blocks)?;
+
+    blocks.retain(|cached| cached.height() != block.height());
+    if blocks.len() == CACHED_BLOCKS {
+        blocks.pop_front();
+    }
+    blocks.push_back(block);
+
+    Ok(())
+}
+
+#[async_trait]
+impl ChainBackend for BitcoindRpcBackend {
+    fn get_block_data_for_range(
+        &self,
+        range: RangeInclusive<Height>,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Pin<Box<dyn Stream<Item = Result<BlockData>> + Send>> {
+        let client = self.client.clone();
+        let recent_blocks = self.recent_blocks.clone();
+
+        // convert range to u32 since Height does not implement Step
+        let range = range.start().to_consensus_u32()..=range.end().to_consensus_u32();
+
+        let res = stream::iter(range)
+            .map(move |n| {
+                let client = client.clone();
+                let recent_blocks = recent_blocks.clone();
+
+                async move {
+                    let blkheight = Height::from_consensus(n)?;
+                    let block = Arc::new(Self::block_index(&client, blkheight).await?);
+                    cache_block(&recent_blocks, block.clone())?;
+                    Ok(block.block_data(dust_limit, with_cutthrough))
+                }
+            })
+            .buffered(CONCURRENT_BLOCK_REQUESTS);
+
+        Box::pin(res)
+    }
+
+    async fn spent_index(&self, block_height: Height) -> Result<SpentIndexData> {
+        Ok(self.cached_block_index(block_height).await?.spent_index())
+    }
+
+    async fn utxos(&self, block_height: Height) -> Result<Vec<UtxoData>> {
+        Ok(self.cached_block_index(block_height).await?.utxos())
+    }
+
+    async fn block_height(&self) -> Result<Height> {
+        self.client.block_count().await
+    }
+
+    async fn block_hash(&self, block_height: Height) -> Result<BlockHash> {
+        self.client.block_hash(block_height).await
+    }
+
+    /// Transactions that leave the mempool while it is fetched are left out.
+    async fn mempool(&self, dust_limit: Amount) -> Result<Vec<MempoolTxData>> {
+        let txids = self.client.raw_mempool().await?;
+        let client = &self.client;
+
+        let txs: Vec<Option<MempoolTxData>> = stream::iter(txids)
+            .map(|txid| async move {
+                let Some(res) = client.mempool_transaction(txid).await? else {
+                    return Ok(None);
+                };
+                let (tx, prevouts) = res.into_tx_and_prevouts()?;
+
+                if tx.compute_txid() != txid {
+                    return Err(Error::msg(format!("Expected transaction {}", txid)));
+                }
+
+                mempool_tx_data(&tx, &prevouts, dust_limit)
+            })
+            .buffered(CONCURRENT_BLOCK_REQUESTS)
+            .try_collect()
+            .await?;
+
+        Ok(txs.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use bitcoin::absolute::Height;
+use bitcoin::{BlockHash, Txid};
+use reqwest::{Client, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use super::api_structs::{BlockResponse, BlockTxResponse, RpcError, RpcRequest, RpcResponse};
+
+// the error code of bitcoind for an unknown transaction
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// How to authenticate with the RPC server.
+#[derive(Clone, Debug)]
+pub enum Auth {
+    None,
+    UserPass(String, String),
+    /// The `.cookie` file in the data directory of bitcoind.
+    CookieFile(PathBuf),
+}
+
+#[derive(Clone)]
+pub struct BitcoindRpcClient {
+    client: Client,
+    host_url: Url,
+    credentials: Option<(String, String)>,
+}
+
+impl std::fmt::Debug for BitcoindRpcClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BitcoindRpcClient")
+            .field("host_url", &self.host_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BitcoindRpcClient {
+    pub fn new(host_url: &str, auth: Auth) -> Result<Self> {
+        let host_url = Url::parse(host_url)?;
+        let client = reqwest::Client::new();
+
+        let credentials = match auth {
+            Auth::None => None,
+            Auth::UserPass(user, pass) => Some((user, pass)),
+            Auth::CookieFile(path) => {
+                let cookie = std::fs::read_to_string(&path)?;
+                let (user, pass) = cookie
+                    .trim()
+                    .split_once(':')
+                    .ok_or_else(|| Error::msg(format!("Invalid cookie file {}", path.display())))?;
+                Some((user.to_owned(), pass.to_owned()))
+            }
+        };
+
+        Ok(BitcoindRpcClient {
+            client,
+            host_url,
+            credentials,
+        })
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: &[Value]) -> Result<T> {
+        self.try_call(method, params)
+            .await?
+            .map_err(|err| rpc_error(method, err))
+    }
+
+    /// Same as [call](Self::call), but an error returned by bitcoind is kept apart from the other errors.
+    async fn try_call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[Value],
+    ) -> Result<std::result::Result<T, RpcError>> {
+        let mut req = self
+            .client
+            .post(self.host_url.clone())
+            .json(&RpcRequest::new(method, params));
+
+        if let Some((user, pass)) = &self.credentials {
+            req = req.basic_auth(user, Some(pass));
+        }
+
+        let res = req.send().await?;
+
+        // bitcoind replies with an empty body if authentication fails
+        if res.status() == StatusCode::UNAUTHORIZED {
+            return Err(Error::msg("RPC authentication failed"));
+        }
+
+        let res: RpcResponse<T> = serde_json::from_str(&res.text().await?)?;
+
+        match (res.result, res.error) {
+            (_, Some(err)) => Ok(Err(err)),
+            (Some(result), None) => Ok(Ok(result)),
+            (None, None) => Err(Error::msg(format!("Empty RPC response for {}", method))),
+        }
+    }
+
+    pub async fn block_count(&self) -> Result<Height> {
+        self.call("getblockcount", &[]).await
+    }
+
+    pub async fn block_hash(&self, block_height: Height) -> Result<BlockHash> {
+        self.call("getblockhash", &[json!(block_height.to_consensus_u32())])
+            .await
+    }
+
+    /// Get a block including the prevouts of its inputs, this needs bitcoind v23.0 or later.
+    pub async fn block(&self, block_hash: BlockHash) -> Result<BlockResponse> {
+        self.call("getblock", &[json!(block_hash), json!(3)]).await
+    }
+
+    pub async fn raw_mempool(&self) -> Result<Vec<Txid>> {
+        self.call("getrawmempool", &[]).await
+    }
+
+    /// Get a transaction including the prevouts of its inputs, this needs bitcoind v25.0 or later.
+    ///
+    /// Confirmed transactions can only be found if bitcoind runs with `-txindex`.
+    pub async fn transaction(&self, txid: Txid) -> Result<BlockTxResponse> {
+        self.call("getrawtransaction", &[json!(txid), json!(2)])
+            .await
+    }
+
+    /// Same as [transaction](Self::transaction), but returns None if the transaction is not in the mempool,
+    /// e.g. because it was mined or replaced after it was listed with [raw_mempool](Self::raw_mempool).
+    pub async fn mempool_transaction(&self, txid: Txid) -> Result<Option<BlockTxResponse>> {
+        match self
+            .try_call("getrawtransaction", &[json!(txid), json!(2)])
+            .await?
+        {
+            Ok(res) => Ok(Some(res)),
+            Err(err) if err.code == RPC_INVALID_ADDRESS_OR_KEY => Ok(None),
+            Err(err) => Err(rpc_error("getrawtransaction", err)),
+        }
+    }
+
+    pub async fn send_raw_transaction(&self, tx_hex: String) -> Result<Txid> {
+        self.call("sendrawtransaction", &[json!(tx_hex)]).await
+    }
+}
+
+fn rpc_error(method: &str, err: RpcError) -> Error {
+    Error::msg(format!(
+        "RPC error calling {}: {} ({})",
+        method, err.message, err.code
+    ))
+}
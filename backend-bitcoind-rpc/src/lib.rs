@@ -0,0 +1,6 @@
+pub mod api_structs;
+mod backend;
+mod client;
+
+pub use backend::BitcoindRpcBackend;
+pub use client::{Auth, BitcoindRpcClient};
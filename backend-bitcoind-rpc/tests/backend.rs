@@ -0,0 +1,320 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use backend_bitcoind_rpc::{Auth, BitcoindRpcBackend, BitcoindRpcClient};
+use bitcoin::absolute::Height;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::{Amount, TxOut, Txid};
+use futures::StreamExt;
+use serde_json::json;
+use spdk_core::chain::ChainBackend;
+use spdk_indexer::{BlockIndex, mempool_tx_data};
+use spdk_wallet::scanner::{CancellationToken, SpScanner};
+
+use crate::mock::chain::{MockChain, START_HEIGHT, receiver};
+use crate::mock::server::MockRpcServer;
+use crate::mock::updater::MockUpdater;
+
+mod mock;
+
+const DUST_LIMIT: Amount = Amount::from_sat(546);
+
+// base64 of "user:pass"
+const AUTHORIZATION: &str = "Basic dXNlcjpwYXNz";
+
+fn height(n: u32) -> Height {
+    Height::from_consensus(n).unwrap()
+}
+
+async fn start_server(chain: Arc<MockChain>) -> MockRpcServer {
+    MockRpcServer::start(AUTHORIZATION, move |method, params| {
+        chain.handle(method, params)
+    })
+    .await
+}
+
+fn new_client(server: &MockRpcServer) -> BitcoindRpcClient {
+    let auth = Auth::UserPass("user".to_owned(), "pass".to_owned());
+    BitcoindRpcClient::new(&server.url, auth).unwrap()
+}
+
+#[tokio::test]
+async fn block_data_is_computed_from_blocks() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone()).await;
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+
+    let range = height(START_HEIGHT)..=height(START_HEIGHT + 2);
+    let block_data: Vec<_> = backend
+        .get_block_data_for_range(range, DUST_LIMIT, false)
+        .collect()
+        .await;
+    assert_eq!(block_data.len(), 3);
+
+    for (n, data) in block_data.into_iter().enumerate() {
+        let data = data.unwrap();
+        let (block, prevouts) = &chain.blocks[n];
+        let expected = BlockIndex::new(height(START_HEIGHT + n as u32), block, prevouts)
+            .unwrap()
+            .block_data(DUST_LIMIT, false);
+
+        assert_eq!(data.blkheight, expected.blkheight);
+        assert_eq!(data.blkhash, block.block_hash());
+        assert_eq!(data.tweaks, expected.tweaks);
+        assert_eq!(data.new_utxo_filter.data, expected.new_utxo_filter.data);
+        assert_eq!(data.spent_filter.data, expected.spent_filter.data);
+    }
+
+    // only the payment is eligible
+    let utxos = backend.utxos(height(START_HEIGHT + 1)).await.unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].txid, chain.sp_outpoint.txid);
+
+    let spent_index = backend.spent_index(height(START_HEIGHT + 2)).await.unwrap();
+    assert_eq!(spent_index.data.len(), 1);
+
+    assert!(
+        server
+            .methods()
+            .iter()
+            .all(|method| method == "getblockhash" || method == "getblock")
+    );
+}
+
+#[tokio::test]
+async fn last_block_is_cached() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone()).await;
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+
+    let getblock_count = || {
+        server
+            .methods()
+            .iter()
+            .filter(|method| *method == "getblock")
+            .count()
+    };
+
+    let blkheight = height(START_HEIGHT + 1);
+    backend.spent_index(blkheight).await.unwrap();
+    backend.utxos(blkheight).await.unwrap();
+    assert_eq!(getblock_count(), 1);
+
+    backend.utxos(height(START_HEIGHT + 2)).await.unwrap();
+    backend.spent_index(blkheight).await.unwrap();
+    assert_eq!(getblock_count(), 2);
+
+    // the blocks of the range are cached as well
+    let range = height(START_HEIGHT)..=height(START_HEIGHT + 2);
+    let block_data: Vec<_> = backend
+        .get_block_data_for_range(range, DUST_LIMIT, false)
+        .collect()
+        .await;
+    assert_eq!(block_data.len(), 3);
+    assert_eq!(getblock_count(), 5);
+
+    for n in START_HEIGHT..=START_HEIGHT + 2 {
+        backend.spent_index(height(n)).await.unwrap();
+        backend.utxos(height(n)).await.unwrap();
+    }
+    assert_eq!(getblock_count(), 5);
+
+    // a scan fetches every block once
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+    let mut scanner = SpScanner::new(
+        receiver(),
+        Box::new(MockUpdater::default()),
+        Box::new(backend),
+        HashSet::new(),
+        CancellationToken::new(),
+    );
+    scanner
+        .scan_blocks(
+            height(START_HEIGHT)..=height(START_HEIGHT + 2),
+            DUST_LIMIT,
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(getblock_count(), 8);
+}
+
+#[tokio::test]
+async fn scan_with_bitcoind_backend() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone()).await;
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+
+    let updater = MockUpdater::default();
+    let updates = updater.updates.clone();
+    let cancel = CancellationToken::new();
+
+    let mut scanner = SpScanner::new(
+        receiver(),
+        Box::new(updater),
+        Box::new(backend),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    scanner
+        .scan_blocks(
+            height(START_HEIGHT)..=height(START_HEIGHT + 2),
+            DUST_LIMIT,
+            true,
+        )
+        .await
+        .unwrap();
+
+    let updates = updates.lock().unwrap();
+    assert_eq!(updates.len(), 3);
+
+    // the payment is found
+    assert_eq!(updates[1].blkheight, height(START_HEIGHT + 1));
+    assert_eq!(updates[1].blkhash, chain.blocks[1].0.block_hash());
+    assert_eq!(updates[1].discovered_outputs.len(), 1);
+    assert_eq!(
+        updates[1].discovered_outputs[&chain.sp_outpoint].value,
+        Amount::from_sat(50_000)
+    );
+
+    // and spent in the next block
+    assert_eq!(
+        updates[2].discovered_inputs,
+        HashSet::from([chain.sp_outpoint])
+    );
+}
+
+#[tokio::test]
+async fn mempool_is_computed_from_transactions() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone()).await;
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+
+    let txs = backend.mempool(DUST_LIMIT).await.unwrap();
+    assert_eq!(txs.len(), 1);
+
+    let (tx, prevouts) = &chain.mempool[0];
+    let prevouts: Vec<TxOut> = tx
+        .input
+        .iter()
+        .map(|txin| prevouts[&txin.previous_output].clone())
+        .collect();
+    let expected = mempool_tx_data(tx, &prevouts, DUST_LIMIT).unwrap().unwrap();
+
+    assert_eq!(txs[0].txid, chain.mempool_sp_outpoint.txid);
+    assert_eq!(txs[0].tweak, expected.tweak);
+
+    // only the taproot output
+    assert_eq!(txs[0].outputs.len(), 1);
+    assert_eq!(txs[0].outputs[0].vout, chain.mempool_sp_outpoint.vout);
+    assert_eq!(txs[0].outputs[0].value, Amount::from_sat(30_000));
+    assert!(!txs[0].outputs[0].spent);
+
+    // the output is below the dust limit
+    let txs = backend.mempool(Amount::from_sat(30_001)).await.unwrap();
+    assert!(txs.is_empty());
+}
+
+#[tokio::test]
+async fn transactions_that_leave_the_mempool_are_skipped() {
+    let chain = Arc::new(MockChain::new());
+    let handler_chain = chain.clone();
+    let server = MockRpcServer::start(AUTHORIZATION, move |method, params| {
+        let res = handler_chain.handle(method, params);
+        match method {
+            // a transaction that is mined before it is fetched
+            "getrawmempool" => res.map(|txids| {
+                let mut txids: Vec<Txid> = serde_json::from_value(txids).unwrap();
+                txids.insert(0, Txid::all_zeros());
+                json!(txids)
+            }),
+            _ => res,
+        }
+    })
+    .await;
+    let backend = BitcoindRpcBackend::new(new_client(&server));
+
+    let txs = backend.mempool(DUST_LIMIT).await.unwrap();
+    assert_eq!(txs.len(), 1);
+    assert_eq!(txs[0].txid, chain.mempool_sp_outpoint.txid);
+}
+
+#[tokio::test]
+async fn send_raw_transaction() {
+    let chain = MockChain::new();
+    let tx = chain.blocks[1].0.txdata[1].clone();
+    let txid = tx.compute_txid();
+
+    let server = MockRpcServer::start(AUTHORIZATION, move |method, params| match method {
+        "sendrawtransaction" => {
+            let tx_hex = params[0].as_str().unwrap();
+            let tx = bitcoin::consensus::encode::deserialize_hex::<bitcoin::Transaction>(tx_hex)
+                .unwrap();
+            Ok(json!(tx.compute_txid()))
+        }
+        _ => Err((-32601, "Method not found".to_owned())),
+    })
+    .await;
+    let client = new_client(&server);
+
+    let res: Txid = client
+        .send_raw_transaction(serialize_hex(&tx))
+        .await
+        .unwrap();
+    assert_eq!(res, txid);
+    assert_eq!(server.methods(), vec!["sendrawtransaction"]);
+}
+
+#[tokio::test]
+async fn rpc_errors() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone()).await;
+    let client = new_client(&server);
+
+    assert_eq!(
+        client.block_count().await.unwrap(),
+        height(START_HEIGHT + 2)
+    );
+
+    let err = client
+        .block_hash(height(START_HEIGHT + 3))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Block height out of range"));
+
+    // a block that doesn't exist fails the whole range
+    let backend = BitcoindRpcBackend::new(client);
+    let range = height(START_HEIGHT + 2)..=height(START_HEIGHT + 3);
+    let block_data: Vec<_> = backend
+        .get_block_data_for_range(range, DUST_LIMIT, false)
+        .collect()
+        .await;
+    assert!(block_data[0].is_ok());
+    assert!(block_data[1].is_err());
+
+    // wrong credentials
+    let auth = Auth::UserPass("user".to_owned(), "wrong".to_owned());
+    let client = BitcoindRpcClient::new(&server.url, auth).unwrap();
+    let err = client.block_count().await.unwrap_err();
+    assert!(err.to_string().contains("authentication"));
+}
+
+#[tokio::test]
+async fn cookie_file_auth() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain).await;
+
+    let path = std::env::temp_dir().join(format!("spdk-test-{}.cookie", std::process::id()));
+    std::fs::write(&path, "user:pass").unwrap();
+    let client = BitcoindRpcClient::new(&server.url, Auth::CookieFile(path.clone())).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        client.block_count().await.unwrap(),
+        height(START_HEIGHT + 2)
+    );
+
+    assert!(BitcoindRpcClient::new(&server.url, Auth::CookieFile(path)).is_err());
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::hashes::Hash;
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf, Transaction, TxIn,
+    TxMerkleNode, TxOut, Txid, WPubkeyHash, Witness, transaction,
+};
+use serde_json::{Value, json};
+use silentpayments::sending::generate_recipient_pubkeys;
+use silentpayments::utils::sending::calculate_partial_secret;
+use spdk_wallet::client::{SpClient, SpendKey};
+
+use super::server::RpcResult;
+
+pub const START_HEIGHT: u32 = 100;
+
+pub fn receiver() -> SpClient {
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Regtest).unwrap()
+}
+
+fn new_block(prev_blockhash: BlockHash, txdata: Vec<Transaction>) -> Block {
+    let mut block = Block {
+        header: Header {
+            version: Version::TWO,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata,
+    };
+    block.header.merkle_root = block.compute_merkle_root().unwrap();
+    block
+}
+
+fn new_tx(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    }
+}
+
+fn coinbase(n: u32, output: Vec<TxOut>) -> Transaction {
+    let input = TxIn {
+        previous_output: OutPoint::null(),
+        script_sig: ScriptBuf::builder().push_int(n as i64).into_script(),
+        ..Default::default()
+    };
+    new_tx(vec![input], output)
+}
+
+fn p2wpkh(pubkey: &PublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(&pubkey.serialize()))
+}
+
+/// A silent payment of `value` to the receiver, with 10_000 sats of fees and the rest as change to the sender.
+fn sp_payment(sender_sk: &SecretKey, funding: OutPoint, value: Amount) -> Transaction {
+    let sender_pk = sender_sk.public_key(&Secp256k1::new());
+    let partial_secret =
+        calculate_partial_secret(&[(*sender_sk, false)], &[funding.into()]).unwrap();
+    let address = receiver().get_receiving_address();
+    let output_keys = generate_recipient_pubkeys(vec![address], partial_secret).unwrap();
+    let output_key = TweakedPublicKey::dangerous_assume_tweaked(output_keys[&address][0]);
+
+    new_tx(
+        vec![TxIn {
+            previous_output: funding,
+            witness: Witness::from_slice(&[vec![0x30; 71], sender_pk.serialize().to_vec()]),
+            ..Default::default()
+        }],
+        vec![
+            TxOut {
+                value,
+                script_pubkey: ScriptBuf::new_p2tr_tweaked(output_key),
+            },
+            TxOut {
+                value: Amount::from_sat(90_000) - value,
+                script_pubkey: p2wpkh(&sender_pk),
+            },
+        ],
+    )
+}
+
+/// A small regtest chain of 3 blocks, the signatures are not valid:
+///
+/// * block 100: a coinbase paying to the sender.
+/// * block 101: the sender pays a silent payment to the receiver.
+/// * block 102: the silent payment output is spent.
+///
+/// The mempool has another silent payment to the receiver, that spends the change of block 101.
+pub struct MockChain {
+    pub blocks: Vec<(Block, HashMap<OutPoint, TxOut>)>,
+    pub sp_outpoint: OutPoint,
+    pub mempool: Vec<(Transaction, HashMap<OutPoint, TxOut>)>,
+    pub mempool_sp_outpoint: OutPoint,
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        let secp = Secp256k1::new();
+        let sender_sk = SecretKey::from_slice(&[0x03; 32]).unwrap();
+        let sender_pk = sender_sk.public_key(&secp);
+
+        let cb = coinbase(
+            START_HEIGHT,
+            vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: p2wpkh(&sender_pk),
+            }],
+        );
+        let block0 = new_block(BlockHash::all_zeros(), vec![cb.clone()]);
+
+        let funding = OutPoint::new(cb.compute_txid(), 0);
+        let payment = sp_payment(&sender_sk, funding, Amount::from_sat(50_000));
+        let block1 = new_block(
+            block0.block_hash(),
+            vec![coinbase(START_HEIGHT + 1, vec![]), payment.clone()],
+        );
+
+        let sp_outpoint = OutPoint::new(payment.compute_txid(), 0);
+        let sweep = new_tx(
+            vec![TxIn {
+                previous_output: sp_outpoint,
+                witness: Witness::from_slice(&[vec![0x01; 64]]),
+                ..Default::default()
+            }],
+            vec![TxOut {
+                value: Amount::from_sat(49_000),
+                script_pubkey: p2wpkh(&sender_pk),
+            }],
+        );
+        let block2 = new_block(
+            block1.block_hash(),
+            vec![coinbase(START_HEIGHT + 2, vec![]), sweep],
+        );
+
+        let change = OutPoint::new(payment.compute_txid(), 1);
+        let unconfirmed = sp_payment(&sender_sk, change, Amount::from_sat(30_000));
+        let mempool_sp_outpoint = OutPoint::new(unconfirmed.compute_txid(), 0);
+
+        Self {
+            mempool: vec![(
+                unconfirmed,
+                HashMap::from([(change, payment.output[1].clone())]),
+            )],
+            mempool_sp_outpoint,
+            blocks: vec![
+                (block0, HashMap::new()),
+                (block1, HashMap::from([(funding, cb.output[0].clone())])),
+                (
+                    block2,
+                    HashMap::from([(sp_outpoint, payment.output[0].clone())]),
+                ),
+            ],
+            sp_outpoint,
+        }
+    }
+
+    fn get(&self, height: u64) -> Option<&(Block, HashMap<OutPoint, TxOut>)> {
+        let n = height.checked_sub(START_HEIGHT as u64)?;
+        self.blocks.get(n as usize)
+    }
+
+    /// A transaction of `getblock` with verbosity 3, this is the same as `getrawtransaction` with verbosity 2.
+    fn verbose_tx(
+        prevout_height: u32,
+        tx: &Transaction,
+        prevouts: &HashMap<OutPoint, TxOut>,
+    ) -> Value {
+        let vin: Vec<Value> = tx
+            .input
+            .iter()
+            .map(|txin| match prevouts.get(&txin.previous_output) {
+                Some(prevout) => json!({
+                    "txid": txin.previous_output.txid,
+                    "vout": txin.previous_output.vout,
+                    "prevout": {
+                        "generated": false,
+                        "height": prevout_height,
+                        "value": prevout.value.to_btc(),
+                        "scriptPubKey": {"hex": prevout.script_pubkey.to_hex_string()},
+                    },
+                    "sequence": txin.sequence.0,
+                }),
+                None => json!({
+                    "coinbase": txin.script_sig.to_hex_string(),
+                    "sequence": txin.sequence.0,
+                }),
+            })
+            .collect();
+
+        json!({"txid": tx.compute_txid(), "hex": serialize_hex(tx), "vin": vin})
+    }
+
+    /// The response of `getblock` with verbosity 3.
+    fn verbose_block(
+        &self,
+        height: u32,
+        block: &Block,
+        prevouts: &HashMap<OutPoint, TxOut>,
+    ) -> Value {
+        let txs: Vec<Value> = block
+            .txdata
+            .iter()
+            .map(|tx| Self::verbose_tx(height - 1, tx, prevouts))
+            .collect();
+
+        json!({
+            "hash": block.block_hash(),
+            "height": height,
+            "version": block.header.version.to_consensus(),
+            "previousblockhash": block.header.prev_blockhash,
+            "merkleroot": block.header.merkle_root,
+            "time": block.header.time,
+            "bits": format!("{:08x}", block.header.bits.to_consensus()),
+            "nonce": block.header.nonce,
+            "tx": txs,
+        })
+    }
+
+    /// Answer an RPC call like bitcoind would for this chain.
+    pub fn handle(&self, method: &str, params: &[Value]) -> RpcResult {
+        match method {
+            "getblockcount" => Ok(json!(START_HEIGHT as usize + self.blocks.len() - 1)),
+            "getblockhash" => {
+                let height = params[0].as_u64().unwrap();
+                match self.get(height) {
+                    Some((block, _)) => Ok(json!(block.block_hash())),
+                    None => Err((-8, "Block height out of range".to_owned())),
+                }
+            }
+            "getblock" => {
+                assert_eq!(params[1], json!(3));
+                let hash: BlockHash = serde_json::from_value(params[0].clone()).unwrap();
+                self.blocks
+                    .iter()
+                    .enumerate()
+                    .find(|(_, (block, _))| block.block_hash() == hash)
+                    .map(|(n, (block, prevouts))| {
+                        self.verbose_block(START_HEIGHT + n as u32, block, prevouts)
+                    })
+                    .ok_or((-5, "Block not found".to_owned()))
+            }
+            "getrawmempool" => Ok(json!(
+                self.mempool
+                    .iter()
+                    .map(|(tx, _)| tx.compute_txid())
+                    .collect::<Vec<_>>()
+            )),
+            "getrawtransaction" => {
+                assert_eq!(params[1], json!(2));
+                let txid: Txid = serde_json::from_value(params[0].clone()).unwrap();
+                let tip = START_HEIGHT + self.blocks.len() as u32 - 1;
+                self.mempool
+                    .iter()
+                    .find(|(tx, _)| tx.compute_txid() == txid)
+                    .map(|(tx, prevouts)| Self::verbose_tx(tip, tx, prevouts))
+                    .ok_or((-5, "No such mempool or blockchain transaction".to_owned()))
+            }
+            _ => Err((-32601, "Method not found".to_owned())),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The result of a call, or the code and message of an RPC error.
+pub type RpcResult = Result<Value, (i32, String)>;
+
+type Handler = Arc<dyn Fn(&str, &[Value]) -> RpcResult + Send + Sync>;
+
+/// The method and params of the received requests.
+type Requests = Arc<Mutex<Vec<(String, Vec<Value>)>>>;
+
+/// A minimal HTTP server that answers JSON-RPC requests like bitcoind.
+pub struct MockRpcServer {
+    pub url: String,
+    pub requests: Requests,
+}
+
+impl MockRpcServer {
+    /// Start the server, requests without the given `Authorization` header are rejected.
+    pub async fn start(
+        authorization: &str,
+        handler: impl Fn(&str, &[Value]) -> RpcResult + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let handler: Handler = Arc::new(handler);
+        let authorization = authorization.to_owned();
+        let received = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                let authorization = authorization.clone();
+                let received = received.clone();
+
+                tokio::spawn(async move {
+                    handle(stream, &authorization, handler, received).await;
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(method, _)| method.clone()).collect()
+    }
+}
+
+async fn handle(mut stream: TcpStream, authorization: &str, handler: Handler, received: Requests) {
+    // read the headers
+    let mut buf = vec![];
+    let header_end = loop {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            return;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let headers: HashMap<String, String> = head
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_owned()))
+        .collect();
+
+    // read the rest of the body
+    let content_length: usize = headers
+        .get("content-length")
+        .map(|x| x.parse().unwrap())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await.unwrap();
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    if headers.get("authorization").map(String::as_str) != Some(authorization) {
+        let response =
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        stream.write_all(response.as_bytes()).await.unwrap();
+        return;
+    }
+
+    let request: Value = serde_json::from_slice(&buf[header_end..]).unwrap();
+    let method = request["method"].as_str().unwrap().to_owned();
+    let params = request["params"].as_array().cloned().unwrap_or_default();
+
+    received
+        .lock()
+        .unwrap()
+        .push((method.clone(), params.clone()));
+
+    // like bitcoind, errors are returned with status 500
+    let (status, body) = match handler(&method, &params) {
+        Ok(result) => (
+            "200 OK",
+            json!({"result": result, "error": null, "id": request["id"]}),
+        ),
+        Err((code, message)) => (
+            "500 Internal Server Error",
+            json!({"result": null, "error": {"code": code, "message": message}, "id": request["id"]}),
+        ),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.unwrap();
+}
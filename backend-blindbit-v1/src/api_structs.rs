@@ -42,6 +42,8 @@ impl From<SpentIndexResponse> for SpentIndexData {
     fn from(value: SpentIndexResponse) -> Self {
         Self {
             data: value.data.into_iter().map(|x| x.hex).collect(),
+            // the spent index of blindbit only has the hashes
+            spending_txs: vec![],
         }
     }
 }
@@ -2,7 +2,7 @@ use std::{ops::RangeInclusive, pin::Pin};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use bitcoin::{Amount, absolute::Height};
+use bitcoin::{Amount, BlockHash, absolute::Height};
 use futures::{Stream, StreamExt, stream};
 
 use spdk_core::chain::{BlockData, ChainBackend, SpentIndexData, UtxoData};
@@ -79,4 +79,12 @@ impl ChainBackend for BlindbitBackend {
             .map(Into::into)
             .collect())
     }
+
+    async fn block_height(&self) -> Result<Height> {
+        self.client.block_height().await
+    }
+
+    async fn block_hash(&self, block_height: Height) -> Result<BlockHash> {
+        Ok(self.client.filter_new_utxos(block_height).await?.block_hash)
+    }
 }
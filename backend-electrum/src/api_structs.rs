@@ -0,0 +1,77 @@
+use bitcoin::{Txid, secp256k1::PublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const SP_SUBSCRIBE: &str = "blockchain.silentpayments.subscribe";
+pub const SP_UNSUBSCRIBE: &str = "blockchain.silentpayments.unsubscribe";
+
+#[derive(Debug, Serialize)]
+pub struct Request<'a> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: &'a [Value],
+}
+
+impl<'a> Request<'a> {
+    pub fn new(id: u64, method: &'a str, params: &'a [Value]) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            method,
+            params,
+        }
+    }
+}
+
+/// A message from the server, either a response to a request or a notification.
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub id: Option<u64>,
+    pub result: Option<Value>,
+    pub error: Option<ErrorResponse>,
+    pub method: Option<String>,
+    pub params: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub code: i64,
+    pub message: String,
+}
+
+/// The notification that is sent for a silent payments subscription.
+///
+/// The server sends these while it scans, until `progress` reaches 1.
+#[derive(Debug, Deserialize)]
+pub struct SilentPaymentsNotification {
+    pub subscription: SubscriptionInfo,
+    pub progress: f64,
+    #[serde(default)]
+    pub history: Vec<SilentPaymentsHistoryItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscriptionInfo {
+    pub address: String,
+    #[serde(default)]
+    pub labels: Vec<u32>,
+    pub start_height: Option<u32>,
+}
+
+/// A transaction that may contain outputs for the subscription, with the tweak that is needed to scan it.
+///
+/// Transactions in the mempool have a height of 0 or lower.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SilentPaymentsHistoryItem {
+    pub height: i64,
+    pub tx_hash: Txid,
+    pub tweak_key: PublicKey,
+}
+
+/// An entry of `blockchain.scripthash.get_history`.
+#[derive(Debug, Deserialize)]
+pub struct ScripthashHistoryItem {
+    pub height: i64,
+    pub tx_hash: Txid,
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+
+use anyhow::{Error, Result};
+use bitcoin::absolute::Height;
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{Script, Transaction, Txid};
+use log::warn;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+
+use crate::api_structs::{
+    Message, Request, SP_SUBSCRIBE, SP_UNSUBSCRIBE, ScripthashHistoryItem,
+    SilentPaymentsNotification,
+};
+
+/// A notification sent by the server, e.g. for a subscription.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub method: String,
+    pub params: Value,
+}
+
+type Pending = Arc<StdMutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>;
+
+/// A client for the Electrum protocol, over TCP or TLS.
+///
+/// Requests can be made concurrently, the responses are matched by their id.
+/// Notifications are queued until they are read with [next_notification](Self::next_notification).
+#[derive(Clone)]
+pub struct ElectrumClient {
+    writer: Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>,
+    pending: Pending,
+    notifications: Arc<Mutex<mpsc::UnboundedReceiver<Notification>>>,
+    next_id: Arc<AtomicU64>,
+    closed: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for ElectrumClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ElectrumClient").finish_non_exhaustive()
+    }
+}
+
+impl ElectrumClient {
+    /// Connect over plain TCP, e.g. `127.0.0.1:50001`.
+    ///
+    /// [sp_subscribe](Self::sp_subscribe) sends the scan secret key, which anyone on the network can read
+    /// without TLS. Only use this for testing, or for a server on the same machine.
+    pub async fn connect_tcp(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Connect over TLS, the certificate of the server is checked against the webpki root certificates.
+    pub async fn connect_tls(addr: &str, server_name: &str) -> Result<Self> {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        Self::connect_tls_with_roots(addr, server_name, roots).await
+    }
+
+    /// Connect over TLS, the certificate of the server is checked against the given root certificates.
+    ///
+    /// This can be used for servers with a self-signed certificate.
+    pub async fn connect_tls_with_roots(
+        addr: &str,
+        server_name: &str,
+        roots: RootCertStore,
+    ) -> Result<Self> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let server_name = ServerName::try_from(server_name.to_owned())?;
+        let stream = TcpStream::connect(addr).await?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await?;
+
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Use an existing connection to the server.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let pending: Pending = Arc::new(StdMutex::new(HashMap::new()));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        tokio::spawn(Self::read_messages(
+            reader,
+            pending.clone(),
+            sender,
+            closed.clone(),
+        ));
+
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            pending,
+            notifications: Arc::new(Mutex::new(receiver)),
+            next_id: Arc::new(AtomicU64::new(0)),
+            closed,
+        }
+    }
+
+    /// Dispatch the messages from the server until the connection is closed.
+    async fn read_messages<R: AsyncRead + Unpin>(
+        reader: R,
+        pending: Pending,
+        notifications: mpsc::UnboundedSender<Notification>,
+        closed: Arc<AtomicBool>,
+    ) {
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let message: Message = match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Invalid message from electrum server: {}", e);
+                    continue;
+                }
+            };
+
+            if let Some(id) = message.id {
+                let res = match message.error {
+                    Some(err) => Err(Error::msg(format!(
+                        "Electrum error: {} ({})",
+                        err.message, err.code
+                    ))),
+                    None => Ok(message.result.unwrap_or(Value::Null)),
+                };
+
+                if let Some(sender) = pending.lock().unwrap().remove(&id) {
+                    let _ = sender.send(res);
+                }
+            } else if let Some(method) = message.method {
+                let params = message.params.unwrap_or(Value::Null);
+                let _ = notifications.send(Notification { method, params });
+            }
+        }
+
+        // new requests fail from now on, dropping the senders fails the ones that are waiting
+        closed.store(true, Ordering::SeqCst);
+        pending.lock().unwrap().clear();
+    }
+
+    pub async fn call(&self, method: &str, params: &[Value]) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+
+        if self.closed.load(Ordering::SeqCst) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(Error::msg("Connection to electrum server closed"));
+        }
+
+        let mut line = serde_json::to_vec(&Request::new(id, method, params))?;
+        line.push(b'\n');
+
+        {
+            let mut writer = self.writer.lock().await;
+            if let Err(e) = writer.write_all(&line).await {
+                self.pending.lock().unwrap().remove(&id);
+                return Err(e.into());
+            }
+            writer.flush().await?;
+        }
+
+        receiver
+            .await
+            .map_err(|_| Error::msg("Connection to electrum server closed"))?
+    }
+
+    /// Wait for the next notification from the server.
+    pub async fn next_notification(&self) -> Result<Notification> {
+        self.notifications
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| Error::msg("Connection to electrum server closed"))
+    }
+
+    pub async fn transaction(&self, txid: Txid) -> Result<Transaction> {
+        let res = self
+            .call("blockchain.transaction.get", &[json!(txid)])
+            .await?;
+        let tx_hex = res
+            .as_str()
+            .ok_or_else(|| Error::msg("Expected a transaction hex"))?;
+        Ok(deserialize_hex(tx_hex)?)
+    }
+
+    pub async fn block_header(&self, block_height: Height) -> Result<Header> {
+        let res = self
+            .call(
+                "blockchain.block.header",
+                &[json!(block_height.to_consensus_u32())],
+            )
+            .await?;
+        let header_hex = res
+            .as_str()
+            .ok_or_else(|| Error::msg("Expected a block header hex"))?;
+        Ok(deserialize_hex(header_hex)?)
+    }
+
+    /// The confirmed and unconfirmed transactions that pay to or spend from a script.
+    pub async fn script_history(&self, script: &Script) -> Result<Vec<ScripthashHistoryItem>> {
+        // the electrum script hash is the reversed sha256 of the script
+        let mut scripthash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+        scripthash.reverse();
+
+        let res = self
+            .call(
+                "blockchain.scripthash.get_history",
+                &[json!(scripthash.to_lower_hex_string())],
+            )
+            .await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    pub async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let res = self
+            .call(
+                "blockchain.transaction.broadcast",
+                &[json!(serialize_hex(tx))],
+            )
+            .await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Subscribe to the silent payments of the given keys, starting at `start_height`.
+    ///
+    /// The server scans for the keys and the given labels, and sends a [SilentPaymentsNotification]
+    /// for the results, see [parse_sp_notification](Self::parse_sp_notification).
+    /// Returns the silent payment address of the subscription.
+    pub async fn sp_subscribe(
+        &self,
+        scan_sk: &SecretKey,
+        spend_pk: &PublicKey,
+        start_height: Height,
+        labels: &[u32],
+    ) -> Result<String> {
+        let res = self
+            .call(
+                SP_SUBSCRIBE,
+                &[
+                    json!(scan_sk.secret_bytes().to_lower_hex_string()),
+                    json!(spend_pk.to_string()),
+                    json!(start_height.to_consensus_u32()),
+                    json!(labels),
+                ],
+            )
+            .await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    pub async fn sp_unsubscribe(&self, scan_sk: &SecretKey, spend_pk: &PublicKey) -> Result<()> {
+        self.call(
+            SP_UNSUBSCRIBE,
+            &[
+                json!(scan_sk.secret_bytes().to_lower_hex_string()),
+                json!(spend_pk.to_string()),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Parse a silent payments notification, returns `None` for other notifications.
+    pub fn parse_sp_notification(
+        notification: &Notification,
+    ) -> Result<Option<SilentPaymentsNotification>> {
+        if notification.method != SP_SUBSCRIBE {
+            return Ok(None);
+        }
+
+        // the notification is sent as an object, or wrapped in an array
+        let params = match &notification.params {
+            Value::Array(params) => params
+                .first()
+                .ok_or_else(|| Error::msg("Empty silent payments notification"))?,
+            params => params,
+        };
+
+        Ok(Some(serde_json::from_value(params.clone())?))
+    }
+}
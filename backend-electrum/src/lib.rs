@@ -0,0 +1,11 @@
+//! A backend for Electrum servers that support the `blockchain.silentpayments.*` methods, such as Frigate.
+//!
+//! Unlike the other backends, the server does the scanning: the client hands over its scan secret key and spend public key,
+//! and the server replies with the transactions that may contain outputs for the client.
+//! This is a lot faster on low-power devices, but the server learns which outputs belong to the wallet.
+pub mod api_structs;
+mod client;
+mod scanner;
+
+pub use client::{ElectrumClient, Notification};
+pub use scanner::ElectrumScanner;
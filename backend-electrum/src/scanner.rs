@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bitcoin::{
+    OutPoint, ScriptBuf, Transaction, Txid, XOnlyPublicKey, absolute::Height, secp256k1::SecretKey,
+};
+use log::{info, warn};
+use silentpayments::{receiving::Receiver, utils::receiving::calculate_ecdh_shared_secret};
+
+use spdk_core::chain::SpendingTx;
+use spdk_core::updater::{DiscoveredOutput, Updater};
+
+use crate::{ElectrumClient, api_structs::SilentPaymentsHistoryItem};
+
+/// How long to wait for the next notification of a subscription, see [ElectrumScanner::set_notification_timeout].
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The outputs and inputs found in a block.
+#[derive(Default)]
+struct BlockResult {
+    outputs: HashMap<OutPoint, DiscoveredOutput>,
+    inputs: HashSet<OutPoint>,
+    spending_txs: HashMap<OutPoint, SpendingTx>,
+}
+
+/// Scans for silent payments using the `blockchain.silentpayments.subscribe` method of an Electrum server.
+///
+/// The server returns the transactions that may pay to us, and their tweaks.
+/// The transactions are fetched and scanned locally, so the server can't make us accept outputs that aren't ours.
+/// Spent outputs are found with the script history of the outputs that we own.
+pub struct ElectrumScanner {
+    client: ElectrumClient,
+    receiver: Receiver,
+    scan_sk: SecretKey,
+    updater: Box<dyn Updater + Sync + Send>,
+    owned_outpoints: HashSet<OutPoint>, // used to scan block inputs
+    txs: HashMap<Txid, Transaction>,    // fetched during a scan, cleared afterwards
+    notification_timeout: Duration,
+}
+
+impl ElectrumScanner {
+    pub fn new(
+        client: ElectrumClient,
+        receiver: Receiver,
+        scan_sk: SecretKey,
+        updater: Box<dyn Updater + Sync + Send>,
+        owned_outpoints: HashSet<OutPoint>,
+    ) -> Self {
+        Self {
+            client,
+            receiver,
+            scan_sk,
+            updater,
+            owned_outpoints,
+            txs: HashMap::new(),
+            notification_timeout: NOTIFICATION_TIMEOUT,
+        }
+    }
+
+    /// Set how long [scan](Self::scan) waits for the next notification from the server before it fails.
+    ///
+    /// The server sends a notification whenever its scan progresses, the default is 60 seconds.
+    pub fn set_notification_timeout(&mut self, timeout: Duration) {
+        self.notification_timeout = timeout;
+    }
+
+    /// Scan the blocks from `start` up to the tip of the server.
+    ///
+    /// The results are passed to the [Updater] for every block that has outputs or inputs for us, in order of height.
+    /// Transactions in the mempool are ignored.
+    pub async fn scan(&mut self, start: Height) -> Result<()> {
+        let res = self.scan_blocks(start).await;
+
+        // don't keep the transactions around between scans
+        self.txs.clear();
+
+        res
+    }
+
+    async fn scan_blocks(&mut self, start: Height) -> Result<()> {
+        let spend_pk = self.receiver.get_receiving_address().m_pubkey();
+        let labels: Vec<u32> = self
+            .receiver
+            .list_labels()
+            .iter()
+            .filter_map(|label| label.m())
+            .collect();
+
+        let address = self
+            .client
+            .sp_subscribe(&self.scan_sk, &spend_pk, start, &labels)
+            .await?;
+        info!("subscribed to {}", address);
+
+        let history = self.wait_for_history(&address).await;
+
+        // always unsubscribe, so the server stops scanning for these keys
+        if let Err(e) = self.client.sp_unsubscribe(&self.scan_sk, &spend_pk).await {
+            // the error of the history is the one that matters
+            if history.is_err() {
+                warn!("Failed to unsubscribe from {}: {}", address, e);
+            } else {
+                return Err(e);
+            }
+        }
+        let history = history?;
+
+        let start = start.to_consensus_u32();
+        let mut results: BTreeMap<u32, BlockResult> = BTreeMap::new();
+
+        for item in history {
+            let height = match u32::try_from(item.height) {
+                Ok(height) if height >= start => height,
+                _ => continue,
+            };
+
+            let outputs = self.scan_tx(&item).await?;
+            self.owned_outpoints.extend(outputs.keys());
+            results.entry(height).or_default().outputs.extend(outputs);
+        }
+
+        for (height, spent) in self.find_spent().await? {
+            if height >= start {
+                let result = results.entry(height).or_default();
+                result.inputs.extend(spent.keys());
+                result.spending_txs.extend(spent);
+            }
+        }
+
+        for (height, result) in results {
+            let blkheight = Height::from_consensus(height)?;
+            let blkhash = self.client.block_header(blkheight).await?.block_hash();
+
+            self.owned_outpoints
+                .retain(|outpoint| !result.inputs.contains(outpoint));
+
+            self.updater.record_block_scan_result(
+                blkheight,
+                blkhash,
+                result.inputs,
+                result.outputs,
+            )?;
+            if !result.spending_txs.is_empty() {
+                self.updater
+                    .record_spending_txs(blkheight, result.spending_txs)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collect the history of the subscription for `address`, until the server is done scanning.
+    ///
+    /// Notifications for other subscriptions on the same connection are ignored.
+    async fn wait_for_history(&self, address: &str) -> Result<Vec<SilentPaymentsHistoryItem>> {
+        let mut history: HashMap<Txid, SilentPaymentsHistoryItem> = HashMap::new();
+
+        loop {
+            let notification =
+                tokio::time::timeout(self.notification_timeout, self.client.next_notification())
+                    .await
+                    .map_err(|_| {
+                        Error::msg(format!(
+                            "Timed out waiting for the scan progress of {}",
+                            address
+                        ))
+                    })??;
+
+            let Some(notification) = ElectrumClient::parse_sp_notification(&notification)? else {
+                continue;
+            };
+            if notification.subscription.address != address {
+                continue;
+            }
+
+            for item in notification.history {
+                history.insert(item.tx_hash, item);
+            }
+
+            if notification.progress >= 1.0 {
+                break;
+            }
+        }
+
+        let mut history: Vec<_> = history.into_values().collect();
+        history.sort_by_key(|item| item.height);
+        Ok(history)
+    }
+
+    async fn get_tx(&mut self, txid: Txid) -> Result<&Transaction> {
+        if !self.txs.contains_key(&txid) {
+            let tx = self.client.transaction(txid).await?;
+            if tx.compute_txid() != txid {
+                return Err(Error::msg(format!(
+                    "Server returned the wrong transaction for {}",
+                    txid
+                )));
+            }
+            self.txs.insert(txid, tx);
+        }
+
+        Ok(&self.txs[&txid])
+    }
+
+    async fn scan_tx(
+        &mut self,
+        item: &SilentPaymentsHistoryItem,
+    ) -> Result<HashMap<OutPoint, DiscoveredOutput>> {
+        let secret = calculate_ecdh_shared_secret(&item.tweak_key, &self.scan_sk);
+        let tx = self.get_tx(item.tx_hash).await?.clone();
+
+        // outputs whose key isn't valid are skipped, as in BIP352
+        let taproot_outputs: Vec<(u32, XOnlyPublicKey)> = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.script_pubkey.is_p2tr())
+            .filter_map(|(vout, output)| {
+                XOnlyPublicKey::from_slice(&output.script_pubkey.as_bytes()[2..])
+                    .ok()
+                    .map(|key| (vout as u32, key))
+            })
+            .collect();
+
+        let output_keys: Vec<XOnlyPublicKey> =
+            taproot_outputs.iter().map(|(_, key)| *key).collect();
+        let found = self.receiver.scan_transaction(&secret, &output_keys)?;
+
+        let mut res = HashMap::new();
+        for (vout, key) in taproot_outputs {
+            for (label, map) in found.iter() {
+                if let Some(tweak) = map.get(&key) {
+                    let output = &tx.output[vout as usize];
+                    res.insert(
+                        OutPoint::new(item.tx_hash, vout),
+                        DiscoveredOutput {
+                            tweak: *tweak,
+                            value: output.value,
+                            script_pubkey: output.script_pubkey.clone(),
+                            label: label.clone(),
+                        },
+                    );
+                    break;
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Find the owned outpoints that are spent in a block, and the inputs that spend them,
+    /// using the history of their scripts.
+    async fn find_spent(&mut self) -> Result<BTreeMap<u32, HashMap<OutPoint, SpendingTx>>> {
+        let mut scripts: HashMap<ScriptBuf, Vec<OutPoint>> = HashMap::new();
+        for outpoint in self.owned_outpoints.clone() {
+            let tx = self.get_tx(outpoint.txid).await?;
+            let output = tx
+                .output
+                .get(outpoint.vout as usize)
+                .ok_or_else(|| Error::msg(format!("Unknown output {}", outpoint)))?;
+            scripts
+                .entry(output.script_pubkey.clone())
+                .or_default()
+                .push(outpoint);
+        }
+
+        let mut res: BTreeMap<u32, HashMap<OutPoint, SpendingTx>> = BTreeMap::new();
+        for (script, outpoints) in scripts {
+            for item in self.client.script_history(&script).await? {
+                let height = match u32::try_from(item.height) {
+                    Ok(height) if height > 0 => height,
+                    _ => continue,
+                };
+
+                let tx = self.get_tx(item.tx_hash).await?;
+                for (vin, txin) in tx.input.iter().enumerate() {
+                    if outpoints.contains(&txin.previous_output) {
+                        res.entry(height).or_default().insert(
+                            txin.previous_output,
+                            SpendingTx {
+                                txid: item.tx_hash,
+                                vin: vin as u32,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use backend_electrum::api_structs::{SP_SUBSCRIBE, SP_UNSUBSCRIBE};
+use backend_electrum::{ElectrumClient, ElectrumScanner};
+use bitcoin::absolute::Height;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Amount, Txid};
+use serde_json::json;
+use spdk_core::chain::SpendingTx;
+use tokio::net::TcpListener;
+
+use crate::mock::chain::{MockChain, START_HEIGHT, address, receiver, scan_sk};
+use crate::mock::server::{MockElectrumServer, Reply, test_roots};
+use crate::mock::updater::MockUpdater;
+
+mod mock;
+
+fn height(n: u32) -> Height {
+    Height::from_consensus(n).unwrap()
+}
+
+async fn start_server(chain: Arc<MockChain>, tls: bool) -> MockElectrumServer {
+    MockElectrumServer::start(tls, move |method, params| chain.handle(method, params)).await
+}
+
+#[tokio::test]
+async fn scan_finds_payment_and_spend() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone(), false).await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        scan_sk(),
+        Box::new(updater.clone()),
+        HashSet::new(),
+    );
+    scanner.scan(height(START_HEIGHT)).await.unwrap();
+
+    let updates = updater.updates.lock().unwrap();
+    assert_eq!(updates.len(), 2);
+
+    // the payment, the unconfirmed and the false positive transactions are ignored
+    let found = &updates[0];
+    assert_eq!(found.blkheight, height(START_HEIGHT + 1));
+    assert_eq!(found.blkhash, chain.block_hash(START_HEIGHT + 1));
+    assert!(found.discovered_inputs.is_empty());
+    assert_eq!(found.discovered_outputs.len(), 1);
+    let output = &found.discovered_outputs[&chain.sp_outpoint];
+    assert_eq!(output.value, Amount::from_sat(50_000));
+    assert_eq!(output.script_pubkey, chain.payment.output[0].script_pubkey);
+    assert_eq!(output.label, None);
+
+    let spent = &updates[1];
+    assert_eq!(spent.blkheight, height(START_HEIGHT + 2));
+    assert_eq!(spent.blkhash, chain.block_hash(START_HEIGHT + 2));
+    assert_eq!(spent.discovered_inputs, HashSet::from([chain.sp_outpoint]));
+    assert!(spent.discovered_outputs.is_empty());
+    assert_eq!(
+        *updater.spending_txs.lock().unwrap(),
+        HashMap::from([(
+            chain.sp_outpoint,
+            SpendingTx {
+                txid: chain.sweep.compute_txid(),
+                vin: 0,
+            }
+        )])
+    );
+
+    assert!(
+        server
+            .methods()
+            .iter()
+            .any(|method| method == SP_UNSUBSCRIBE)
+    );
+}
+
+#[tokio::test]
+async fn scan_from_height_uses_owned_outpoints() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone(), false).await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        scan_sk(),
+        Box::new(updater.clone()),
+        HashSet::from([chain.sp_outpoint]),
+    );
+    scanner.scan(height(START_HEIGHT + 2)).await.unwrap();
+
+    // the payment was found in an earlier scan
+    let updates = updater.updates.lock().unwrap();
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].blkheight, height(START_HEIGHT + 2));
+    assert_eq!(
+        updates[0].discovered_inputs,
+        HashSet::from([chain.sp_outpoint])
+    );
+}
+
+#[tokio::test]
+async fn notifications_for_other_subscriptions_are_ignored() {
+    let chain = Arc::new(MockChain::new());
+    let handler_chain = chain.clone();
+    let server = MockElectrumServer::start(false, move |method, params| {
+        let mut reply = handler_chain.handle(method, params);
+        if method == SP_SUBSCRIBE {
+            // another subscription on the same connection finishes first
+            let other = json!({
+                "subscription": {"address": "sp1other", "labels": []},
+                "progress": 1.0,
+                "history": [],
+            });
+            reply
+                .notifications
+                .insert(0, (SP_SUBSCRIBE.to_owned(), other));
+        }
+        reply
+    })
+    .await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        scan_sk(),
+        Box::new(updater.clone()),
+        HashSet::new(),
+    );
+    scanner.scan(height(START_HEIGHT)).await.unwrap();
+
+    assert_eq!(updater.updates.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn scan_times_out_without_notifications() {
+    let chain = Arc::new(MockChain::new());
+    let handler_chain = chain.clone();
+    let server = MockElectrumServer::start(false, move |method, params| match method {
+        SP_SUBSCRIBE => Reply::new(Ok(json!(address()))),
+        method => handler_chain.handle(method, params),
+    })
+    .await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        scan_sk(),
+        Box::new(updater.clone()),
+        HashSet::new(),
+    );
+    scanner.set_notification_timeout(Duration::from_millis(100));
+
+    let err = scanner.scan(height(START_HEIGHT)).await.unwrap_err();
+    assert!(err.to_string().starts_with("Timed out"));
+    assert!(updater.updates.lock().unwrap().is_empty());
+
+    assert!(
+        server
+            .methods()
+            .iter()
+            .any(|method| method == SP_UNSUBSCRIBE)
+    );
+}
+
+#[tokio::test]
+async fn unsubscribe_error_keeps_the_scan_error() {
+    let chain = Arc::new(MockChain::new());
+    let handler_chain = chain.clone();
+    let server = MockElectrumServer::start(false, move |method, params| match method {
+        SP_SUBSCRIBE => Reply::new(Ok(json!(address()))),
+        SP_UNSUBSCRIBE => Reply::error(-32600, "Unsubscribe failed"),
+        method => handler_chain.handle(method, params),
+    })
+    .await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        scan_sk(),
+        Box::new(updater.clone()),
+        HashSet::new(),
+    );
+    scanner.set_notification_timeout(Duration::from_millis(100));
+
+    let err = scanner.scan(height(START_HEIGHT)).await.unwrap_err();
+    assert!(err.to_string().starts_with("Timed out"));
+}
+
+#[tokio::test]
+async fn queries_over_tls() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone(), true).await;
+    let client = ElectrumClient::connect_tls_with_roots(&server.addr, "localhost", test_roots())
+        .await
+        .unwrap();
+
+    let header = client.block_header(height(START_HEIGHT + 1)).await.unwrap();
+    assert_eq!(header.block_hash(), chain.block_hash(START_HEIGHT + 1));
+
+    let tx = client
+        .transaction(chain.payment.compute_txid())
+        .await
+        .unwrap();
+    assert_eq!(tx, chain.payment);
+
+    let txid = client.broadcast(&chain.sweep).await.unwrap();
+    assert_eq!(txid, chain.sweep.compute_txid());
+
+    // the certificate is not trusted by default
+    assert!(
+        ElectrumClient::connect_tls(&server.addr, "localhost")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn electrum_errors_are_returned() {
+    let chain = Arc::new(MockChain::new());
+    let server = start_server(chain.clone(), false).await;
+    let client = ElectrumClient::connect_tcp(&server.addr).await.unwrap();
+
+    let err = client
+        .transaction(Txid::all_zeros())
+        .await
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "Electrum error: No such mempool or blockchain transaction (2)"
+    );
+
+    let err = client
+        .block_header(height(START_HEIGHT + 3))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("out of range"));
+
+    // the server only knows the keys of the receiver
+    let other_sk = SecretKey::from_slice(&[0x04; 32]).unwrap();
+    let updater = MockUpdater::default();
+    let mut scanner = ElectrumScanner::new(
+        client,
+        receiver(),
+        other_sk,
+        Box::new(updater.clone()),
+        HashSet::new(),
+    );
+    let err = scanner.scan(height(START_HEIGHT)).await.unwrap_err();
+    assert_eq!(err.to_string(), "Electrum error: Invalid scan key (-32602)");
+    assert!(updater.updates.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn closed_connection() {
+    // a server that closes every connection right away
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            drop(stream);
+        }
+    });
+
+    let client = ElectrumClient::connect_tcp(&addr).await.unwrap();
+
+    // returns once the connection is closed
+    let err = client.next_notification().await.unwrap_err();
+    assert_eq!(err.to_string(), "Connection to electrum server closed");
+
+    let err = client.block_header(height(START_HEIGHT)).await.unwrap_err();
+    assert_eq!(err.to_string(), "Connection to electrum server closed");
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use backend_electrum::api_structs::{SP_SUBSCRIBE, SP_UNSUBSCRIBE};
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header, Version};
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::DisplayHex;
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{
+    Amount, BlockHash, CompactTarget, OutPoint, ScriptBuf, Transaction, TxIn, TxMerkleNode, TxOut,
+    Txid, WPubkeyHash, Witness, transaction,
+};
+use serde_json::{Value, json};
+use silentpayments::receiving::{Label, Receiver};
+use silentpayments::sending::generate_recipient_pubkeys;
+use silentpayments::utils::receiving::tweak_data_for_tx;
+use silentpayments::utils::sending::calculate_partial_secret;
+use silentpayments::{Network as SpNetwork, SilentPaymentAddressDisplay, SpVersion};
+
+use super::server::{Reply, RpcResult};
+
+pub const START_HEIGHT: u32 = 100;
+
+pub fn scan_sk() -> SecretKey {
+    SecretKey::from_slice(&[0x01; 32]).unwrap()
+}
+
+pub fn receiver() -> Receiver {
+    let secp = Secp256k1::new();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    Receiver::new(
+        SpVersion::ZERO,
+        scan_sk().public_key(&secp),
+        spend_sk.public_key(&secp),
+        Label::new(scan_sk(), 0),
+        SpNetwork::Regtest,
+    )
+    .unwrap()
+}
+
+/// The address of the receiver, as the server shows it.
+pub fn address() -> String {
+    let address = receiver().get_receiving_address();
+    SilentPaymentAddressDisplay::new(
+        address.scan_key(),
+        address.m_pubkey(),
+        SpNetwork::Regtest,
+        SpVersion::ZERO,
+    )
+    .to_string()
+}
+
+fn new_tx(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    }
+}
+
+fn p2wpkh(pubkey: &PublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(&pubkey.serialize()))
+}
+
+fn scripthash(script: &ScriptBuf) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+    hash.reverse();
+    hash.to_lower_hex_string()
+}
+
+/// A small regtest chain of 3 blocks, the signatures are not valid:
+///
+/// * block 100: a coinbase paying to the sender.
+/// * block 101: the sender pays a silent payment to the receiver, along with an output with an invalid taproot key.
+/// * block 102: the silent payment output is spent.
+///
+/// Only the headers and the transactions are kept, this is all an electrum server serves.
+pub struct MockChain {
+    pub headers: Vec<Header>,
+    pub txs: HashMap<Txid, (u32, Transaction)>,
+    pub payment: Transaction,
+    pub payment_tweak: PublicKey,
+    pub sweep: Transaction,
+    pub sp_outpoint: OutPoint,
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        let secp = Secp256k1::new();
+        let sender_sk = SecretKey::from_slice(&[0x03; 32]).unwrap();
+        let sender_pk = sender_sk.public_key(&secp);
+
+        let cb = new_tx(
+            vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder()
+                    .push_int(START_HEIGHT as i64)
+                    .into_script(),
+                ..Default::default()
+            }],
+            vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: p2wpkh(&sender_pk),
+            }],
+        );
+
+        let funding = OutPoint::new(cb.compute_txid(), 0);
+        let partial_secret =
+            calculate_partial_secret(&[(sender_sk, false)], &[funding.into()]).unwrap();
+        let address = receiver().get_receiving_address();
+        let output_keys = generate_recipient_pubkeys(vec![address], partial_secret).unwrap();
+        let output_key = TweakedPublicKey::dangerous_assume_tweaked(output_keys[&address][0]);
+
+        let payment = new_tx(
+            vec![TxIn {
+                previous_output: funding,
+                witness: Witness::from_slice(&[vec![0x30; 71], sender_pk.serialize().to_vec()]),
+                ..Default::default()
+            }],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(output_key),
+                },
+                TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: p2wpkh(&sender_pk),
+                },
+                // a taproot output whose key isn't on the curve
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::from_bytes([&[0x51, 0x20][..], &[0; 32]].concat()),
+                },
+            ],
+        );
+        let payment_tweak = tweak_data_for_tx(&payment, &[cb.output[0].clone()])
+            .unwrap()
+            .unwrap();
+
+        let sp_outpoint = OutPoint::new(payment.compute_txid(), 0);
+        let sweep = new_tx(
+            vec![TxIn {
+                previous_output: sp_outpoint,
+                witness: Witness::from_slice(&[vec![0x01; 64]]),
+                ..Default::default()
+            }],
+            vec![TxOut {
+                value: Amount::from_sat(49_000),
+                script_pubkey: p2wpkh(&sender_pk),
+            }],
+        );
+
+        let mut headers: Vec<Header> = vec![];
+        for n in 0..3 {
+            headers.push(Header {
+                version: Version::TWO,
+                prev_blockhash: headers
+                    .last()
+                    .map(|header| header.block_hash())
+                    .unwrap_or(BlockHash::all_zeros()),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 1_700_000_000 + n,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            });
+        }
+
+        let txs = [(0, cb), (1, payment.clone()), (2, sweep.clone())]
+            .into_iter()
+            .map(|(n, tx)| (tx.compute_txid(), (START_HEIGHT + n, tx)))
+            .collect();
+
+        Self {
+            headers,
+            txs,
+            payment,
+            payment_tweak,
+            sweep,
+            sp_outpoint,
+        }
+    }
+
+    pub fn block_hash(&self, height: u32) -> BlockHash {
+        self.headers[(height - START_HEIGHT) as usize].block_hash()
+    }
+
+    /// The history that is sent for a silent payments subscription.
+    ///
+    /// Besides the payment, the server also sends a transaction that doesn't pay to us,
+    /// and the payment again while it was in the mempool.
+    fn sp_history(&self) -> (Value, Value) {
+        let payment = json!({
+            "height": START_HEIGHT + 1,
+            "tx_hash": self.payment.compute_txid(),
+            "tweak_key": self.payment_tweak.to_string(),
+        });
+        let unconfirmed = json!({
+            "height": 0,
+            "tx_hash": self.payment.compute_txid(),
+            "tweak_key": self.payment_tweak.to_string(),
+        });
+        let false_positive = json!({
+            "height": START_HEIGHT + 2,
+            "tx_hash": self.sweep.compute_txid(),
+            "tweak_key": self.payment_tweak.to_string(),
+        });
+
+        (json!([unconfirmed]), json!([payment, false_positive]))
+    }
+
+    fn notification(&self, start_height: &Value, progress: f64, history: Value) -> (String, Value) {
+        let params = json!({
+            "subscription": {
+                "address": address(),
+                "labels": [],
+                "start_height": start_height,
+            },
+            "progress": progress,
+            "history": history,
+        });
+        (SP_SUBSCRIBE.to_owned(), params)
+    }
+
+    /// Answer a request like an electrum server with silent payments support would for this chain.
+    pub fn handle(&self, method: &str, params: &[Value]) -> Reply {
+        match method {
+            SP_SUBSCRIBE => {
+                if params[0] != json!(scan_sk().secret_bytes().to_lower_hex_string()) {
+                    return Reply::error(-32602, "Invalid scan key");
+                }
+
+                let (first, last) = self.sp_history();
+
+                Reply::new(Ok(json!(address()))).with_notifications(vec![
+                    self.notification(&params[2], 0.5, first),
+                    self.notification(&params[2], 1.0, last),
+                ])
+            }
+            SP_UNSUBSCRIBE => Reply::new(Ok(json!(true))),
+            method => Reply::new(self.query(method, params)),
+        }
+    }
+
+    fn query(&self, method: &str, params: &[Value]) -> RpcResult {
+        match method {
+            "blockchain.block.header" => {
+                let height = params[0].as_u64().unwrap() as u32;
+                height
+                    .checked_sub(START_HEIGHT)
+                    .and_then(|n| self.headers.get(n as usize))
+                    .map(|header| json!(serialize_hex(header)))
+                    .ok_or((1, format!("height {} out of range", height)))
+            }
+            "blockchain.transaction.get" => {
+                let txid: Txid = serde_json::from_value(params[0].clone()).unwrap();
+                self.txs
+                    .get(&txid)
+                    .map(|(_, tx)| json!(serialize_hex(tx)))
+                    .ok_or((2, "No such mempool or blockchain transaction".to_owned()))
+            }
+            "blockchain.scripthash.get_history" => {
+                let scripthash = params[0].as_str().unwrap();
+                let mut history: Vec<(u32, Txid)> = vec![];
+                for (txid, (height, tx)) in self.txs.iter() {
+                    let pays = tx
+                        .output
+                        .iter()
+                        .any(|output| self::scripthash(&output.script_pubkey) == scripthash);
+                    let spends = tx.input.iter().any(|txin| {
+                        self.txs
+                            .get(&txin.previous_output.txid)
+                            .and_then(|(_, prev)| {
+                                prev.output.get(txin.previous_output.vout as usize)
+                            })
+                            .is_some_and(|prevout| {
+                                self::scripthash(&prevout.script_pubkey) == scripthash
+                            })
+                    });
+                    if pays || spends {
+                        history.push((*height, *txid));
+                    }
+                }
+                history.sort();
+                Ok(json!(
+                    history
+                        .into_iter()
+                        .map(|(height, txid)| json!({"height": height, "tx_hash": txid}))
+                        .collect::<Vec<_>>()
+                ))
+            }
+            "blockchain.transaction.broadcast" => {
+                let tx: Transaction =
+                    deserialize_hex(params[0].as_str().unwrap()).map_err(|e| (1, e.to_string()))?;
+                Ok(json!(tx.compute_txid()))
+            }
+            _ => Err((-32601, "Method not found".to_owned())),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, RootCertStore, ServerConfig};
+
+/// The result of a call, or the code and message of an electrum error.
+pub type RpcResult = Result<Value, (i64, String)>;
+
+/// The response to a request, and the notifications that are sent after it.
+pub struct Reply {
+    pub result: RpcResult,
+    pub notifications: Vec<(String, Value)>,
+}
+
+impl Reply {
+    pub fn new(result: RpcResult) -> Self {
+        Self {
+            result,
+            notifications: vec![],
+        }
+    }
+
+    pub fn error(code: i64, message: &str) -> Self {
+        Self::new(Err((code, message.to_owned())))
+    }
+
+    pub fn with_notifications(mut self, notifications: Vec<(String, Value)>) -> Self {
+        self.notifications = notifications;
+        self
+    }
+}
+
+type Handler = Arc<dyn Fn(&str, &[Value]) -> Reply + Send + Sync>;
+
+/// The method and params of the received requests.
+type Requests = Arc<Mutex<Vec<(String, Vec<Value>)>>>;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/resources/cert.pem");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/resources/key.pem");
+
+/// The root certificates to connect to the server over TLS, only has the self-signed certificate of the server.
+pub fn test_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(CERT).unwrap())
+        .unwrap();
+    roots
+}
+
+fn tls_acceptor() -> TlsAcceptor {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from_pem_file(CERT).unwrap()],
+            PrivateKeyDer::from_pem_file(KEY).unwrap(),
+        )
+        .unwrap();
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// A minimal electrum server, that answers newline delimited JSON-RPC requests.
+pub struct MockElectrumServer {
+    pub addr: String,
+    pub requests: Requests,
+}
+
+impl MockElectrumServer {
+    /// Start the server, over TLS with the certificate in `tests/resources` if `tls` is set.
+    pub async fn start(
+        tls: bool,
+        handler: impl Fn(&str, &[Value]) -> Reply + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let handler: Handler = Arc::new(handler);
+        let acceptor = tls.then(tls_acceptor);
+        let received = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                let received = received.clone();
+
+                match acceptor.clone() {
+                    Some(acceptor) => {
+                        tokio::spawn(async move {
+                            let stream = acceptor.accept(stream).await.unwrap();
+                            handle(stream, handler, received).await;
+                        });
+                    }
+                    None => {
+                        tokio::spawn(handle(stream, handler, received));
+                    }
+                }
+            }
+        });
+
+        Self { addr, requests }
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(method, _)| method.clone()).collect()
+    }
+}
+
+async fn handle<S>(stream: S, handler: Handler, received: Requests)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let request: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(request["jsonrpc"], "2.0");
+        let method = request["method"].as_str().unwrap().to_owned();
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+
+        received
+            .lock()
+            .unwrap()
+            .push((method.clone(), params.clone()));
+
+        let reply = handler(&method, &params);
+        let mut messages = vec![match reply.result {
+            Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": request["id"]}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "error": {"code": code, "message": message},
+                "id": request["id"],
+            }),
+        }];
+        messages.extend(reply.notifications.into_iter().map(
+            |(method, params)| json!({"jsonrpc": "2.0", "method": method, "params": [params]}),
+        ));
+
+        for message in messages {
+            let mut line = message.to_string();
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+        writer.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+//! Discrete Log Equality proofs, as defined in [BIP374](https://github.com/bitcoin/bips/blob/master/bip-0374.mediawiki).
+//!
+//! A DLEQ proof shows that `A = a·G` and `C = a·B` share the same secret `a`, without revealing it.
+//! For silent payments, this lets a signer prove that an ECDH share `a·B_scan` was computed
+//! correctly, so that an untrusted coordinator can check the share before computing the outputs.
+//!
+//! To create a proof for an ECDH share, use [`calculate_ecdh_share_proof`](crate::utils::sending::calculate_ecdh_share_proof).
+//! Such a proof can be checked with [`verify_dleq_proof`], where `A` is the sum of the input public keys.
+use secp256k1::constants::{CURVE_ORDER, GENERATOR_X};
+use secp256k1::ecdh::shared_secret_point;
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+
+use bitcoin_hashes::Hash;
+
+use crate::prelude::*;
+use crate::utils::hash::{DleqAuxHash, DleqChallengeHash, DleqNonceHash};
+use crate::{Error, Result};
+
+/// A BIP374 DLEQ proof, consisting of the challenge `e` and the response `s`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct DleqProof([u8; 64]);
+
+impl DleqProof {
+    pub fn from_bytes(bytes: [u8; 64]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parse a proof from a 64-byte slice.
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let bytes: [u8; 64] = data
+            .try_into()
+            .map_err(|_| Error::InvalidDleqProof("Proof must be 64 bytes long".to_owned()))?;
+        Ok(Self(bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.0
+    }
+
+    fn e(&self) -> [u8; 32] {
+        self.0[..32].try_into().expect("proof is 64 bytes")
+    }
+
+    fn s(&self) -> [u8; 32] {
+        self.0[32..].try_into().expect("proof is 64 bytes")
+    }
+}
+
+/// The secp256k1 generator point `G`.
+fn generator() -> PublicKey {
+    let mut bytes = [0u8; 33];
+    bytes[0] = 0x02;
+    bytes[1..].copy_from_slice(&GENERATOR_X);
+    PublicKey::from_slice(&bytes).expect("generator is a valid point")
+}
+
+/// Multiply a point with a secret scalar in constant time.
+fn mul_secret(point: &PublicKey, k: &SecretKey) -> PublicKey {
+    let mut bytes = [0u8; 65];
+    bytes[0] = 0x04;
+    bytes[1..].copy_from_slice(&shared_secret_point(point, k));
+
+    PublicKey::from_slice(&bytes).expect("guaranteed to be a point on the curve")
+}
+
+/// Multiply a point with a public scalar, where [None] is the point at infinity.
+fn mul_public(point: &PublicKey, k: &Scalar) -> Option<PublicKey> {
+    let secp = Secp256k1::verification_only();
+    point.mul_tweak(&secp, k).ok()
+}
+
+/// Add two points, where [None] is the point at infinity.
+fn add(p: Option<PublicKey>, q: Option<PublicKey>) -> Option<PublicKey> {
+    match (p, q) {
+        (Some(p), Some(q)) => p.combine(&q).ok(),
+        (p, None) => p,
+        (None, q) => q,
+    }
+}
+
+/// Reduce a 32-byte big-endian integer modulo the curve order.
+fn reduce_mod_order(bytes: [u8; 32]) -> [u8; 32] {
+    if bytes < CURVE_ORDER {
+        return bytes;
+    }
+
+    // since 2^256 < 2n, a single subtraction is enough
+    let mut res = [0u8; 32];
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let mut diff = bytes[i] as i16 - CURVE_ORDER[i] as i16 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 256;
+            borrow = 1;
+        }
+        res[i] = diff as u8;
+    }
+    res
+}
+
+/// Generate a DLEQ proof that `A = a·G` and `C = a·B` have the same discrete logarithm,
+/// using the secp256k1 generator as `G`.
+///
+/// # Arguments
+///
+/// * `a` - The secret scalar.
+/// * `B` - The public key that `a` is multiplied with, e.g. the recipient scan key.
+/// * `r` - 32 bytes of fresh auxiliary randomness.
+/// * `m` - An optional 32-byte message to commit to.
+///
+/// # Errors
+///
+/// This function will error if:
+///
+/// * The proof nonce is zero, or the generated proof doesn't verify (both extremely unlikely).
+pub fn generate_dleq_proof(
+    a: &SecretKey,
+    B: &PublicKey,
+    r: &[u8; 32],
+    m: Option<&[u8; 32]>,
+) -> Result<DleqProof> {
+    generate_dleq_proof_with_generator(a, B, r, &generator(), m)
+}
+
+/// Generate a DLEQ proof that `A = a·G` and `C = a·B` have the same discrete logarithm,
+/// for an arbitrary generator point `G`.
+///
+/// See [`generate_dleq_proof`] for the arguments and errors.
+pub fn generate_dleq_proof_with_generator(
+    a: &SecretKey,
+    B: &PublicKey,
+    r: &[u8; 32],
+    G: &PublicKey,
+    m: Option<&[u8; 32]>,
+) -> Result<DleqProof> {
+    let A = mul_secret(G, a);
+    let C = mul_secret(B, a);
+
+    // t = a xor hash_BIP0374/aux(r)
+    let mut t = a.secret_bytes();
+    for (t_i, aux_i) in t
+        .iter_mut()
+        .zip(DleqAuxHash::from_aux_rand(r).to_byte_array())
+    {
+        *t_i ^= aux_i;
+    }
+
+    let rand = DleqNonceHash::from_t_A_C_and_m(&t, &A, &C, m).to_byte_array();
+    let k = SecretKey::from_slice(&reduce_mod_order(rand))
+        .map_err(|_| Error::InvalidDleqProof("Proof nonce is zero".to_owned()))?;
+
+    let R1 = mul_secret(G, &k);
+    let R2 = mul_secret(B, &k);
+
+    let e_bytes = DleqChallengeHash::from_points_and_m(&A, B, &C, G, &R1, &R2, m).to_byte_array();
+    let e = Scalar::from_be_bytes(e_bytes)?;
+
+    // s = k + e·a
+    let s = k.add_tweak(&a.mul_tweak(&e)?.into())?;
+
+    let mut proof = [0u8; 64];
+    proof[..32].copy_from_slice(&e_bytes);
+    proof[32..].copy_from_slice(&s.secret_bytes());
+    let proof = DleqProof(proof);
+
+    if !verify_dleq_proof_with_generator(&A, B, &C, &proof, G, m) {
+        return Err(Error::InvalidDleqProof(
+            "Generated proof does not verify".to_owned(),
+        ));
+    }
+
+    Ok(proof)
+}
+
+/// Verify a DLEQ proof that `A = a·G` and `C = a·B` have the same discrete logarithm,
+/// using the secp256k1 generator as `G`.
+///
+/// # Arguments
+///
+/// * `A` - The public key `a·G`, e.g. the sum of the input public keys.
+/// * `B` - The public key that `a` was multiplied with, e.g. the recipient scan key.
+/// * `C` - The claimed result of `a·B`, e.g. the ECDH share.
+/// * `proof` - The proof to verify.
+/// * `m` - The optional 32-byte message that the proof commits to.
+///
+/// # Returns
+///
+/// Returns [true] if the proof is valid.
+pub fn verify_dleq_proof(
+    A: &PublicKey,
+    B: &PublicKey,
+    C: &PublicKey,
+    proof: &DleqProof,
+    m: Option<&[u8; 32]>,
+) -> bool {
+    verify_dleq_proof_with_generator(A, B, C, proof, &generator(), m)
+}
+
+/// Verify a DLEQ proof that `A = a·G` and `C = a·B` have the same discrete logarithm,
+/// for an arbitrary generator point `G`.
+///
+/// See [`verify_dleq_proof`] for the arguments.
+pub fn verify_dleq_proof_with_generator(
+    A: &PublicKey,
+    B: &PublicKey,
+    C: &PublicKey,
+    proof: &DleqProof,
+    G: &PublicKey,
+    m: Option<&[u8; 32]>,
+) -> bool {
+    let secp = Secp256k1::verification_only();
+
+    // a challenge that is out of range can never be equal to a valid challenge hash,
+    // except with negligible probability
+    let Ok(e) = Scalar::from_be_bytes(proof.e()) else {
+        return false;
+    };
+    let Ok(s) = Scalar::from_be_bytes(proof.s()) else {
+        return false;
+    };
+
+    let neg_eA = mul_public(A, &e).map(|p| p.negate(&secp));
+    let neg_eC = mul_public(C, &e).map(|p| p.negate(&secp));
+
+    // R1 = s·G - e·A
+    let Some(R1) = add(mul_public(G, &s), neg_eA) else {
+        return false;
+    };
+    // R2 = s·B - e·C
+    let Some(R2) = add(mul_public(B, &s), neg_eC) else {
+        return false;
+    };
+
+    DleqChallengeHash::from_points_and_m(A, B, C, G, &R1, &R2, m).to_byte_array() == proof.e()
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::constants::CURVE_ORDER;
+
+    use super::reduce_mod_order;
+
+    #[test]
+    fn reduce_mod_order_below_order() {
+        let bytes = [0xaa; 32];
+        assert_eq!(reduce_mod_order(bytes), bytes);
+    }
+
+    #[test]
+    fn reduce_mod_order_above_order() {
+        assert_eq!(reduce_mod_order(CURVE_ORDER), [0u8; 32]);
+
+        let mut expected = [0u8; 32];
+        expected[31] = 1;
+        let mut order_plus_one = CURVE_ORDER;
+        order_plus_one[31] += 1;
+        assert_eq!(reduce_mod_order(order_plus_one), expected);
+
+        // 2^256 - 1 - n
+        let reduced = reduce_mod_order([0xff; 32]);
+        assert_eq!(
+            hex::encode(reduced),
+            "000000000000000000000000000000014551231950b75fc4402da1732fc9bebe"
+        );
+    }
+}
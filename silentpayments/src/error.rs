@@ -1,4 +1,6 @@
-use std::fmt;
+use core::fmt;
+
+use crate::prelude::*;
 
 #[derive(Debug)]
 pub enum Error {
@@ -7,9 +9,13 @@ pub enum Error {
     InvalidAddress(String),
     InvalidSharedSecret(String),
     InvalidVin(String),
+    UnknownWitnessVersion(u8),
+    TooManyRecipients(usize),
     InvalidNetwork(String),
+    InvalidDleqProof(String),
     Secp256k1Error(secp256k1::Error),
     OutOfRangeError(secp256k1::scalar::OutOfRangeError),
+    #[cfg(feature = "std")]
     IOError(std::io::Error),
     EmptyArray,
 }
@@ -22,15 +28,27 @@ impl fmt::Display for Error {
             Error::InvalidAddress(msg) => write!(f, "{}", msg),
             Error::InvalidSharedSecret(msg) => write!(f, "{}", msg),
             Error::InvalidVin(msg) => write!(f, "{}", msg),
+            Error::UnknownWitnessVersion(v) => {
+                write!(f, "Input spends an unknown witness version: {}", v)
+            }
+            Error::TooManyRecipients(n) => write!(
+                f,
+                "Too many recipients for a single scan key: {} (max {})",
+                n,
+                crate::utils::K_MAX
+            ),
             Error::InvalidNetwork(msg) => write!(f, "Invalid network: {}", msg),
+            Error::InvalidDleqProof(msg) => write!(f, "Invalid DLEQ proof: {}", msg),
             Error::Secp256k1Error(e) => e.fmt(f),
             Error::OutOfRangeError(e) => e.fmt(f),
+            #[cfg(feature = "std")]
             Error::IOError(e) => e.fmt(f),
             Error::EmptyArray => write!(f, "Non-empty array required"),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for Error {}
 
 #[cfg(any(feature = "sending", feature = "receiving"))]
@@ -59,6 +77,7 @@ impl From<secp256k1::scalar::OutOfRangeError> for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Error::IOError(e)
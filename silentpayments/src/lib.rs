@@ -5,11 +5,15 @@
 //!
 //! This library offers granular feature flags to minimize dependencies:
 //!
-//! - **default**: Enables `encode`, `sending`, and `receiving` features
+//! - **default**: Enables `std`, `encode`, `sending`, and `receiving` features
+//! - **std**: Uses the standard library. Without it, the crate is `no_std` and only requires `alloc`
 //! - **encode**: Enables string encoding/decoding for `SilentPaymentAddressDisplay` (requires `bech32`)
 //! - **serde**: Enables serde serialization/deserialization for types
 //! - **sending**: Enables sending functionality (requires `bitcoin_hashes`, `hex`, and `encode`)
 //! - **receiving**: Enables receiving functionality (requires `bitcoin_hashes`, `hex`, `bimap`, `serde`, and `encode`)
+//! - **zeroize**: Wipes secret keys such as [`PartialSecret`](utils::sending::PartialSecret) and [`SharedSecret`] from memory when they are dropped
+//! - **rayon**: Scans batches of transactions in parallel in [`Receiver::scan_transactions`](receiving::Receiver::scan_transactions) (requires `rayon` and `std`)
+//! - **bitcoin**: Enables helpers that work on `rust-bitcoin` types, such as `utils::receiving::tweak_data_for_tx` (requires `bitcoin` and `receiving`)
 //!
 //! ### Minimal Usage
 //!
@@ -18,7 +22,7 @@
 //!
 //! ```toml
 //! [dependencies]
-//! silentpayments = { version = "0.4", default-features = false }
+//! silentpayments = { version = "0.6", default-features = false }
 //! ```
 //!
 //! This will only pull in `secp256k1` as a dependency, giving you access to the core types
@@ -29,6 +33,19 @@
 //! [`SilentPaymentAddressDisplay`] for bech32m strings (see [`SilentPaymentAddressDisplay::new`]
 //! for the on-wire format if you parse bech32 yourself).
 //!
+//! ### `no_std` Usage
+//!
+//! The sending functions and [`Receiver::scan_transaction`](receiving::Receiver::scan_transaction)
+//! are available on targets without the standard library, e.g. hardware signing devices:
+//!
+//! ```toml
+//! [dependencies]
+//! silentpayments = { version = "0.6", default-features = false, features = ["sending", "receiving"] }
+//! ```
+//!
+//! The maps and sets that are returned are always the [`hashbrown`] types, with or without `std`.
+//! The `bitcoin` feature requires `std`.
+//!
 //! ## Examples
 //!
 //! Will be added soon.
@@ -37,7 +54,14 @@
 //!
 //! Alternatively, have a look at [Sp client](https://github.com/cygnet3/sp-client/tree/master),
 //! which is a WIP wallet client for building silent payment wallets.
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 #![allow(dead_code, non_snake_case)]
+
+#[cfg(not(feature = "std"))]
+extern crate alloc;
+
+#[cfg(any(feature = "sending", feature = "receiving"))]
+pub mod dleq;
 mod error;
 
 #[cfg(feature = "receiving")]
@@ -48,6 +72,7 @@ pub mod utils;
 
 #[cfg(any(feature = "sending", feature = "receiving"))]
 pub use bitcoin_hashes;
+pub use hashbrown;
 pub use secp256k1;
 
 pub use crate::error::Error;
@@ -59,4 +84,29 @@ pub use utils::common::SilentPaymentAddress;
 pub use utils::common::SilentPaymentAddressDisplay;
 pub use utils::common::SpVersion;
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The allocating types that are used throughout the crate, from either `std` or `alloc`.
+mod prelude {
+    #![allow(unused_imports)]
+
+    #[cfg(not(feature = "std"))]
+    pub use alloc::{
+        borrow::ToOwned,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+    // `hashbrown` in both configurations, so that enabling `std` doesn't change the public types
+    pub use hashbrown::{HashMap, HashSet};
+
+    #[cfg(feature = "std")]
+    pub use std::{
+        borrow::ToOwned,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
@@ -10,20 +10,18 @@
 //! To do so, you can use [`calculate_ecdh_shared_secret`](`crate::utils::receiving::calculate_ecdh_shared_secret`) from the `utils` module.
 //!
 //! For a concrete example, have a look at the [test vectors](https://github.com/cygnet3/rust-silentpayments/blob/master/tests/vector_tests.rs).
-use std::{
-    collections::{HashMap, HashSet},
-    fmt,
-};
+use core::{fmt, ops::Range};
 
 use crate::{
+    prelude::*,
     utils::{
         common::{calculate_P_n, calculate_t_n, SharedSecret},
         hash::LabelHash,
-        OP_1, OP_PUSHBYTES_32,
+        K_MAX, OP_1, OP_PUSHBYTES_32,
     },
     Error, Network, Result, SilentPaymentAddress, SpVersion,
 };
-use secp256k1::{Parity, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use secp256k1::{Parity, PublicKey, Scalar, Secp256k1, SecretKey, VerifyOnly, XOnlyPublicKey};
 use serde::{
     de::{self, SeqAccess, Visitor},
     ser::{SerializeStruct, SerializeTuple},
@@ -31,18 +29,31 @@ use serde::{
 };
 
 /// A Silent payment receiving label.
-#[derive(Eq, PartialEq, Clone)]
+///
+/// A label is the tweak `hash(b_scan || m)`. If the label was created with [Label::new], it also remembers the integer `m`.
+/// Labels are compared by their tweak only, whether `m` is known or not.
+#[derive(Clone)]
 pub struct Label {
     s: Scalar,
+    m: Option<u32>,
 }
 
 impl Label {
     pub fn new(b_scan: SecretKey, m: u32) -> Label {
         Label {
             s: LabelHash::from_b_scan_and_m(b_scan, m).to_scalar(),
+            m: Some(m),
         }
     }
 
+    /// The integer `m` this label was created from, if known.
+    ///
+    /// Labels that are parsed from their tweak don't know their `m`,
+    /// use [Receiver::get_label_index] to look it up.
+    pub fn m(&self) -> Option<u32> {
+        self.m
+    }
+
     pub fn into_inner(self) -> Scalar {
         self.s
     }
@@ -62,8 +73,16 @@ impl fmt::Debug for Label {
     }
 }
 
-impl std::hash::Hash for Label {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+impl PartialEq for Label {
+    fn eq(&self, other: &Self) -> bool {
+        self.s == other.s
+    }
+}
+
+impl Eq for Label {}
+
+impl core::hash::Hash for Label {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         let bytes = self.s.to_be_bytes();
         bytes.hash(state);
     }
@@ -71,7 +90,7 @@ impl std::hash::Hash for Label {
 
 impl From<Scalar> for Label {
     fn from(s: Scalar) -> Self {
-        Label { s }
+        Label { s, m: None }
     }
 }
 
@@ -105,7 +124,7 @@ impl From<Label> for Scalar {
 }
 
 impl Serialize for Label {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
@@ -114,7 +133,7 @@ impl Serialize for Label {
 }
 
 impl<'de> Deserialize<'de> for Label {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -123,6 +142,54 @@ impl<'de> Deserialize<'de> for Label {
     }
 }
 
+/// The outputs found by [Receiver::scan_transaction], by label and output key, with the tweak needed to spend each output.
+pub type FoundOutputs = HashMap<Option<Label>, HashMap<XOnlyPublicKey, Scalar>>;
+
+/// The label table used while scanning.
+enum LabelLookup<'a> {
+    /// Labels by their public key `mG`.
+    Direct(&'a HashMap<PublicKey, Label>),
+    /// Labels by their negated public key `-mG`.
+    Negated(&'a HashMap<PublicKey, &'a Label>),
+}
+
+impl LabelLookup<'_> {
+    /// Finds an output that is `P_n` tweaked by one of our labels, i.e. `output - P_n = mG`.
+    fn find<'o>(
+        &self,
+        secp: &Secp256k1<VerifyOnly>,
+        P_n: &PublicKey,
+        outputs: &'o [XOnlyPublicKey],
+    ) -> Result<Option<(&'o XOnlyPublicKey, &Label)>> {
+        match self {
+            Self::Direct(labels) => {
+                // We subtract P_n from each output and check if it matches a public key in our label list
+                let neg_P_n = P_n.negate(secp);
+                for p in outputs {
+                    for parity in [Parity::Even, Parity::Odd] {
+                        let diff = p.public_key(parity).combine(&neg_P_n)?;
+                        if let Some(label) = labels.get(&diff) {
+                            return Ok(Some((p, label)));
+                        }
+                    }
+                }
+            }
+            Self::Negated(labels) => {
+                // P_n - output = -mG, where negating the output only flips its parity
+                for p in outputs {
+                    for parity in [Parity::Odd, Parity::Even] {
+                        let diff = P_n.combine(&p.public_key(parity))?;
+                        if let Some(label) = labels.get(&diff) {
+                            return Ok(Some((p, *label)));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
 /// A struct representing a silent payment recipient.
 ///
 /// It can be used to scan for transaction outputs belonging to us by using the [`scan_transaction`](Receiver::scan_transaction) function.
@@ -142,8 +209,13 @@ struct SerializablePubkey([u8; 33]);
 
 struct SerializableHashMap(HashMap<PublicKey, Label>);
 
+/// An entry of the labels map, `(mG, label, m)`.
+///
+/// Before schema version 2, entries didn't store `m`.
+struct SerializableLabelEntry(SerializablePubkey, Label, Option<u32>);
+
 impl Serialize for SerializablePubkey {
-    fn serialize<S>(&self, serializer: S) -> std::prelude::v1::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
@@ -156,7 +228,7 @@ impl Serialize for SerializablePubkey {
 }
 
 impl<'de> Deserialize<'de> for SerializablePubkey {
-    fn deserialize<D>(deserializer: D) -> std::prelude::v1::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -169,10 +241,7 @@ impl<'de> Deserialize<'de> for SerializablePubkey {
                 formatter.write_str("an array of 33 bytes")
             }
 
-            fn visit_seq<V>(
-                self,
-                mut seq: V,
-            ) -> std::prelude::v1::Result<SerializablePubkey, V::Error>
+            fn visit_seq<V>(self, mut seq: V) -> core::result::Result<SerializablePubkey, V::Error>
             where
                 V: SeqAccess<'de>,
             {
@@ -191,40 +260,89 @@ impl<'de> Deserialize<'de> for SerializablePubkey {
 }
 
 impl Serialize for SerializableHashMap {
-    fn serialize<S>(&self, serializer: S) -> std::prelude::v1::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let pairs: Vec<(SerializablePubkey, Label)> = self
-            .0
-            .iter()
-            .map(|(pubkey, label)| (SerializablePubkey(pubkey.serialize()), label.to_owned()))
+        let mut labels: Vec<(&PublicKey, &Label)> = self.0.iter().collect();
+        // sort the labels, so that the same receiver always serializes the same way
+        labels.sort_unstable_by_key(|(pubkey, _)| *pubkey);
+
+        let entries: Vec<(SerializablePubkey, Label, Option<u32>)> = labels
+            .into_iter()
+            .map(|(pubkey, label)| {
+                (
+                    SerializablePubkey(pubkey.serialize()),
+                    label.to_owned(),
+                    label.m,
+                )
+            })
             .collect();
-        // Now serialize `pairs` as a vector of tuples
-        pairs.serialize(serializer)
+        // Now serialize `entries` as a vector of tuples
+        entries.serialize(serializer)
     }
 }
 
 impl<'de> Deserialize<'de> for SerializableHashMap {
-    fn deserialize<D>(deserializer: D) -> std::prelude::v1::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let pairs: Vec<(SerializablePubkey, Label)> = Deserialize::deserialize(deserializer)?;
+        let entries: Vec<SerializableLabelEntry> = Deserialize::deserialize(deserializer)?;
         let mut map: HashMap<PublicKey, Label> = HashMap::new();
-        for (ser_pubkey, label) in pairs {
-            map.insert(PublicKey::from_slice(&ser_pubkey.0).unwrap(), label);
+        for SerializableLabelEntry(ser_pubkey, mut label, m) in entries {
+            let pubkey = PublicKey::from_slice(&ser_pubkey.0).map_err(de::Error::custom)?;
+            label.m = m;
+            map.insert(pubkey, label);
         }
         Ok(SerializableHashMap(map))
     }
 }
 
+impl<'de> Deserialize<'de> for SerializableLabelEntry {
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SerializableLabelEntryVisitor;
+
+        impl<'de> Visitor<'de> for SerializableLabelEntryVisitor {
+            type Value = SerializableLabelEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a public key, a label and an optional index")
+            }
+
+            fn visit_seq<V>(
+                self,
+                mut seq: V,
+            ) -> core::result::Result<SerializableLabelEntry, V::Error>
+            where
+                V: SeqAccess<'de>,
+            {
+                let pubkey = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let label = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                // missing in older schema versions
+                let m = seq.next_element()?.flatten();
+                Ok(SerializableLabelEntry(pubkey, label, m))
+            }
+        }
+
+        deserializer.deserialize_tuple(3, SerializableLabelEntryVisitor)
+    }
+}
+
 impl Serialize for Receiver {
-    fn serialize<S>(&self, serializer: S) -> std::prelude::v1::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Receiver", 5)?;
+        let mut state = serializer.serialize_struct("Receiver", 7)?;
+        state.serialize_field("schema_version", &Receiver::SCHEMA_VERSION)?;
         state.serialize_field::<u8>("version", &self.version.into())?;
         state.serialize_field("network", &self.network)?;
         state.serialize_field(
@@ -243,6 +361,9 @@ impl Serialize for Receiver {
 
 #[derive(Deserialize)]
 struct ReceiverHelper {
+    // absent in the original format, which is schema version 0
+    #[serde(default)]
+    schema_version: u8,
     version: u8,
     network: Network,
     scan_pubkey: SerializablePubkey,
@@ -252,23 +373,70 @@ struct ReceiverHelper {
 }
 
 impl<'de> Deserialize<'de> for Receiver {
-    fn deserialize<D>(deserializer: D) -> std::prelude::v1::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let helper = ReceiverHelper::deserialize(deserializer)?;
-        Ok(Receiver {
-            version: helper.version.try_into().unwrap(),
+        Receiver::try_from(helper).map_err(de::Error::custom)
+    }
+}
+
+impl TryFrom<ReceiverHelper> for Receiver {
+    type Error = Error;
+
+    fn try_from(helper: ReceiverHelper) -> Result<Self> {
+        match helper.schema_version {
+            // version 1 only added the schema version itself, version 2 added the index of the labels
+            0..=2 => (),
+            v => {
+                return Err(Error::GenericError(format!(
+                    "Unsupported receiver schema version: {}",
+                    v
+                )))
+            }
+        }
+
+        let receiver = Receiver {
+            version: helper.version.try_into()?,
             network: helper.network,
-            scan_pubkey: PublicKey::from_slice(&helper.scan_pubkey.0).unwrap(),
-            spend_pubkey: PublicKey::from_slice(&helper.spend_pubkey.0).unwrap(),
-            change_label: Label::try_from(helper.change_label).unwrap(),
+            scan_pubkey: PublicKey::from_slice(&helper.scan_pubkey.0)?,
+            spend_pubkey: PublicKey::from_slice(&helper.spend_pubkey.0)?,
+            change_label: Label::try_from(helper.change_label)?,
             labels: helper.labels.0,
-        })
+        };
+
+        // every label must be stored under its own public key, and the change label must be known
+        let secp = Secp256k1::signing_only();
+        for (mG, label) in &receiver.labels {
+            let tweak = SecretKey::from_slice(&label.as_inner().to_be_bytes())?;
+            if tweak.public_key(&secp) != *mG {
+                return Err(Error::InvalidLabel(format!(
+                    "Label {} doesn't match its public key",
+                    label.as_string()
+                )));
+            }
+        }
+        if !receiver
+            .labels
+            .values()
+            .any(|l| *l == receiver.change_label)
+        {
+            return Err(Error::InvalidLabel("Change label is missing".to_owned()));
+        }
+
+        Ok(receiver)
     }
 }
 
 impl Receiver {
+    /// Version of the serialization format of a [Receiver].
+    ///
+    /// Version 0 is the original format, without a `schema_version` field.
+    /// Version 1 added the `schema_version` field, and version 2 stores the integer `m` of each label, if known.
+    /// Older versions are still accepted when deserializing, and are migrated to the current version.
+    pub const SCHEMA_VERSION: u8 = 2;
+
     pub fn new(
         version: SpVersion,
         scan_pubkey: PublicKey,
@@ -304,7 +472,67 @@ impl Receiver {
         // check that the combined key with spend_key is valid
         mG.combine(&self.spend_pubkey)?;
 
-        Ok(self.labels.insert(mG, label).is_none())
+        Ok(self.insert_label(mG, label))
+    }
+
+    /// Adds the labels for all integers `m` in the given range, see [Label::new].
+    ///
+    /// This is meant for registering many labels at once, e.g. one for each customer.
+    /// Returns the number of labels that were new.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    ///
+    /// * `b_scan` is not the scan secret key of this receiver.
+    /// * A label results in an invalid key (extremely unlikely).
+    pub fn add_labels(&mut self, b_scan: SecretKey, m: Range<u32>) -> Result<usize> {
+        let secp = Secp256k1::signing_only();
+
+        if b_scan.public_key(&secp) != self.scan_pubkey {
+            return Err(Error::InvalidLabel(
+                "Scan key doesn't belong to this receiver".to_owned(),
+            ));
+        }
+
+        self.labels.reserve(m.len());
+
+        let mut added = 0;
+        for m in m {
+            let label = Label::new(b_scan, m);
+            let mG = SecretKey::from_slice(&label.as_inner().to_be_bytes())?.public_key(&secp);
+
+            // check that the combined key with spend_key is valid
+            mG.combine(&self.spend_pubkey)?;
+
+            if self.insert_label(mG, label) {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Returns true if the label was new.
+    fn insert_label(&mut self, mG: PublicKey, label: Label) -> bool {
+        if let Some(known) = self.labels.get_mut(&mG) {
+            // don't forget the index if we already knew it
+            known.m = label.m.or(known.m);
+            return false;
+        }
+        self.labels.insert(mG, label);
+        true
+    }
+
+    /// Looks up the integer `m` of a known label from its tweak.
+    ///
+    /// This can be used to find out which label received an output, since labels that are deserialized on their own don't know their `m`.
+    ///
+    /// Returns [None] if the label is unknown, or if it was added without its `m`.
+    pub fn get_label_index(&self, label: &Scalar) -> Option<u32> {
+        let tweak = SecretKey::from_slice(&label.to_be_bytes()).ok()?;
+        let mG = tweak.public_key(&Secp256k1::signing_only());
+
+        self.labels.get(&mG).and_then(Label::m)
     }
 
     /// List all currently known labels used by this recipient.
@@ -370,6 +598,8 @@ impl Receiver {
     ///
     /// If successful, the function returns a [Result] wrapping a [HashMap] of labels to a map of outputs to key tweaks (since the same label may have been paid multiple times in one transaction). The key tweaks can be added to the wallet's spending private key to produce a key that can spend the utxo. A resulting [HashMap] of length 0 implies none of the outputs are owned by us.
     ///
+    /// At most [K_MAX] outputs are returned, any further outputs in the transaction are not scanned for.
+    ///
     /// # Errors
     ///
     /// This function will return an error if:
@@ -380,39 +610,88 @@ impl Receiver {
         &self,
         ecdh_shared_secret: &SharedSecret,
         pubkeys_to_check: &[XOnlyPublicKey],
-    ) -> Result<HashMap<Option<Label>, HashMap<XOnlyPublicKey, Scalar>>> {
-        let secp = secp256k1::Secp256k1::new();
+    ) -> Result<FoundOutputs> {
+        let secp = Secp256k1::verification_only();
+
+        self.scan_transaction_with(
+            &secp,
+            &LabelLookup::Direct(&self.labels),
+            ecdh_shared_secret,
+            pubkeys_to_check,
+        )
+    }
 
-        let mut found: HashMap<Option<Label>, HashMap<XOnlyPublicKey, Scalar>> = HashMap::new();
+    /// Scans a batch of transactions for outputs belonging to us.
+    ///
+    /// This gives the same results as calling [scan_transaction](Receiver::scan_transaction) for each transaction,
+    /// but the work that doesn't depend on the transaction is only done once for the whole batch.
+    /// With the `rayon` feature, the transactions are scanned in parallel.
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The ECDH shared secret and the taproot output keys of each transaction, see [scan_transaction](Receiver::scan_transaction).
+    ///
+    /// # Returns
+    ///
+    /// The result of scanning each transaction, in the same order as the given transactions.
+    /// An error for one transaction doesn't affect the others.
+    pub fn scan_transactions(
+        &self,
+        transactions: &[(SharedSecret, Vec<XOnlyPublicKey>)],
+    ) -> Vec<Result<FoundOutputs>> {
+        // if using rayon feature, import the preludes
+        #[cfg(feature = "rayon")]
+        use rayon::prelude::*;
+
+        let secp = Secp256k1::verification_only();
+
+        // the negated label keys, so that we don't have to negate P_n for every transaction
+        let negated_labels: HashMap<PublicKey, &Label> = self
+            .labels
+            .iter()
+            .map(|(mG, label)| (mG.negate(&secp), label))
+            .collect();
+        let labels = LabelLookup::Negated(&negated_labels);
+
+        // parallel iterator using rayon
+        #[cfg(feature = "rayon")]
+        let tx_iterator = transactions.par_iter();
+
+        // regular iterator
+        #[cfg(not(feature = "rayon"))]
+        let tx_iterator = transactions.iter();
+
+        tx_iterator
+            .map(|(ecdh_shared_secret, pubkeys_to_check)| {
+                self.scan_transaction_with(&secp, &labels, ecdh_shared_secret, pubkeys_to_check)
+            })
+            .collect()
+    }
+
+    fn scan_transaction_with(
+        &self,
+        secp: &Secp256k1<VerifyOnly>,
+        labels: &LabelLookup,
+        ecdh_shared_secret: &SharedSecret,
+        pubkeys_to_check: &[XOnlyPublicKey],
+    ) -> Result<FoundOutputs> {
+        let mut found: FoundOutputs = HashMap::new();
         let mut n_found: u32 = 0;
         let mut n: u32 = 0;
-        while n_found == n {
+        while n_found == n && n < K_MAX {
             let t_n: SecretKey = calculate_t_n(ecdh_shared_secret, n)?;
-            let P_n: PublicKey = calculate_P_n(&self.spend_pubkey, t_n.into())?;
+            let P_n: PublicKey = self.spend_pubkey.add_exp_tweak(secp, &t_n.into())?;
             let P_n_xonly = P_n.x_only_public_key().0;
             if pubkeys_to_check.iter().any(|p| p.eq(&P_n_xonly)) {
                 n_found += 1;
                 found.entry(None).or_default().insert(P_n_xonly, t_n.into());
-            } else {
-                // We subtract P_n from each outputs to check and see if match a public key in our label list
-                'outer: for p in pubkeys_to_check {
-                    let even_output = p.public_key(Parity::Even);
-                    let odd_output = p.public_key(Parity::Odd);
-                    let even_diff = even_output.combine(&P_n.negate(&secp))?;
-                    let odd_diff = odd_output.combine(&P_n.negate(&secp))?;
-
-                    for diff in [even_diff, odd_diff] {
-                        if let Some(label) = self.labels.get(&diff) {
-                            n_found += 1;
-                            let t_n_label = t_n.add_tweak(label.as_inner())?;
-                            found
-                                .entry(Some(label.clone()))
-                                .or_default()
-                                .insert(*p, t_n_label.into());
-                            break 'outer;
-                        }
-                    }
-                }
+            } else if let Some((p, label)) = labels.find(secp, &P_n, pubkeys_to_check)? {
+                n_found += 1;
+                let t_n_label = t_n.add_tweak(label.as_inner())?;
+                found
+                    .entry(Some(label.clone()))
+                    .or_default()
+                    .insert(*p, t_n_label.into());
             }
             n += 1;
         }
@@ -535,4 +814,240 @@ mod tests {
 
         assert_eq!(receiver, deserialized);
     }
+
+    fn receiver_json() -> serde_json::Value {
+        let scan_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let spend_key = SecretKey::from_slice(&[2u8; 32]).unwrap();
+        let secp = Secp256k1::new();
+
+        let mut receiver = super::Receiver::new(
+            SpVersion::ZERO,
+            scan_key.public_key(&secp),
+            spend_key.public_key(&secp),
+            Label::new(scan_key, 0),
+            crate::Network::Testnet,
+        )
+        .unwrap();
+        receiver.add_label(Label::new(scan_key, 1)).unwrap();
+
+        serde_json::to_value(&receiver).unwrap()
+    }
+
+    #[test]
+    fn deserialize_legacy_receiver() {
+        let json = receiver_json();
+        assert_eq!(json["schema_version"], super::Receiver::SCHEMA_VERSION);
+
+        let receiver: super::Receiver = serde_json::from_value(json.clone()).unwrap();
+
+        // the original format has no schema version
+        let mut legacy = json.clone();
+        legacy.as_object_mut().unwrap().remove("schema_version");
+        let migrated: super::Receiver = serde_json::from_value(legacy).unwrap();
+
+        assert_eq!(receiver, migrated);
+        assert_eq!(serde_json::to_value(&migrated).unwrap(), json);
+    }
+
+    #[test]
+    fn deserialize_corrupt_receiver() {
+        let modified = |f: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = receiver_json();
+            f(&mut json);
+            serde_json::from_value::<super::Receiver>(json)
+        };
+
+        assert!(modified(&|_| ()).is_ok());
+
+        assert!(modified(&|json| {
+            json["schema_version"] = (super::Receiver::SCHEMA_VERSION + 1).into()
+        })
+        .is_err());
+        assert!(modified(&|json| json["version"] = 31.into()).is_err());
+        assert!(modified(&|json| json["scan_pubkey"][0] = 0x05.into()).is_err());
+        assert!(modified(&|json| json["spend_pubkey"] = serde_json::json!([2, 3])).is_err());
+        assert!(modified(&|json| json["change_label"] = "deadbeef".into()).is_err());
+        assert!(modified(&|json| json["labels"][0][0][0] = 0x05.into()).is_err());
+        // labels stored under the wrong public key
+        assert!(modified(&|json| {
+            let first = json["labels"][0][1].clone();
+            json["labels"][0][1] = json["labels"][1][1].clone();
+            json["labels"][1][1] = first;
+        })
+        .is_err());
+        assert!(modified(&|json| json["labels"] = serde_json::json!([])).is_err());
+
+        // truncated data
+        let serialized = receiver_json().to_string();
+        for len in [0, 1, serialized.len() / 2, serialized.len() - 1] {
+            assert!(serde_json::from_str::<super::Receiver>(&serialized[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn label_index() {
+        let scan_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let secp = Secp256k1::new();
+
+        let mut receiver: super::Receiver = serde_json::from_value(receiver_json()).unwrap();
+
+        assert_eq!(receiver.add_labels(scan_key, 1..1001).unwrap(), 999);
+        assert_eq!(receiver.add_labels(scan_key, 0..1001).unwrap(), 0);
+        assert_eq!(receiver.list_labels().len(), 1001);
+
+        let label = Label::new(scan_key, 500);
+        assert_eq!(label.m(), Some(500));
+        assert_eq!(receiver.get_label_index(label.as_inner()), Some(500));
+        assert_eq!(
+            receiver.get_label_index(Label::new(scan_key, 1001).as_inner()),
+            None
+        );
+
+        // a label without its index is equal, and doesn't make the receiver forget the index
+        let unnamed = Label::from(label.clone().into_inner());
+        assert_eq!(unnamed.m(), None);
+        assert_eq!(unnamed, label);
+        assert!(!receiver.add_label(unnamed).unwrap());
+        assert_eq!(receiver.get_label_index(label.as_inner()), Some(500));
+
+        // the index survives serialization
+        let json = serde_json::to_value(&receiver).unwrap();
+        let deserialized: super::Receiver = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.get_label_index(label.as_inner()), Some(500));
+        let address = receiver.get_receiving_address_for_label(&label).unwrap();
+        assert_eq!(
+            deserialized
+                .get_receiving_address_for_label(&label)
+                .unwrap(),
+            address
+        );
+
+        // labels from another scan key are rejected
+        let other_scan_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        assert_ne!(other_scan_key.public_key(&secp), scan_key.public_key(&secp));
+        assert!(receiver.add_labels(other_scan_key, 0..10).is_err());
+    }
+
+    #[test]
+    fn deserialize_labels_without_index() {
+        let mut json = receiver_json();
+        json["schema_version"] = 1.into();
+        for entry in json["labels"].as_array_mut().unwrap() {
+            entry.as_array_mut().unwrap().truncate(2);
+        }
+
+        let receiver: super::Receiver = serde_json::from_value(json).unwrap();
+        let scan_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+
+        let label = Label::new(scan_key, 1);
+        assert!(receiver.list_labels().contains(&label));
+        assert_eq!(receiver.get_label_index(label.as_inner()), None);
+    }
+
+    #[cfg(feature = "sending")]
+    #[test]
+    fn scan_reports_label_index() {
+        use crate::sending::generate_recipient_pubkeys;
+        use crate::utils::{
+            common::OutPoint,
+            receiving::{calculate_ecdh_shared_secret, calculate_tweak_data},
+            sending::calculate_partial_secret,
+        };
+
+        let scan_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let secp = Secp256k1::new();
+
+        let mut receiver: super::Receiver = serde_json::from_value(receiver_json()).unwrap();
+        receiver.add_labels(scan_key, 1..100).unwrap();
+
+        let address = receiver
+            .get_receiving_address_for_label(&Label::new(scan_key, 42))
+            .unwrap();
+
+        let input_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let outpoint = OutPoint::from_bytes([0x22; 36]);
+        let partial_secret = calculate_partial_secret(&[(input_key, false)], &[outpoint]).unwrap();
+        let outputs = generate_recipient_pubkeys(vec![address], partial_secret).unwrap();
+        let outputs: Vec<_> = outputs.into_values().flatten().collect();
+
+        let tweak_data =
+            calculate_tweak_data(&[&input_key.public_key(&secp)], &[outpoint]).unwrap();
+        let shared_secret = calculate_ecdh_shared_secret(&tweak_data, &scan_key);
+
+        let found = receiver.scan_transaction(&shared_secret, &outputs).unwrap();
+        let label = found.keys().next().unwrap().as_ref().unwrap();
+        assert_eq!(label.m(), Some(42));
+    }
+
+    #[cfg(feature = "sending")]
+    #[test]
+    fn scan_transactions_matches_scan_transaction() {
+        use crate::sending::generate_recipient_pubkeys;
+        use crate::utils::{
+            common::OutPoint,
+            receiving::{calculate_ecdh_shared_secret, calculate_tweak_data},
+            sending::calculate_partial_secret,
+        };
+        use crate::SilentPaymentAddress;
+
+        let scan_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let secp = Secp256k1::new();
+
+        let mut receiver: super::Receiver = serde_json::from_value(receiver_json()).unwrap();
+        receiver.add_labels(scan_key, 1..50).unwrap();
+
+        let address = |m: Option<u32>| match m {
+            Some(m) => receiver
+                .get_receiving_address_for_label(&Label::new(scan_key, m))
+                .unwrap(),
+            None => receiver.get_receiving_address(),
+        };
+        let stranger = {
+            let other = SecretKey::from_slice(&[9u8; 32]).unwrap().public_key(&secp);
+            SilentPaymentAddress::new(SpVersion::ZERO, other, other)
+        };
+
+        let payments: Vec<Vec<SilentPaymentAddress>> = vec![
+            vec![address(None)],
+            vec![address(Some(7)), address(Some(7)), stranger],
+            vec![stranger],
+            vec![address(Some(0)), address(None), address(Some(49))],
+            vec![],
+        ];
+
+        let transactions: Vec<_> = payments
+            .into_iter()
+            .enumerate()
+            .map(|(i, recipients)| {
+                let input_key = SecretKey::from_slice(&[0x11 + i as u8; 32]).unwrap();
+                let outpoint = OutPoint::from_bytes([0x22 + i as u8; 36]);
+                let partial_secret =
+                    calculate_partial_secret(&[(input_key, false)], &[outpoint]).unwrap();
+                let outputs: Vec<_> = generate_recipient_pubkeys(recipients, partial_secret)
+                    .unwrap()
+                    .into_values()
+                    .flatten()
+                    .collect();
+
+                let tweak_data =
+                    calculate_tweak_data(&[&input_key.public_key(&secp)], &[outpoint]).unwrap();
+                let shared_secret = calculate_ecdh_shared_secret(&tweak_data, &scan_key);
+                (shared_secret, outputs)
+            })
+            .collect();
+
+        let batch = receiver.scan_transactions(&transactions);
+        assert_eq!(batch.len(), transactions.len());
+
+        let n_found: Vec<usize> = batch
+            .iter()
+            .map(|found| found.as_ref().unwrap().values().map(|m| m.len()).sum())
+            .collect();
+        assert_eq!(n_found, [1, 2, 0, 3, 0]);
+
+        for ((shared_secret, outputs), found) in transactions.iter().zip(batch) {
+            let single = receiver.scan_transaction(shared_secret, outputs).unwrap();
+            assert_eq!(found.unwrap(), single);
+        }
+    }
 }
@@ -9,14 +9,15 @@
 //! for a concrete example.
 
 use secp256k1::{PublicKey, Secp256k1, XOnlyPublicKey};
-use std::collections::HashMap;
 
+use crate::prelude::*;
 use crate::utils::common::calculate_t_n;
 use crate::utils::common::SharedSecret;
 use crate::utils::common::SilentPaymentAddress;
 use crate::utils::sending::calculate_ecdh_shared_secret;
 use crate::utils::sending::PartialSecret;
-use crate::Result;
+use crate::utils::K_MAX;
+use crate::{Error, Result};
 
 /// Create outputs for a given set of silent payment recipients and their corresponding shared secrets.
 ///
@@ -26,7 +27,7 @@ use crate::Result;
 ///
 /// # Arguments
 ///
-/// * `recipients` - A [Vec] of silent payment addresses to be paid.
+/// * `recipients` - A [Vec] of silent payment addresses to be paid. Addresses with a version higher than 0 are paid exactly like version 0 addresses.
 /// * `partial_secret` - [PartialSecret] that represents the sum of the private keys of eligible inputs of the transaction multiplied by the input hash.
 ///
 /// # Returns
@@ -38,6 +39,7 @@ use crate::Result;
 ///
 /// This function will return an error if:
 ///
+/// * More than [K_MAX] recipients share the same scan key, see [Error::TooManyRecipients].
 /// * Edge cases are hit during elliptic curve computation (extremely unlikely).
 pub fn generate_recipient_pubkeys(
     recipients: Vec<SilentPaymentAddress>,
@@ -60,6 +62,13 @@ pub fn generate_recipient_pubkeys(
         }
     }
 
+    if let Some((_, recipients)) = silent_payment_groups
+        .values()
+        .find(|(_, recipients)| recipients.len() > K_MAX as usize)
+    {
+        return Err(Error::TooManyRecipients(recipients.len()));
+    }
+
     let mut result: HashMap<SilentPaymentAddress, Vec<XOnlyPublicKey>> = HashMap::new();
     for group in silent_payment_groups.into_values() {
         let (ecdh_shared_secret, recipients) = group;
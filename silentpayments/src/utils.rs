@@ -12,6 +12,8 @@ pub(crate) mod script;
 pub mod sending;
 
 pub(crate) mod common;
+#[cfg(feature = "zeroize")]
+pub(crate) mod erase;
 
 #[cfg(any(feature = "sending", feature = "receiving"))]
 pub use common::OutPoint;
@@ -44,6 +46,11 @@ pub(crate) const OP_CHECKSIG: u8 = 0xAC;
 // Only compressed pubkeys are supported for silent payments
 const COMPRESSED_PUBKEY_SIZE: usize = 33;
 
+/// Maximum number of outputs for a single recipient scan key in one transaction, `K_max` in [BIP352](https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki).
+///
+/// This bounds the scanning work a malicious transaction can cause.
+pub const K_MAX: u32 = 2323;
+
 // Derivation paths according to BIP
 pub const MAIN_SCAN_PATH: &str = "m/352h/0h/0h/1h/0";
 pub const MAIN_SPEND_PATH: &str = "m/352h/0h/0h/0h/0";
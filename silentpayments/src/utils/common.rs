@@ -1,6 +1,7 @@
 #[cfg(feature = "encode")]
 use core::fmt;
 
+use crate::prelude::*;
 #[cfg(any(feature = "sending", feature = "receiving"))]
 use crate::utils::hash::SharedSecretHash;
 use crate::Error;
@@ -69,10 +70,48 @@ impl OutPoint {
     }
 }
 
+#[cfg(feature = "bitcoin")]
+impl From<bitcoin::OutPoint> for OutPoint {
+    fn from(outpoint: bitcoin::OutPoint) -> Self {
+        let bytes: [u8; 36] = bitcoin::consensus::serialize(&outpoint)
+            .try_into()
+            .expect("serialized outpoint is 36 bytes");
+        Self(bytes)
+    }
+}
+
+/// The ECDH shared secret between the sender and the recipient of a transaction.
+///
+/// This is secret data, so it doesn't implement `Copy`, and its [Debug](fmt::Debug) output is redacted.
+/// With the `zeroize` feature, the secret is wiped from memory when dropped.
 #[cfg(any(feature = "sending", feature = "receiving"))]
-#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+#[derive(Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct SharedSecret(pub(crate) PublicKey);
 
+#[cfg(any(feature = "sending", feature = "receiving"))]
+impl SharedSecret {
+    /// Wrap the result of an ECDH computation that was done outside of this library, e.g. by a hardware device.
+    ///
+    /// For receiving, the point should be `b_scan * tweak_data`.
+    pub fn from_point(point: PublicKey) -> Self {
+        Self(point)
+    }
+}
+
+#[cfg(any(feature = "sending", feature = "receiving"))]
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedSecret(<redacted>)")
+    }
+}
+
+#[cfg(all(feature = "zeroize", any(feature = "sending", feature = "receiving")))]
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        crate::utils::erase::erase_public_key(&mut self.0);
+    }
+}
+
 #[cfg(any(feature = "sending", feature = "receiving"))]
 pub(crate) fn calculate_t_n(ecdh_shared_secret: &SharedSecret, k: u32) -> Result<SecretKey> {
     let hash = SharedSecretHash::from_ecdh_and_k(ecdh_shared_secret, k).to_byte_array();
@@ -115,7 +154,7 @@ impl From<Network> for &str {
 impl TryFrom<&str> for Network {
     type Error = crate::Error;
 
-    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+    fn try_from(value: &str) -> core::result::Result<Self, Self::Error> {
         let res = match value {
             "bitcoin" | "main" => Self::Mainnet, // We also take the core style argument
             "regtest" => Self::Regtest,
@@ -126,28 +165,45 @@ impl TryFrom<&str> for Network {
     }
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-pub enum SpVersion {
-    ZERO,
+/// Silent payment address version.
+///
+/// Only version 0 is currently defined. Following BIP352, versions 1 to 30 are accepted for forward compatibility:
+/// these addresses are handled like version 0, using only the first 66 bytes of their payload.
+/// Version 31 is reserved for a backwards incompatible upgrade, and is rejected.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpVersion(u8);
+
+impl SpVersion {
+    pub const ZERO: SpVersion = SpVersion(0);
+
+    /// The highest version that can still be parsed as a version 0 address.
+    pub const MAX_COMPATIBLE: SpVersion = SpVersion(30);
+
+    /// Returns true if this is a version 0 address.
+    pub fn is_v0(&self) -> bool {
+        *self == Self::ZERO
+    }
 }
 
 impl From<SpVersion> for u8 {
     fn from(value: SpVersion) -> Self {
-        match value {
-            SpVersion::ZERO => 0u8,
-        }
+        value.0
     }
 }
 
 impl TryFrom<u8> for SpVersion {
     type Error = crate::Error;
 
-    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+    fn try_from(value: u8) -> core::result::Result<Self, Self::Error> {
         match value {
-            0 => Ok(Self::ZERO),
-            _ => Err(Error::GenericError(
-                "Unknown silent payment version".to_string(),
+            0..=30 => Ok(Self(value)),
+            31 => Err(Error::InvalidAddress(
+                "Silent payment version 31 is reserved".to_string(),
             )),
+            _ => Err(Error::InvalidAddress(format!(
+                "Unknown silent payment version: {}",
+                value
+            ))),
         }
     }
 }
@@ -237,9 +293,9 @@ pub struct SilentPaymentAddressDisplay {
     network: Network,
 }
 
-#[cfg(feature = "serde")]
+#[cfg(all(feature = "serde", feature = "encode"))]
 impl Serialize for SilentPaymentAddressDisplay {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
@@ -248,9 +304,9 @@ impl Serialize for SilentPaymentAddressDisplay {
     }
 }
 
-#[cfg(feature = "serde")]
+#[cfg(all(feature = "serde", feature = "encode"))]
 impl<'de> Deserialize<'de> for SilentPaymentAddressDisplay {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -289,6 +345,7 @@ impl SilentPaymentAddressDisplay {
     /// - **Data**: a single 5-bit version digit, then the 66-byte payload
     ///   `serP(B_scan) ‖ serP(B_m)` converted to 5-bit characters.
     ///   `B_m` is the address spend pubkey (labeled or not).
+    ///   Versions 1 to 30 may have a longer payload, of which only the first 66 bytes are used.
     ///
     /// # Example
     ///
@@ -362,6 +419,10 @@ impl fmt::Display for SilentPaymentAddressDisplay {
     }
 }
 
+/// Number of 5-bit characters in the data part of a version 0 address: the version and a 66-byte payload.
+#[cfg(feature = "encode")]
+const V0_DATA_LEN: usize = 107;
+
 #[cfg(feature = "encode")]
 impl TryFrom<&str> for SilentPaymentAddressDisplay {
     type Error = Error;
@@ -369,11 +430,15 @@ impl TryFrom<&str> for SilentPaymentAddressDisplay {
     fn try_from(addr: &str) -> Result<Self> {
         let (hrp, data, _variant) = bech32::decode(addr)?;
 
-        if data.len() != 107 {
+        let Some(version) = data.first() else {
             return Err(Error::GenericError("Address length is wrong".to_owned()));
-        }
+        };
+        let version: SpVersion = version.to_u8().try_into()?;
 
-        let version: SpVersion = data[0].to_u8().try_into()?;
+        // version 0 addresses have an exact length, future versions may be longer
+        if (version.is_v0() && data.len() != V0_DATA_LEN) || data.len() < V0_DATA_LEN {
+            return Err(Error::GenericError("Address length is wrong".to_owned()));
+        }
 
         let network = match hrp.as_str() {
             "sp" => Network::Mainnet,
@@ -387,10 +452,15 @@ impl TryFrom<&str> for SilentPaymentAddressDisplay {
             }
         };
 
-        let data = Vec::<u8>::from_base32(&data[1..])?;
+        let data = if version.is_v0() {
+            Vec::<u8>::from_base32(&data[1..])?
+        } else {
+            // only read the first 66 bytes, the remaining data may not be a whole number of bytes
+            bech32::convert_bits(&data[1..V0_DATA_LEN], 5, 8, true)?
+        };
 
-        let scan_key = PublicKey::from_slice(&data[..33])?;
-        let m_pubkey = PublicKey::from_slice(&data[33..])?;
+        let scan_key = PublicKey::from_slice(&data[..PUBLIC_KEY_SIZE])?;
+        let m_pubkey = PublicKey::from_slice(&data[PUBLIC_KEY_SIZE..PUBLIC_KEY_SIZE * 2])?;
 
         Ok(Self::from_sp_address(
             SilentPaymentAddress::new(version, scan_key, m_pubkey),
@@ -480,4 +550,117 @@ mod tests {
 
         assert_eq!(sp_outpoint_from_txid_and_vout, sp_outpoint_from_bytes);
     }
+
+    #[cfg(feature = "encode")]
+    mod address_versions {
+        use bech32::{ToBase32, Variant};
+        use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+        use crate::{Network, SilentPaymentAddressDisplay, SpVersion};
+
+        fn keys() -> (PublicKey, PublicKey) {
+            let secp = Secp256k1::signing_only();
+            let scan = SecretKey::from_slice(&[0x01; 32])
+                .unwrap()
+                .public_key(&secp);
+            let spend = SecretKey::from_slice(&[0x02; 32])
+                .unwrap()
+                .public_key(&secp);
+            (scan, spend)
+        }
+
+        fn encode(version: u8, extra_data: &[u8]) -> String {
+            let (scan, spend) = keys();
+            let payload = [&scan.serialize()[..], &spend.serialize(), extra_data].concat();
+
+            let mut data = vec![bech32::u5::try_from_u8(version).unwrap()];
+            data.extend(payload.to_base32());
+
+            bech32::encode("sp", data, Variant::Bech32m).unwrap()
+        }
+
+        #[test]
+        fn parse_v0() {
+            let (scan, spend) = keys();
+
+            let address = SilentPaymentAddressDisplay::try_from(encode(0, &[])).unwrap();
+            assert_eq!(address.version(), SpVersion::ZERO);
+            assert_eq!(address.scan_key(), scan);
+            assert_eq!(address.m_pubkey(), spend);
+            assert_eq!(address.network(), Network::Mainnet);
+
+            // version 0 addresses must have exactly 66 bytes of payload
+            assert!(SilentPaymentAddressDisplay::try_from(encode(0, &[0x00])).is_err());
+        }
+
+        #[test]
+        fn parse_future_versions() {
+            let (scan, spend) = keys();
+
+            for version in 1..=30 {
+                for extra_data in [&[][..], &[0xff], &[0xab; 13], &[0x42; 100]] {
+                    let address =
+                        SilentPaymentAddressDisplay::try_from(encode(version, extra_data)).unwrap();
+
+                    assert_eq!(u8::from(address.version()), version);
+                    assert!(!address.version().is_v0());
+                    assert_eq!(address.scan_key(), scan);
+                    assert_eq!(address.m_pubkey(), spend);
+                }
+            }
+        }
+
+        #[test]
+        fn parse_reserved_version() {
+            assert!(SilentPaymentAddressDisplay::try_from(encode(31, &[])).is_err());
+            assert!(SpVersion::try_from(31).is_err());
+            assert!(SpVersion::try_from(32).is_err());
+        }
+
+        #[test]
+        fn parse_short_payload() {
+            let (scan, _) = keys();
+
+            let mut data = vec![bech32::u5::try_from_u8(1).unwrap()];
+            data.extend(scan.serialize().to_base32());
+            let address = bech32::encode("sp", data, Variant::Bech32m).unwrap();
+
+            assert!(SilentPaymentAddressDisplay::try_from(address).is_err());
+        }
+
+        #[test]
+        fn future_version_roundtrip() {
+            let address = SilentPaymentAddressDisplay::try_from(encode(1, &[0xab; 13])).unwrap();
+
+            // the version is kept, the extra data is dropped
+            let encoded: String = address.into();
+            assert_eq!(encoded, encode(1, &[]));
+            assert_eq!(
+                SilentPaymentAddressDisplay::try_from(encoded).unwrap(),
+                address
+            );
+        }
+
+        #[cfg(feature = "sending")]
+        #[test]
+        fn future_versions_are_paid_like_v0() {
+            use crate::sending::generate_recipient_pubkeys;
+            use crate::utils::{common::OutPoint, sending::calculate_partial_secret};
+
+            let input_key = SecretKey::from_slice(&[0x11; 32]).unwrap();
+            let outpoint = OutPoint::from_bytes([0x22; 36]);
+            let send = |address: &str| {
+                let address = SilentPaymentAddressDisplay::try_from(address).unwrap();
+                let partial_secret =
+                    calculate_partial_secret(&[(input_key, false)], &[outpoint]).unwrap();
+                let outputs =
+                    generate_recipient_pubkeys(vec![address.as_inner()], partial_secret).unwrap();
+                outputs.into_values().next().unwrap()
+            };
+
+            let v0_outputs = send(&encode(0, &[]));
+            assert_eq!(send(&encode(1, &[0xab; 13])), v0_outputs);
+            assert_eq!(send(&encode(30, &[])), v0_outputs);
+        }
+    }
 }
@@ -0,0 +1,51 @@
+//! Helpers for wiping key material from memory.
+//!
+//! The keys are invalid afterwards, so they should only be called right before a key is dropped.
+use secp256k1::ffi;
+use secp256k1::{PublicKey, SecretKey};
+
+/// Overwrite a [SecretKey] with a constant, see [SecretKey::non_secure_erase].
+pub(crate) fn erase_secret_key(key: &mut SecretKey) {
+    key.non_secure_erase();
+}
+
+/// Overwrite a [PublicKey] with zeroes, the same way as [SecretKey::non_secure_erase].
+///
+/// This is used for points that are secret, such as the ECDH shared secret.
+#[cfg(any(feature = "sending", feature = "receiving"))]
+pub(crate) fn erase_public_key(key: &mut PublicKey) {
+    // SAFETY: the zeroed key is never used, it only overwrites a key that is about to be dropped
+    let zeroed = PublicKey::from(unsafe { ffi::PublicKey::new() });
+    ffi::non_secure_erase_impl(key, zeroed);
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::ffi::CPtr;
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+    use super::{erase_public_key, erase_secret_key};
+
+    #[test]
+    fn erase_secret_key_overwrites_the_key() {
+        let mut sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+
+        erase_secret_key(&mut sk);
+
+        assert_ne!(sk.secret_bytes(), [0x02; 32]);
+    }
+
+    #[test]
+    fn erase_public_key_overwrites_the_key() {
+        let secp = Secp256k1::signing_only();
+        let sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+        let pk = PublicKey::from_secret_key(&secp, &sk);
+
+        let mut erased = pk;
+        erase_public_key(&mut erased);
+
+        // the erased key is invalid, so it can only be compared by its bytes
+        let bytes = unsafe { (*erased.as_c_ptr()).underlying_bytes() };
+        assert_eq!(bytes, [0; 64]);
+    }
+}
@@ -26,6 +26,30 @@ sha256t_hash_newtype! {
     /// This hash type is for computing the shared secret.
     #[hash_newtype(forward)]
     pub(crate) struct SharedSecretHash(_);
+
+    pub(crate) struct DleqAuxTag = hash_str("BIP0374/aux");
+
+    /// BIP0374-tagged hash with tag \"aux\".
+    ///
+    /// This is used for masking the secret with the auxiliary randomness.
+    #[hash_newtype(forward)]
+    pub(crate) struct DleqAuxHash(_);
+
+    pub(crate) struct DleqNonceTag = hash_str("BIP0374/nonce");
+
+    /// BIP0374-tagged hash with tag \"nonce\".
+    ///
+    /// This is used for deriving the proof nonce.
+    #[hash_newtype(forward)]
+    pub(crate) struct DleqNonceHash(_);
+
+    pub(crate) struct DleqChallengeTag = hash_str("BIP0374/challenge");
+
+    /// BIP0374-tagged hash with tag \"challenge\".
+    ///
+    /// This is used for computing the proof challenge.
+    #[hash_newtype(forward)]
+    pub(crate) struct DleqChallengeHash(_);
 }
 
 impl InputsHash {
@@ -73,3 +97,50 @@ pub(crate) fn calculate_input_hash(
 ) -> Scalar {
     InputsHash::from_outpoint_and_A_sum(outpoints_data.min(), A_sum).to_scalar()
 }
+
+impl DleqAuxHash {
+    pub(crate) fn from_aux_rand(r: &[u8; 32]) -> DleqAuxHash {
+        let mut eng = DleqAuxHash::engine();
+        eng.input(r);
+        DleqAuxHash::from_engine(eng)
+    }
+}
+
+impl DleqNonceHash {
+    pub(crate) fn from_t_A_C_and_m(
+        t: &[u8; 32],
+        A: &PublicKey,
+        C: &PublicKey,
+        m: Option<&[u8; 32]>,
+    ) -> DleqNonceHash {
+        let mut eng = DleqNonceHash::engine();
+        eng.input(t);
+        eng.input(&A.serialize());
+        eng.input(&C.serialize());
+        if let Some(m) = m {
+            eng.input(m);
+        }
+        DleqNonceHash::from_engine(eng)
+    }
+}
+
+impl DleqChallengeHash {
+    pub(crate) fn from_points_and_m(
+        A: &PublicKey,
+        B: &PublicKey,
+        C: &PublicKey,
+        G: &PublicKey,
+        R1: &PublicKey,
+        R2: &PublicKey,
+        m: Option<&[u8; 32]>,
+    ) -> DleqChallengeHash {
+        let mut eng = DleqChallengeHash::engine();
+        for point in [A, B, C, G, R1, R2] {
+            eng.input(&point.serialize());
+        }
+        if let Some(m) = m {
+            eng.input(m);
+        }
+        DleqChallengeHash::from_engine(eng)
+    }
+}
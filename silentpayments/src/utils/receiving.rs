@@ -1,5 +1,6 @@
 //! Receiving utility functions.
 use crate::{
+    prelude::*,
     utils::{
         common::{NonEmptyArray, OutPoint, SharedSecret},
         script::{is_p2pkh, is_p2sh, is_p2wpkh},
@@ -100,6 +101,79 @@ pub fn calculate_tweak_data(
     Ok(A_sum.mul_tweak(&secp, &input_hash)?)
 }
 
+/// Calculate the tweak data of a [bitcoin::Transaction], given the outputs spent by its inputs.
+///
+/// This applies the input eligibility rules of BIP352: inputs that are not silent payment eligible are skipped,
+/// while the outpoints of all inputs are used for the input hash.
+///
+/// # Arguments
+///
+/// * `tx` - The transaction.
+/// * `prevouts` - The outputs spent by the transaction, in the same order as the inputs.
+///
+/// # Returns
+///
+/// This function returns the tweak data for this transaction, see [calculate_tweak_data].
+/// If the transaction is a coinbase, has no eligible inputs, or the eligible input keys sum up to the point at infinity, [None] is returned.
+///
+/// # Errors
+///
+/// This function will error if:
+///
+/// * The number of prevouts doesn't match the number of inputs.
+/// * An input spends a segwit output with a version higher than 1, in which case the transaction must be skipped.
+/// * The input data of an input is invalid, see [get_pubkey_from_input].
+#[cfg(feature = "bitcoin")]
+pub fn tweak_data_for_tx(
+    tx: &bitcoin::Transaction,
+    prevouts: &[bitcoin::TxOut],
+) -> Result<Option<PublicKey>> {
+    if tx.is_coinbase() {
+        return Ok(None);
+    }
+
+    if tx.input.len() != prevouts.len() {
+        return Err(Error::InvalidVin(format!(
+            "Expected {} prevouts, got {}",
+            tx.input.len(),
+            prevouts.len()
+        )));
+    }
+
+    let mut input_pub_keys: Vec<PublicKey> = vec![];
+    let mut outpoints_data: Vec<OutPoint> = vec![];
+
+    for (txin, prevout) in tx.input.iter().zip(prevouts) {
+        if let Some(version) = prevout.script_pubkey.witness_version() {
+            if version.to_num() > 1 {
+                return Err(Error::UnknownWitnessVersion(version.to_num()));
+            }
+        }
+
+        outpoints_data.push(txin.previous_output.into());
+
+        if let Some(pubkey) = get_pubkey_from_input(
+            txin.script_sig.as_bytes(),
+            &txin.witness.to_vec(),
+            prevout.script_pubkey.as_bytes(),
+        )? {
+            input_pub_keys.push(pubkey);
+        }
+    }
+
+    if input_pub_keys.is_empty() {
+        return Ok(None);
+    }
+
+    let input_pub_keys: Vec<&PublicKey> = input_pub_keys.iter().collect();
+
+    match calculate_tweak_data(&input_pub_keys, &outpoints_data) {
+        Ok(tweak_data) => Ok(Some(tweak_data)),
+        Err(Error::Secp256k1Error(secp256k1::Error::InvalidPublicKeySum)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 /// Calculate the shared secret of a transaction.
 ///
 /// # Arguments
@@ -1,4 +1,8 @@
 //! Sending utility functions.
+use core::fmt;
+
+use crate::dleq::{generate_dleq_proof, DleqProof};
+use crate::prelude::*;
 use crate::utils::common::{NonEmptyArray, OutPoint};
 use crate::{utils::common::SharedSecret, Error, Result};
 use secp256k1::constants::SECRET_KEY_SIZE;
@@ -8,9 +12,25 @@ use secp256k1::{PublicKey, Secp256k1, SecretKey};
 use super::hash::calculate_input_hash;
 
 /// Represents the sum of all eligible input private keys of a transaction, multiplied with the input hash.
-#[derive(Clone, Copy, Debug)]
+///
+/// This is secret data, so it doesn't implement `Copy`, and its [Debug](fmt::Debug) output is redacted.
+/// With the `zeroize` feature, the secret is wiped from memory when dropped.
+#[derive(Clone)]
 pub struct PartialSecret(pub(crate) SecretKey);
 
+impl fmt::Debug for PartialSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PartialSecret(<redacted>)")
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for PartialSecret {
+    fn drop(&mut self) {
+        crate::utils::erase::erase_secret_key(&mut self.0);
+    }
+}
+
 impl PartialSecret {
     /// Re-construct the partial secret from the inner bytes.
     pub fn from_slice(data: &[u8]) -> Result<Self> {
@@ -80,6 +100,67 @@ pub fn calculate_ecdh_shared_secret(
     SharedSecret(PublicKey::from_slice(&ss_bytes).expect("guaranteed to be a point on the curve"))
 }
 
+/// Calculate the ECDH share of a set of inputs for a recipient, as defined in [BIP375](https://github.com/bitcoin/bips/blob/master/bip-0375.mediawiki).
+///
+/// Unlike the shared secret, the ECDH share is not multiplied with the input hash.
+/// This allows a coordinator to verify it against the input public keys, without knowing the private keys.
+///
+/// # Arguments
+///
+/// * `input_keys` - A reference to a list of tuples, each tuple containing a [SecretKey] and [bool]. The [SecretKey] is the private key used in the input, and the [bool] indicates whether this was from a taproot address.
+/// * `B_scan` - The scan public key of the recipient.
+///
+/// # Returns
+///
+/// This function returns the ECDH share `a_sum * B_scan`.
+///
+/// # Errors
+///
+/// This function will error if:
+///
+/// * The input keys array is of length zero, or the summing results in an invalid key.
+pub fn calculate_ecdh_share(
+    input_keys: &[(SecretKey, bool)],
+    B_scan: &PublicKey,
+) -> Result<PublicKey> {
+    let a_sum = get_a_sum_secret_keys(input_keys)?;
+
+    let mut share_bytes = [0u8; 65];
+    share_bytes[0] = 0x04;
+
+    // Using `shared_secret_point` to ensure the multiplication is constant time
+    share_bytes[1..].copy_from_slice(&shared_secret_point(B_scan, &a_sum));
+
+    Ok(PublicKey::from_slice(&share_bytes).expect("guaranteed to be a point on the curve"))
+}
+
+/// Calculate a [BIP374](https://github.com/bitcoin/bips/blob/master/bip-0374.mediawiki) DLEQ proof for the ECDH share of a set of inputs, see [calculate_ecdh_share].
+///
+/// The proof can be verified with [verify_dleq_proof](crate::dleq::verify_dleq_proof), using the sum of the input public keys as `A`.
+/// Note that for taproot inputs, the public key with even y-coordinate should be used.
+///
+/// # Arguments
+///
+/// * `input_keys` - A reference to a list of tuples, each tuple containing a [SecretKey] and [bool]. The [SecretKey] is the private key used in the input, and the [bool] indicates whether this was from a taproot address.
+/// * `B_scan` - The scan public key of the recipient.
+/// * `aux_rand` - 32 bytes of fresh auxiliary randomness.
+///
+/// # Errors
+///
+/// This function will error if:
+///
+/// * The input keys array is of length zero, or the summing results in an invalid key.
+/// * Proof generation fails (extremely unlikely).
+pub fn calculate_ecdh_share_proof(
+    input_keys: &[(SecretKey, bool)],
+    B_scan: &PublicKey,
+    aux_rand: &[u8; 32],
+) -> Result<DleqProof> {
+    let a_sum = get_a_sum_secret_keys(input_keys)?;
+
+    generate_dleq_proof(&a_sum, B_scan, aux_rand, None)
+}
+
 fn get_a_sum_secret_keys(input: &[(SecretKey, bool)]) -> Result<SecretKey> {
     if input.is_empty() {
         return Err(Error::GenericError("No input provided".to_owned()));
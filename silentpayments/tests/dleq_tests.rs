@@ -0,0 +1,151 @@
+#![allow(non_snake_case)]
+//! Tests for the BIP374 DLEQ proofs.
+//!
+//! The vector files use the same csv format as the BIP374 test vectors.
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use silentpayments::dleq::{
+        generate_dleq_proof_with_generator, verify_dleq_proof, verify_dleq_proof_with_generator,
+        DleqProof,
+    };
+    use silentpayments::utils::sending::{calculate_ecdh_share, calculate_ecdh_share_proof};
+
+    const GENERATE_PROOF_VECTORS: &str = "tests/resources/test_vectors_generate_proof.csv";
+    const VERIFY_PROOF_VECTORS: &str = "tests/resources/test_vectors_verify_proof.csv";
+
+    fn read_csv(path: &str) -> Vec<Vec<String>> {
+        let contents = fs::read_to_string(path).unwrap();
+        contents
+            .lines()
+            .skip(1) // header
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split(',').map(|s| s.to_owned()).collect())
+            .collect()
+    }
+
+    fn parse_point(s: &str) -> Option<PublicKey> {
+        PublicKey::from_slice(&hex::decode(s).ok()?).ok()
+    }
+
+    fn parse_message(s: &str) -> Option<[u8; 32]> {
+        if s.is_empty() {
+            None
+        } else {
+            Some(hex::decode(s).unwrap().try_into().unwrap())
+        }
+    }
+
+    #[test]
+    fn generate_proof_vectors() {
+        for row in read_csv(GENERATE_PROOF_VECTORS) {
+            let (G, a, B, r, m, expected, comment) = (
+                &row[1], &row[2], &row[3], &row[4], &row[5], &row[6], &row[7],
+            );
+            println!("generate proof: {}", comment);
+
+            let G = parse_point(G).unwrap();
+            let r: [u8; 32] = hex::decode(r).unwrap().try_into().unwrap();
+            let m = parse_message(m);
+
+            // invalid scalars or points can't be represented by the secp256k1 types
+            let (Ok(a), Some(B)) = (
+                SecretKey::from_slice(&hex::decode(a).unwrap()),
+                parse_point(B),
+            ) else {
+                assert_eq!(expected, "INVALID");
+                continue;
+            };
+
+            match generate_dleq_proof_with_generator(&a, &B, &r, &G, m.as_ref()) {
+                Ok(proof) => assert_eq!(hex::encode(proof.to_bytes()), *expected),
+                Err(_) => assert_eq!(expected, "INVALID"),
+            }
+        }
+    }
+
+    #[test]
+    fn verify_proof_vectors() {
+        for row in read_csv(VERIFY_PROOF_VECTORS) {
+            let (G, A, B, C, proof, m, expected, comment) = (
+                &row[1], &row[2], &row[3], &row[4], &row[5], &row[6], &row[7], &row[8],
+            );
+            println!("verify proof: {}", comment);
+
+            let expected = match expected.as_str() {
+                "TRUE" => true,
+                "FALSE" => false,
+                _ => panic!("unexpected result {}", expected),
+            };
+
+            let G = parse_point(G).unwrap();
+            let proof = DleqProof::from_slice(&hex::decode(proof).unwrap()).unwrap();
+            let m = parse_message(m);
+
+            // points at infinity can't be represented, and never verify
+            let (Some(A), Some(B), Some(C)) = (parse_point(A), parse_point(B), parse_point(C))
+            else {
+                assert!(!expected);
+                continue;
+            };
+
+            assert_eq!(
+                verify_dleq_proof_with_generator(&A, &B, &C, &proof, &G, m.as_ref()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn ecdh_share_proof() {
+        let secp = Secp256k1::new();
+
+        let input_keys = vec![
+            (SecretKey::from_slice(&[0x01; 32]).unwrap(), true),
+            (SecretKey::from_slice(&[0x02; 32]).unwrap(), false),
+            (SecretKey::from_slice(&[0x03; 32]).unwrap(), true),
+        ];
+        let B_scan = SecretKey::from_slice(&[0x04; 32])
+            .unwrap()
+            .public_key(&secp);
+
+        // taproot inputs contribute their even-y public key
+        let input_pubkeys: Vec<PublicKey> = input_keys
+            .iter()
+            .map(|(sk, is_taproot)| {
+                if *is_taproot {
+                    let (xonly, _) = sk.x_only_public_key(&secp);
+                    xonly.public_key(secp256k1::Parity::Even)
+                } else {
+                    sk.public_key(&secp)
+                }
+            })
+            .collect();
+        let input_pubkey_refs: Vec<&PublicKey> = input_pubkeys.iter().collect();
+        let A_sum = PublicKey::combine_keys(&input_pubkey_refs).unwrap();
+
+        // aggregated share over all inputs
+        let share = calculate_ecdh_share(&input_keys, &B_scan).unwrap();
+        let proof = calculate_ecdh_share_proof(&input_keys, &B_scan, &[0xaa; 32]).unwrap();
+        assert!(verify_dleq_proof(&A_sum, &B_scan, &share, &proof, None));
+
+        // the proof doesn't hold for a different share
+        let wrong_share = calculate_ecdh_share(&input_keys[..2], &B_scan).unwrap();
+        assert!(!verify_dleq_proof(
+            &A_sum,
+            &B_scan,
+            &wrong_share,
+            &proof,
+            None
+        ));
+
+        // per-input shares
+        for (input_key, A) in input_keys.iter().zip(input_pubkeys.iter()) {
+            let share = calculate_ecdh_share(&[*input_key], &B_scan).unwrap();
+            let proof = calculate_ecdh_share_proof(&[*input_key], &B_scan, &[0xbb; 32]).unwrap();
+            assert!(verify_dleq_proof(A, &B_scan, &share, &proof, None));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+#![allow(non_snake_case)]
+//! Tests for the per-group recipient limit `K_max`.
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::{sha256, Hash, HashEngine};
+    use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+    use silentpayments::{
+        receiving::{Label, Receiver},
+        sending::generate_recipient_pubkeys,
+        utils::{
+            receiving::{calculate_ecdh_shared_secret, calculate_tweak_data},
+            sending::calculate_partial_secret,
+            OutPoint, K_MAX,
+        },
+        Error, Network, SilentPaymentAddress, SpVersion,
+    };
+
+    const OUTPOINT_TXID: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+
+    fn input_key() -> SecretKey {
+        SecretKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    fn outpoints() -> Vec<OutPoint> {
+        vec![OutPoint::from_txid_and_vout(OUTPOINT_TXID.to_owned(), 0).unwrap()]
+    }
+
+    fn receiver(b_scan: SecretKey, b_spend: SecretKey) -> Receiver {
+        let secp = Secp256k1::signing_only();
+        Receiver::new(
+            SpVersion::ZERO,
+            b_scan.public_key(&secp),
+            b_spend.public_key(&secp),
+            Label::new(b_scan, 0),
+            Network::Mainnet,
+        )
+        .unwrap()
+    }
+
+    fn send_to(recipients: Vec<SilentPaymentAddress>) -> silentpayments::Result<usize> {
+        let partial_secret = calculate_partial_secret(&[(input_key(), false)], &outpoints())?;
+        let outputs = generate_recipient_pubkeys(recipients, partial_secret)?;
+        Ok(outputs.values().map(|v| v.len()).sum())
+    }
+
+    // P_k = B_spend + hash_BIP0352/SharedSecret(ecdh_shared_secret || k)·G
+    fn output_key(ecdh_shared_secret: &PublicKey, B_spend: &PublicKey, k: u32) -> XOnlyPublicKey {
+        let secp = Secp256k1::new();
+
+        let tag = sha256::Hash::hash(b"BIP0352/SharedSecret");
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag.as_ref());
+        engine.input(tag.as_ref());
+        engine.input(&ecdh_shared_secret.serialize());
+        engine.input(&k.to_be_bytes());
+        let t_k = SecretKey::from_slice(sha256::Hash::from_engine(engine).as_ref()).unwrap();
+
+        B_spend
+            .combine(&t_k.public_key(&secp))
+            .unwrap()
+            .x_only_public_key()
+            .0
+    }
+
+    #[test]
+    fn sending_kmax_limit() {
+        let recipient = receiver(
+            SecretKey::from_slice(&[0x01; 32]).unwrap(),
+            SecretKey::from_slice(&[0x02; 32]).unwrap(),
+        );
+        let address = recipient.get_receiving_address();
+        let labeled_address = recipient.get_change_address();
+
+        // exactly K_max outputs for one scan key is allowed
+        let recipients = vec![address; K_MAX as usize];
+        assert_eq!(send_to(recipients).unwrap(), K_MAX as usize);
+
+        // one more is not
+        let recipients = vec![address; K_MAX as usize + 1];
+        assert!(matches!(
+            send_to(recipients),
+            Err(Error::TooManyRecipients(n)) if n == K_MAX as usize + 1
+        ));
+
+        // labeled addresses count towards the same group
+        let mut recipients = vec![address; K_MAX as usize];
+        recipients.push(labeled_address);
+        assert!(matches!(
+            send_to(recipients),
+            Err(Error::TooManyRecipients(_))
+        ));
+
+        // the limit is per scan key
+        let other = receiver(
+            SecretKey::from_slice(&[0x03; 32]).unwrap(),
+            SecretKey::from_slice(&[0x04; 32]).unwrap(),
+        );
+        let mut recipients = vec![address; K_MAX as usize];
+        recipients.push(other.get_receiving_address());
+        assert_eq!(send_to(recipients).unwrap(), K_MAX as usize + 1);
+    }
+
+    #[test]
+    fn receiving_kmax_limit() {
+        let secp = Secp256k1::new();
+
+        let b_scan = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let b_spend = SecretKey::from_slice(&[0x02; 32]).unwrap();
+        let B_spend = b_spend.public_key(&secp);
+        let recipient = receiver(b_scan, b_spend);
+
+        let A = input_key().public_key(&secp);
+        let tweak_data = calculate_tweak_data(&[&A], &outpoints()).unwrap();
+        let ecdh_shared_secret = calculate_ecdh_shared_secret(&tweak_data, &b_scan);
+        let ecdh_point = tweak_data.mul_tweak(&secp, &Scalar::from(b_scan)).unwrap();
+
+        // a malicious sender pays K_max + 1 outputs to the same recipient
+        let outputs: Vec<XOnlyPublicKey> = (0..=K_MAX)
+            .map(|k| output_key(&ecdh_point, &B_spend, k))
+            .collect();
+
+        // the outputs up to K_max match what a sender would have generated
+        let partial_secret =
+            calculate_partial_secret(&[(input_key(), false)], &outpoints()).unwrap();
+        let sent = generate_recipient_pubkeys(
+            vec![recipient.get_receiving_address(); K_MAX as usize],
+            partial_secret,
+        )
+        .unwrap();
+        assert_eq!(
+            sent[&recipient.get_receiving_address()],
+            outputs[..K_MAX as usize]
+        );
+
+        let found = recipient
+            .scan_transaction(&ecdh_shared_secret, &outputs)
+            .unwrap();
+        let found = &found[&None];
+
+        // scanning stops after K_max outputs
+        assert_eq!(found.len(), K_MAX as usize);
+        assert!(!found.contains_key(&outputs[K_MAX as usize]));
+    }
+}
@@ -39,6 +39,121 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "bitcoin")]
+    #[test]
+    fn test_tweak_data_for_tx_with_test_vectors() {
+        use bitcoin::{
+            absolute::LockTime, transaction::Version, Amount, ScriptBuf, Sequence, Transaction,
+            TxIn, TxOut, Txid, Witness,
+        };
+        use silentpayments::{utils::receiving::tweak_data_for_tx, Error};
+
+        let testdata = utils::read_file();
+
+        let secp = Secp256k1::new();
+
+        for test_case in testdata {
+            println!("test: {}", test_case.comment);
+
+            for receivingtest in test_case.receiving {
+                let given = receivingtest.given;
+                let expected = receivingtest.expected;
+
+                let mut input = vec![];
+                let mut prevouts = vec![];
+                let mut input_pub_keys = vec![];
+                for vin in given.vin {
+                    let script_sig = hex::decode(&vin.scriptSig).unwrap();
+                    let txinwitness_bytes = hex::decode(&vin.txinwitness).unwrap();
+                    let mut cursor = Cursor::new(&txinwitness_bytes);
+                    let txinwitness = deser_string_vector(&mut cursor).unwrap();
+                    let script_pub_key = hex::decode(&vin.prevout.scriptPubKey.hex).unwrap();
+
+                    if let Ok(Some(pubkey)) =
+                        get_pubkey_from_input(&script_sig, &txinwitness, &script_pub_key)
+                    {
+                        input_pub_keys.push(pubkey);
+                    }
+
+                    input.push(TxIn {
+                        previous_output: bitcoin::OutPoint::new(
+                            Txid::from_str(&vin.txid).unwrap(),
+                            vin.vout,
+                        ),
+                        script_sig: ScriptBuf::from_bytes(script_sig),
+                        sequence: Sequence::MAX,
+                        witness: Witness::from_slice(&txinwitness),
+                    });
+                    prevouts.push(TxOut {
+                        value: Amount::ZERO,
+                        script_pubkey: ScriptBuf::from_bytes(script_pub_key),
+                    });
+                }
+
+                let tx = Transaction {
+                    version: Version::TWO,
+                    lock_time: LockTime::ZERO,
+                    input,
+                    output: vec![],
+                };
+
+                let tweak_data = tweak_data_for_tx(&tx, &prevouts).unwrap();
+                assert_eq!(tweak_data.is_some(), !input_pub_keys.is_empty());
+
+                // the tweak data must find the expected outputs of the vector
+                if let Some(tweak_data) = tweak_data {
+                    let b_scan = SecretKey::from_str(&given.key_material.scan_priv_key).unwrap();
+                    let b_spend = SecretKey::from_str(&given.key_material.spend_priv_key).unwrap();
+
+                    let mut sp_receiver = Receiver::new(
+                        silentpayments::SpVersion::ZERO,
+                        b_scan.public_key(&secp),
+                        b_spend.public_key(&secp),
+                        Label::new(b_scan, 0),
+                        NETWORK,
+                    )
+                    .unwrap();
+                    for label_int in &given.labels {
+                        sp_receiver
+                            .add_label(Label::new(b_scan, *label_int))
+                            .unwrap();
+                    }
+
+                    let ecdh_shared_secret = calculate_ecdh_shared_secret(&tweak_data, &b_scan);
+                    let found: HashSet<String> = sp_receiver
+                        .scan_transaction(
+                            &ecdh_shared_secret,
+                            &decode_outputs_to_check(&given.outputs),
+                        )
+                        .unwrap()
+                        .into_values()
+                        .flat_map(|outputs| outputs.into_keys())
+                        .map(|key| key.to_string())
+                        .collect();
+
+                    let expected_outputs: HashSet<String> = expected
+                        .outputs
+                        .iter()
+                        .map(|output| output.pub_key.clone())
+                        .collect();
+                    assert_eq!(found, expected_outputs);
+                }
+
+                // all prevouts must be given
+                assert!(tweak_data_for_tx(&tx, &prevouts[1..]).is_err());
+
+                // spending a segwit output with an unknown version invalidates the transaction
+                let mut unknown_version = vec![0x52, 0x20];
+                unknown_version.extend_from_slice(&[0xaa; 32]);
+                prevouts[0].script_pubkey = ScriptBuf::from_bytes(unknown_version);
+                assert!(matches!(
+                    tweak_data_for_tx(&tx, &prevouts),
+                    Err(Error::UnknownWitnessVersion(2))
+                ));
+            }
+        }
+    }
+
     fn process_test_case(test_case: TestData) {
         println!("test: {}", test_case.comment);
         let secp = Secp256k1::new();
@@ -16,8 +16,27 @@ pub struct UtxoData {
     pub spent: bool,
 }
 
+/// An unconfirmed transaction that may contain silent payment outputs.
+pub struct MempoolTxData {
+    pub txid: Txid,
+    pub tweak: PublicKey,
+    /// The taproot outputs of the transaction.
+    pub outputs: Vec<UtxoData>,
+}
+
 pub struct SpentIndexData {
     pub data: Vec<Vec<u8>>,
+    /// The inputs that spend the outputs of `data`, in the same order, so there is one for every entry of `data`.
+    /// Empty if the backend doesn't know the spending transactions.
+    pub spending_txs: Vec<SpendingTx>,
+}
+
+/// The input of a transaction that spends an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpendingTx {
+    pub txid: Txid,
+    /// The index of the spending input in the transaction.
+    pub vin: u32,
 }
 
 pub struct FilterData {
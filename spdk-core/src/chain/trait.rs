@@ -1,11 +1,11 @@
 use std::{ops::RangeInclusive, pin::Pin};
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use async_trait::async_trait;
-use bitcoin::{absolute::Height, Amount};
-use futures::Stream;
+use bitcoin::{absolute::Height, Amount, BlockHash};
+use futures::{Stream, StreamExt};
 
-use super::structs::{BlockData, SpentIndexData, UtxoData};
+use super::structs::{BlockData, MempoolTxData, SpentIndexData, UtxoData};
 
 #[async_trait]
 pub trait ChainBackend {
@@ -19,4 +19,37 @@ pub trait ChainBackend {
     async fn spent_index(&self, block_height: Height) -> Result<SpentIndexData>;
 
     async fn utxos(&self, block_height: Height) -> Result<Vec<UtxoData>>;
+
+    /// The height of the tip of the chain.
+    async fn block_height(&self) -> Result<Height>;
+
+    /// The transactions in the mempool that may contain silent payments.
+    ///
+    /// Outputs below `dust_limit` can be left out.
+    /// This is optional, backends without access to the mempool return an error.
+    async fn mempool(&self, _dust_limit: Amount) -> Result<Vec<MempoolTxData>>
+    where
+        Self: Sync,
+    {
+        Err(Error::msg("The mempool is not supported by this backend"))
+    }
+
+    /// The hash of the block at the given height, used to detect reorgs.
+    ///
+    /// By default this fetches the block data of the block, backends should override this if there is a cheaper way.
+    async fn block_hash(&self, block_height: Height) -> Result<BlockHash>
+    where
+        Self: Sync,
+    {
+        let mut block_data =
+            self.get_block_data_for_range(block_height..=block_height, Amount::ZERO, false);
+
+        match block_data.next().await {
+            Some(block_data) => Ok(block_data?.blkhash),
+            None => Err(Error::msg(format!(
+                "No block data for height {}",
+                block_height
+            ))),
+        }
+    }
 }
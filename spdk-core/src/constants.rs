@@ -11,3 +11,11 @@ pub const NUMS: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9a
 pub const DUST_THRESHOLD: bitcoin::Amount = bitcoin::Amount::from_sat(546);
 
 pub const DATA_CARRIER_SIZE: usize = 205;
+
+// BIP375 key types for silent payment fields
+pub const PSBT_GLOBAL_SP_ECDH_SHARE: u8 = 0x07;
+pub const PSBT_GLOBAL_SP_DLEQ: u8 = 0x08;
+pub const PSBT_IN_SP_ECDH_SHARE: u8 = 0x1d;
+pub const PSBT_IN_SP_DLEQ: u8 = 0x1e;
+pub const PSBT_OUT_SP_V0_INFO: u8 = 0x09;
+pub const PSBT_OUT_SP_V0_LABEL: u8 = 0x0a;
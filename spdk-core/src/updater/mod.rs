@@ -1,5 +1,5 @@
 mod structs;
 mod r#trait;
 
-pub use r#trait::Updater;
+pub use r#trait::{AsyncUpdater, Updater};
 pub use structs::*;
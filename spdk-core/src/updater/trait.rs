@@ -1,10 +1,12 @@
 use std::collections::{HashMap, HashSet};
 
+use async_trait::async_trait;
 use bitcoin::{absolute::Height, BlockHash, OutPoint};
 
 use anyhow::Result;
 
 use super::DiscoveredOutput;
+use crate::chain::SpendingTx;
 
 pub trait Updater {
     fn record_block_scan_result(
@@ -14,4 +16,98 @@ pub trait Updater {
         discovered_inputs: HashSet<OutPoint>,
         discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
     ) -> Result<()>;
+
+    /// Called after a reorg, with the last block that is still part of the chain.
+    ///
+    /// The results of all the blocks above `blkheight` must be undone:
+    /// the outputs discovered in these blocks are removed, and the inputs discovered in these blocks are unspent again.
+    /// The blocks are scanned again afterwards.
+    fn rollback_to_block(&mut self, blkheight: Height, blkhash: BlockHash) -> Result<()>;
+
+    /// Called when all the blocks up to `blkheight` are processed, also for blocks without any results.
+    ///
+    /// This can be stored as a checkpoint, a scan that is interrupted can resume from the block after it.
+    /// After a rollback, this is called with the last block that is still part of the chain.
+    fn record_progress(&mut self, _blkheight: Height, _blkhash: BlockHash) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after [record_block_scan_result](Self::record_block_scan_result),
+    /// with the transactions that spend the discovered inputs of the block.
+    ///
+    /// Only the inputs whose spending transaction is known to the backend are included,
+    /// see [SpentIndexData::spending_txs](crate::chain::SpentIndexData::spending_txs).
+    /// This is not called if there are none. The spends are undone by [rollback_to_block](Self::rollback_to_block).
+    fn record_spending_txs(
+        &mut self,
+        _blkheight: Height,
+        _spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called after scanning the mempool, with the changes to the pending outputs.
+    ///
+    /// `new_outputs` are found in unconfirmed transactions, these are pending until they are confirmed.
+    /// `dropped_outputs` were pending, but their transactions left the mempool without being confirmed.
+    /// A pending output that gets confirmed is passed to [record_block_scan_result](Self::record_block_scan_result),
+    /// and is no longer pending.
+    fn record_mempool_scan_result(
+        &mut self,
+        _new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+        _dropped_outputs: HashSet<OutPoint>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The async variant of [Updater], for wallets that store the results with async I/O,
+/// e.g. in a database or by sending them over a channel.
+///
+/// The scanner awaits each call before it continues with the next block.
+#[async_trait]
+pub trait AsyncUpdater {
+    /// See [Updater::record_block_scan_result].
+    async fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        blkhash: BlockHash,
+        discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()>;
+
+    /// See [Updater::rollback_to_block].
+    async fn rollback_to_block(&mut self, blkheight: Height, blkhash: BlockHash) -> Result<()>;
+
+    /// See [Updater::record_progress].
+    async fn record_progress(&mut self, _blkheight: Height, _blkhash: BlockHash) -> Result<()>
+    where
+        Self: Send,
+    {
+        Ok(())
+    }
+
+    /// See [Updater::record_spending_txs].
+    async fn record_spending_txs(
+        &mut self,
+        _blkheight: Height,
+        _spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()>
+    where
+        Self: Send,
+    {
+        Ok(())
+    }
+
+    /// See [Updater::record_mempool_scan_result].
+    async fn record_mempool_scan_result(
+        &mut self,
+        _new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+        _dropped_outputs: HashSet<OutPoint>,
+    ) -> Result<()>
+    where
+        Self: Send,
+    {
+        Ok(())
+    }
 }
@@ -0,0 +1,82 @@
+use std::{
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{Arc, RwLock, RwLockReadGuard},
+};
+
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use bitcoin::{Amount, BlockHash, absolute::Height};
+use futures::{Stream, stream};
+
+use spdk_core::chain::{BlockData, ChainBackend, SpentIndexData, UtxoData};
+
+use crate::TweakIndex;
+
+/// A [ChainBackend] that serves the data of a [TweakIndex].
+///
+/// The index is shared, so blocks can be added to it while the backend is in use.
+#[derive(Clone, Default)]
+pub struct IndexBackend {
+    index: Arc<RwLock<TweakIndex>>,
+}
+
+impl IndexBackend {
+    pub fn new(index: Arc<RwLock<TweakIndex>>) -> Self {
+        Self { index }
+    }
+
+    /// The index that this backend serves from.
+    pub fn index(&self) -> Arc<RwLock<TweakIndex>> {
+        self.index.clone()
+    }
+
+    fn read_index(index: &RwLock<TweakIndex>) -> Result<RwLockReadGuard<'_, TweakIndex>> {
+        index
+            .read()
+            .map_err(|_| Error::msg("Tweak index lock is poisoned"))
+    }
+}
+
+#[async_trait]
+impl ChainBackend for IndexBackend {
+    fn get_block_data_for_range(
+        &self,
+        range: RangeInclusive<Height>,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Pin<Box<dyn Stream<Item = Result<BlockData>> + Send>> {
+        let index = self.index.clone();
+
+        // convert range to u32 since Height does not implement Step
+        let range = range.start().to_consensus_u32()..=range.end().to_consensus_u32();
+
+        let res = stream::iter(range.map(move |n| {
+            let blkheight = Height::from_consensus(n)?;
+            Self::read_index(&index)?.block_data(blkheight, dust_limit, with_cutthrough)
+        }));
+
+        Box::pin(res)
+    }
+
+    async fn spent_index(&self, block_height: Height) -> Result<SpentIndexData> {
+        Self::read_index(&self.index)?.spent_index(block_height)
+    }
+
+    async fn utxos(&self, block_height: Height) -> Result<Vec<UtxoData>> {
+        Self::read_index(&self.index)?.utxos(block_height)
+    }
+
+    async fn block_height(&self) -> Result<Height> {
+        Self::read_index(&self.index)?
+            .tip()
+            .map(|(height, _)| height)
+            .ok_or_else(|| Error::msg("The tweak index is empty"))
+    }
+
+    async fn block_hash(&self, block_height: Height) -> Result<BlockHash> {
+        Ok(Self::read_index(&self.index)?
+            .get_block(block_height)?
+            .hash())
+    }
+}
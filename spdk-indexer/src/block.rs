@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use anyhow::{Error, Result};
+use bitcoin::{
+    Amount, Block, BlockHash, OutPoint, ScriptBuf, Transaction, TxOut, Txid, absolute::Height,
+    secp256k1::PublicKey,
+};
+use silentpayments::Error as SpError;
+use silentpayments::utils::receiving::tweak_data_for_tx;
+
+use spdk_core::chain::{
+    BlockData, FilterData, MempoolTxData, SpendingTx, SpentIndexData, UtxoData,
+};
+
+use crate::filter::{build_filter, spent_index_hash};
+
+struct IndexedOutput {
+    vout: u32,
+    value: Amount,
+    script_pubkey: ScriptBuf,
+    spent: bool,
+}
+
+/// A transaction that is eligible for silent payments, with its taproot outputs.
+struct IndexedTx {
+    txid: Txid,
+    tweak: PublicKey,
+    outputs: Vec<IndexedOutput>,
+}
+
+/// The tweak and taproot outputs of an unconfirmed transaction.
+///
+/// `prevouts` are the outputs spent by the inputs of `tx`, in the same order.
+/// Returns None if the transaction is not eligible for silent payments,
+/// or if none of its taproot outputs is at least `dust_limit`, like [BlockIndex::tweaks].
+/// Fails if the inputs don't match the prevouts.
+pub fn mempool_tx_data(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    dust_limit: Amount,
+) -> Result<Option<MempoolTxData>> {
+    let Some(indexed) = BlockIndex::index_tx(tx, prevouts)? else {
+        return Ok(None);
+    };
+
+    if !indexed
+        .outputs
+        .iter()
+        .any(|output| output.value >= dust_limit)
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(MempoolTxData {
+        txid: indexed.txid,
+        tweak: indexed.tweak,
+        outputs: indexed
+            .outputs
+            .into_iter()
+            .map(|output| UtxoData {
+                txid: indexed.txid,
+                vout: output.vout,
+                value: output.value,
+                scriptpubkey: output.script_pubkey,
+                spent: false,
+            })
+            .collect(),
+    }))
+}
+
+/// The BIP352 light client data of a single block.
+///
+/// This keeps the tweaks and taproot outputs of the transactions that are eligible for silent payments,
+/// together with the new utxo filter, spent filter and spent index, see [crate::filter].
+/// A block on its own only knows about outputs that are spent within the same block,
+/// [TweakIndex](crate::TweakIndex) also marks the outputs that are spent by later blocks.
+pub struct BlockIndex {
+    height: Height,
+    hash: BlockHash,
+    txs: Vec<IndexedTx>,
+    spent_outpoints: Vec<OutPoint>,
+    // the inputs that spend `spent_outpoints`, in the same order
+    spending_txs: Vec<SpendingTx>,
+    new_utxo_filter: Vec<u8>,
+    spent_filter: Vec<u8>,
+    spent_index: Vec<Vec<u8>>,
+}
+
+impl BlockIndex {
+    /// Compute the light client data of a block.
+    ///
+    /// `prevouts` must contain the outputs spent by the inputs of the block.
+    /// Outputs that are created and spent in the same block don't need to be included.
+    ///
+    /// Transactions that can't be used for silent payments, e.g. because they spend a segwit output
+    /// with a version higher than 1, are skipped as described in BIP352.
+    /// Fails if the inputs of a transaction don't match their prevouts.
+    pub fn new(height: Height, block: &Block, prevouts: &HashMap<OutPoint, TxOut>) -> Result<Self> {
+        let hash = block.block_hash();
+
+        // outputs created in this block can be spent by later transactions in the same block
+        let mut block_outputs: HashMap<OutPoint, &TxOut> = HashMap::new();
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                block_outputs.insert(OutPoint::new(txid, vout as u32), output);
+            }
+        }
+
+        let mut txs = vec![];
+        let mut new_utxo_keys = vec![];
+        let mut spent_outpoints = vec![];
+        let mut spending_txs = vec![];
+
+        for tx in &block.txdata {
+            for output in &tx.output {
+                if output.script_pubkey.is_p2tr() {
+                    new_utxo_keys.push(output.script_pubkey.as_bytes()[2..].to_vec());
+                }
+            }
+
+            if tx.is_coinbase() {
+                continue;
+            }
+
+            let tx_prevouts = tx
+                .input
+                .iter()
+                .map(|txin| {
+                    let outpoint = txin.previous_output;
+                    block_outputs
+                        .get(&outpoint)
+                        .copied()
+                        .or_else(|| prevouts.get(&outpoint))
+                        .cloned()
+                        .ok_or_else(|| Error::msg(format!("Missing prevout for {}", outpoint)))
+                })
+                .collect::<Result<Vec<TxOut>>>()?;
+
+            let txid = tx.compute_txid();
+            for (vin, (txin, prevout)) in tx.input.iter().zip(&tx_prevouts).enumerate() {
+                if prevout.script_pubkey.is_p2tr() {
+                    spent_outpoints.push(txin.previous_output);
+                    spending_txs.push(SpendingTx {
+                        txid,
+                        vin: vin as u32,
+                    });
+                }
+            }
+
+            if let Some(indexed) = Self::index_tx(tx, &tx_prevouts)? {
+                txs.push(indexed);
+            }
+        }
+
+        let spent_index: Vec<Vec<u8>> = spent_outpoints
+            .iter()
+            .map(|outpoint| spent_index_hash(outpoint, &hash).to_vec())
+            .collect();
+
+        let mut res = Self {
+            height,
+            hash,
+            new_utxo_filter: build_filter(&hash, &new_utxo_keys)?,
+            spent_filter: build_filter(&hash, &spent_index)?,
+            spent_index,
+            spent_outpoints,
+            spending_txs,
+            txs,
+        };
+
+        for outpoint in res.spent_outpoints.clone() {
+            res.set_spent(&outpoint, true);
+        }
+
+        Ok(res)
+    }
+
+    fn index_tx(tx: &Transaction, prevouts: &[TxOut]) -> Result<Option<IndexedTx>> {
+        let outputs: Vec<IndexedOutput> = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.script_pubkey.is_p2tr())
+            .map(|(vout, output)| IndexedOutput {
+                vout: vout as u32,
+                value: output.value,
+                script_pubkey: output.script_pubkey.clone(),
+                spent: false,
+            })
+            .collect();
+
+        if outputs.is_empty() {
+            return Ok(None);
+        }
+
+        let tweak = match tweak_data_for_tx(tx, prevouts) {
+            Ok(Some(tweak)) => tweak,
+            Ok(None) => return Ok(None),
+            // the transaction must be skipped
+            Err(SpError::UnknownWitnessVersion(_)) => return Ok(None),
+            Err(e) => {
+                return Err(Error::msg(format!(
+                    "Invalid input data in transaction {}: {}",
+                    tx.compute_txid(),
+                    e
+                )));
+            }
+        };
+
+        Ok(Some(IndexedTx {
+            txid: tx.compute_txid(),
+            tweak,
+            outputs,
+        }))
+    }
+
+    /// Mark an output of this block as spent or unspent, returns false if it is not one of the indexed outputs.
+    pub(crate) fn set_spent(&mut self, outpoint: &OutPoint, spent: bool) -> bool {
+        let output = self
+            .txs
+            .iter_mut()
+            .filter(|tx| tx.txid == outpoint.txid)
+            .flat_map(|tx| tx.outputs.iter_mut())
+            .find(|output| output.vout == outpoint.vout);
+
+        match output {
+            Some(output) => {
+                output.spent = spent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn height(&self) -> Height {
+        self.height
+    }
+
+    pub fn hash(&self) -> BlockHash {
+        self.hash
+    }
+
+    /// The taproot outputs that are spent by this block.
+    pub fn spent_outpoints(&self) -> &[OutPoint] {
+        &self.spent_outpoints
+    }
+
+    /// The outpoints of the indexed outputs that are not known to be spent.
+    pub(crate) fn unspent_outpoints(&self) -> impl Iterator<Item = OutPoint> + '_ {
+        self.txs.iter().flat_map(|tx| {
+            tx.outputs
+                .iter()
+                .filter(|output| !output.spent)
+                .map(|output| OutPoint::new(tx.txid, output.vout))
+        })
+    }
+
+    /// The tweaks of this block.
+    ///
+    /// Only transactions with a taproot output of at least `dust_limit` are included.
+    /// If `with_cutthrough` is set, outputs that are known to be spent are ignored,
+    /// so transactions without any unspent taproot output are left out.
+    pub fn tweaks(&self, dust_limit: Amount, with_cutthrough: bool) -> Vec<PublicKey> {
+        self.txs
+            .iter()
+            .filter(|tx| {
+                tx.outputs
+                    .iter()
+                    .any(|output| output.value >= dust_limit && !(with_cutthrough && output.spent))
+            })
+            .map(|tx| tx.tweak)
+            .collect()
+    }
+
+    /// The data that is needed to check if this block is relevant, see [BlockData].
+    pub fn block_data(&self, dust_limit: Amount, with_cutthrough: bool) -> BlockData {
+        BlockData {
+            blkheight: self.height,
+            blkhash: self.hash,
+            tweaks: self.tweaks(dust_limit, with_cutthrough),
+            new_utxo_filter: FilterData {
+                block_hash: self.hash,
+                data: self.new_utxo_filter.clone(),
+            },
+            spent_filter: FilterData {
+                block_hash: self.hash,
+                data: self.spent_filter.clone(),
+            },
+        }
+    }
+
+    /// The taproot outputs of the eligible transactions in this block.
+    pub fn utxos(&self) -> Vec<UtxoData> {
+        self.txs
+            .iter()
+            .flat_map(|tx| {
+                tx.outputs.iter().map(|output| UtxoData {
+                    txid: tx.txid,
+                    vout: output.vout,
+                    value: output.value,
+                    scriptpubkey: output.script_pubkey.clone(),
+                    spent: output.spent,
+                })
+            })
+            .collect()
+    }
+
+    /// The hashes of the taproot outputs spent in this block, see [spent_index_hash],
+    /// with the inputs that spend them.
+    pub fn spent_index(&self) -> SpentIndexData {
+        SpentIndexData {
+            data: self.spent_index.clone(),
+            spending_txs: self.spending_txs.clone(),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! The BIP158-style filters that are used by [SpScanner](https://docs.rs/spdk-wallet) to check if a block is relevant.
+//!
+//! Both filters use the basic filter parameters and are keyed with the block hash.
+//! The new utxo filter contains the x-only output keys of the taproot outputs in a block,
+//! the spent filter contains the [spent_index_hash] of every taproot output that is spent in a block.
+use anyhow::Result;
+use bitcoin::{
+    BlockHash, OutPoint,
+    bip158::GcsFilterWriter,
+    hashes::{Hash, sha256},
+};
+
+const P: u8 = 19;
+const M: u64 = 784931;
+
+/// Build a filter over the given elements, keyed with the block hash.
+///
+/// The result is the number of elements as a compact size, followed by the encoded set,
+/// which can be read back with [BlockFilter](bitcoin::bip158::BlockFilter).
+pub fn build_filter<I, E>(block_hash: &BlockHash, elements: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = E>,
+    E: AsRef<[u8]>,
+{
+    let key = block_hash.as_byte_array();
+    let k0 = u64::from_le_bytes(key[0..8].try_into()?);
+    let k1 = u64::from_le_bytes(key[8..16].try_into()?);
+
+    let mut data = vec![];
+    let mut writer = GcsFilterWriter::new(&mut data, k0, k1, M, P);
+    for element in elements {
+        writer.add_element(element.as_ref());
+    }
+    writer.finish()?;
+
+    Ok(data)
+}
+
+/// The 8-byte hash that identifies a spent output in the spent filter and spent index.
+///
+/// This is the first 8 bytes of `sha256(txid || vout || block_hash)`, where `vout` is little-endian
+/// and `block_hash` is the hash of the block that spends the output.
+pub fn spent_index_hash(outpoint: &OutPoint, block_hash: &BlockHash) -> [u8; 8] {
+    let mut arr = [0u8; 68];
+    arr[..32].copy_from_slice(&outpoint.txid.to_raw_hash().to_byte_array());
+    arr[32..36].copy_from_slice(&outpoint.vout.to_le_bytes());
+    arr[36..].copy_from_slice(&block_hash.to_byte_array());
+    let hash = sha256::Hash::hash(&arr);
+
+    let mut res = [0u8; 8];
+    res.copy_from_slice(&hash[..8]);
+    res
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Error, Result};
+use bitcoin::{Amount, Block, BlockHash, OutPoint, TxOut, absolute::Height, secp256k1::PublicKey};
+
+use spdk_core::chain::{BlockData, SpentIndexData, UtxoData};
+
+use crate::BlockIndex;
+
+/// An in-memory index of the BIP352 light client data for a chain of blocks.
+///
+/// Blocks are added in order with [TweakIndex::add_block], see [BlockIndex] for the data that is kept for each block.
+/// After a reorg, the stale blocks are removed from the tip with [TweakIndex::disconnect_block].
+/// When a later block spends an indexed output, the output is marked as spent.
+/// This is used for the cut-through variant of the tweaks.
+#[derive(Default)]
+pub struct TweakIndex {
+    blocks: BTreeMap<Height, BlockIndex>,
+    unspent: HashMap<OutPoint, Height>,
+}
+
+impl TweakIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a block to the index.
+    ///
+    /// Unless the index is empty, the block must extend the current tip.
+    /// See [BlockIndex::new] for the prevouts that are needed.
+    pub fn add_block(
+        &mut self,
+        height: Height,
+        block: &Block,
+        prevouts: &HashMap<OutPoint, TxOut>,
+    ) -> Result<()> {
+        if let Some((tip_height, tip)) = self.blocks.last_key_value() {
+            let expected = tip_height.to_consensus_u32() + 1;
+            if height.to_consensus_u32() != expected {
+                return Err(Error::msg(format!(
+                    "Expected block at height {}, got {}",
+                    expected, height
+                )));
+            }
+            if block.header.prev_blockhash != tip.hash() {
+                return Err(Error::msg(format!(
+                    "Block {} does not extend the tip {}",
+                    block.block_hash(),
+                    tip.hash()
+                )));
+            }
+        }
+
+        let new_block = BlockIndex::new(height, block, prevouts)?;
+
+        for outpoint in new_block.spent_outpoints() {
+            if let Some(h) = self.unspent.remove(outpoint)
+                && let Some(block) = self.blocks.get_mut(&h)
+            {
+                block.set_spent(outpoint, true);
+            }
+        }
+
+        self.unspent.extend(
+            new_block
+                .unspent_outpoints()
+                .map(|outpoint| (outpoint, height)),
+        );
+        self.blocks.insert(height, new_block);
+
+        Ok(())
+    }
+
+    /// Remove the last indexed block, e.g. when it was reorged out.
+    ///
+    /// The outputs that it spent are unspent again. Returns the removed block, or None if the index is empty.
+    pub fn disconnect_block(&mut self) -> Option<BlockIndex> {
+        let (_, block) = self.blocks.pop_last()?;
+
+        for outpoint in block.unspent_outpoints() {
+            self.unspent.remove(&outpoint);
+        }
+
+        for outpoint in block.spent_outpoints() {
+            // outputs from before the index, or from the same block, are not found
+            if let Some(h) = self
+                .blocks
+                .iter_mut()
+                .rev()
+                .find_map(|(h, prev)| prev.set_spent(outpoint, false).then_some(*h))
+            {
+                self.unspent.insert(*outpoint, h);
+            }
+        }
+
+        Some(block)
+    }
+
+    /// The height and hash of the last indexed block.
+    pub fn tip(&self) -> Option<(Height, BlockHash)> {
+        self.blocks
+            .last_key_value()
+            .map(|(height, block)| (*height, block.hash()))
+    }
+
+    /// The hash of the indexed block at the given height.
+    pub fn block_hash(&self, height: Height) -> Option<BlockHash> {
+        self.blocks.get(&height).map(|block| block.hash())
+    }
+
+    /// The indexed block at the given height.
+    pub fn get_block(&self, height: Height) -> Result<&BlockIndex> {
+        self.blocks
+            .get(&height)
+            .ok_or_else(|| Error::msg(format!("Block {} is not indexed", height)))
+    }
+
+    /// The tweaks of a block, see [BlockIndex::tweaks].
+    pub fn tweaks(
+        &self,
+        height: Height,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<Vec<PublicKey>> {
+        Ok(self.get_block(height)?.tweaks(dust_limit, with_cutthrough))
+    }
+
+    /// The data that is needed to check if a block is relevant, see [BlockData].
+    pub fn block_data(
+        &self,
+        height: Height,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<BlockData> {
+        Ok(self
+            .get_block(height)?
+            .block_data(dust_limit, with_cutthrough))
+    }
+
+    /// The taproot outputs of the eligible transactions in a block.
+    pub fn utxos(&self, height: Height) -> Result<Vec<UtxoData>> {
+        Ok(self.get_block(height)?.utxos())
+    }
+
+    /// The hashes of the taproot outputs spent in a block.
+    pub fn spent_index(&self, height: Height) -> Result<SpentIndexData> {
+        Ok(self.get_block(height)?.spent_index())
+    }
+}
@@ -0,0 +1,15 @@
+//! Build the BIP352 light client data from raw blocks.
+//!
+//! A [BlockIndex] computes the tweaks, filters and spent index of a block, given the outputs that its inputs spend.
+//! A [TweakIndex] keeps this data for a chain of blocks, and tracks which outputs are spent by later blocks.
+//! [IndexBackend] serves this data as a [ChainBackend](spdk_core::chain::ChainBackend),
+//! so a wallet can scan without relying on a third-party server.
+//! Unconfirmed transactions can be indexed with [mempool_tx_data].
+mod backend;
+mod block;
+pub mod filter;
+mod index;
+
+pub use backend::IndexBackend;
+pub use block::{BlockIndex, mempool_tx_data};
+pub use index::TweakIndex;
@@ -0,0 +1,585 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::Result;
+use backend_blindbit_v1::api_structs::{FilterResponse, SpentIndexResponse};
+use bitcoin::absolute::{Height, LockTime};
+use bitcoin::bip158::BlockFilter;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf, Transaction, TxIn,
+    TxMerkleNode, TxOut, WPubkeyHash, Witness, transaction,
+};
+use silentpayments::sending::generate_recipient_pubkeys;
+use silentpayments::utils::receiving::tweak_data_for_tx;
+use silentpayments::utils::sending::calculate_partial_secret;
+use spdk_core::chain::{ChainBackend, SpendingTx};
+use spdk_core::updater::{DiscoveredOutput, Updater};
+use spdk_indexer::filter::{build_filter, spent_index_hash};
+use spdk_indexer::{IndexBackend, TweakIndex};
+use spdk_wallet::client::{SpClient, SpendKey};
+use spdk_wallet::scanner::{CancellationToken, SpScanner};
+
+const DUST_LIMIT: Amount = Amount::from_sat(546);
+const START_HEIGHT: u32 = 100;
+
+fn height(n: u32) -> Height {
+    Height::from_consensus(n).unwrap()
+}
+
+fn new_block(prev_blockhash: BlockHash, txdata: Vec<Transaction>) -> Block {
+    let mut block = Block {
+        header: Header {
+            version: Version::ONE,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata,
+    };
+    block.header.merkle_root = block.compute_merkle_root().unwrap();
+    block
+}
+
+fn new_tx(input: Vec<TxIn>, output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: LockTime::ZERO,
+        input,
+        output,
+    }
+}
+
+fn coinbase(n: u32, output: Vec<TxOut>) -> Transaction {
+    let input = TxIn {
+        previous_output: OutPoint::null(),
+        script_sig: ScriptBuf::builder().push_int(n as i64).into_script(),
+        ..Default::default()
+    };
+    new_tx(vec![input], output)
+}
+
+fn p2wpkh(pubkey: &PublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(&pubkey.serialize()))
+}
+
+fn p2tr(xonly: bitcoin::XOnlyPublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(xonly))
+}
+
+/// Spend a p2wpkh output, the signature is not checked.
+fn p2wpkh_input(previous_output: OutPoint, pubkey: &PublicKey) -> TxIn {
+    TxIn {
+        previous_output,
+        witness: Witness::from_slice(&[vec![0x30; 71], pubkey.serialize().to_vec()]),
+        ..Default::default()
+    }
+}
+
+/// Spend a taproot output with a key path spend, the signature is not checked.
+fn p2tr_input(previous_output: OutPoint) -> TxIn {
+    TxIn {
+        previous_output,
+        witness: Witness::from_slice(&[vec![0x01; 64]]),
+        ..Default::default()
+    }
+}
+
+fn receiver() -> SpClient {
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Bitcoin).unwrap()
+}
+
+/// A small chain of 3 blocks:
+///
+/// * block 100: a coinbase paying to the sender.
+/// * block 101: the sender pays a silent payment to the receiver, and another transaction has a dust taproot output.
+/// * block 102: the silent payment output is spent.
+struct TestChain {
+    blocks: Vec<(Block, HashMap<OutPoint, TxOut>)>,
+    payment: Transaction,
+    dust_payment: Transaction,
+    sp_outpoint: OutPoint,
+}
+
+impl TestChain {
+    fn new() -> Self {
+        let secp = Secp256k1::new();
+        let sender_sk = SecretKey::from_slice(&[0x03; 32]).unwrap();
+        let sender_pk = sender_sk.public_key(&secp);
+
+        let other_sk = SecretKey::from_slice(&[0x04; 32]).unwrap();
+        let other_pk = other_sk.public_key(&secp);
+        let (other_xonly, _) = other_pk.x_only_public_key();
+
+        let cb = coinbase(
+            START_HEIGHT,
+            vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: p2wpkh(&sender_pk),
+            }],
+        );
+        let block0 = new_block(BlockHash::all_zeros(), vec![cb.clone()]);
+
+        // pay to the receiver
+        let funding = OutPoint::new(cb.compute_txid(), 0);
+        let partial_secret =
+            calculate_partial_secret(&[(sender_sk, false)], &[funding.into()]).unwrap();
+        let address = receiver().get_receiving_address();
+        let output_keys = generate_recipient_pubkeys(vec![address], partial_secret).unwrap();
+
+        let payment = new_tx(
+            vec![p2wpkh_input(funding, &sender_pk)],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: p2tr(output_keys[&address][0]),
+                },
+                TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: p2wpkh(&sender_pk),
+                },
+            ],
+        );
+
+        // a transaction with only a dust taproot output, spending an output from before the index
+        let external = OutPoint::new(bitcoin::Txid::all_zeros(), 7);
+        let external_prevout = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: p2wpkh(&other_pk),
+        };
+        let dust_payment = new_tx(
+            vec![p2wpkh_input(external, &other_pk)],
+            vec![TxOut {
+                value: Amount::from_sat(300),
+                script_pubkey: p2tr(other_xonly),
+            }],
+        );
+
+        let block1 = new_block(
+            block0.block_hash(),
+            vec![
+                coinbase(START_HEIGHT + 1, vec![]),
+                payment.clone(),
+                dust_payment.clone(),
+            ],
+        );
+        let prevouts1 = HashMap::from([
+            (funding, cb.output[0].clone()),
+            (external, external_prevout),
+        ]);
+
+        // spend the silent payment output
+        let sp_outpoint = OutPoint::new(payment.compute_txid(), 0);
+        let sweep = new_tx(
+            vec![p2tr_input(sp_outpoint)],
+            vec![TxOut {
+                value: Amount::from_sat(49_000),
+                script_pubkey: p2wpkh(&other_pk),
+            }],
+        );
+        let block2 = new_block(
+            block1.block_hash(),
+            vec![coinbase(START_HEIGHT + 2, vec![]), sweep],
+        );
+        let prevouts2 = HashMap::from([(sp_outpoint, payment.output[0].clone())]);
+
+        Self {
+            blocks: vec![
+                (block0, HashMap::new()),
+                (block1, prevouts1),
+                (block2, prevouts2),
+            ],
+            payment,
+            dust_payment,
+            sp_outpoint,
+        }
+    }
+
+    fn index(&self, count: usize) -> TweakIndex {
+        let mut index = TweakIndex::new();
+        for (n, (block, prevouts)) in self.blocks.iter().take(count).enumerate() {
+            index
+                .add_block(height(START_HEIGHT + n as u32), block, prevouts)
+                .unwrap();
+        }
+        index
+    }
+}
+
+fn tweak(tx: &Transaction, prevouts: &[TxOut]) -> PublicKey {
+    tweak_data_for_tx(tx, prevouts).unwrap().unwrap()
+}
+
+#[test]
+fn tweaks_match_silentpayments() {
+    let chain = TestChain::new();
+    let index = chain.index(2);
+
+    let (block1, prevouts1) = &chain.blocks[1];
+    let payment_prevouts = [prevouts1[&chain.payment.input[0].previous_output].clone()];
+    let dust_prevouts = [prevouts1[&chain.dust_payment.input[0].previous_output].clone()];
+
+    let tweaks = index
+        .tweaks(height(START_HEIGHT + 1), Amount::ZERO, false)
+        .unwrap();
+    assert_eq!(
+        tweaks,
+        vec![
+            tweak(&chain.payment, &payment_prevouts),
+            tweak(&chain.dust_payment, &dust_prevouts)
+        ]
+    );
+
+    // the coinbase is never eligible
+    assert!(
+        index
+            .tweaks(height(START_HEIGHT), Amount::ZERO, false)
+            .unwrap()
+            .is_empty()
+    );
+
+    // only the taproot outputs are returned
+    let utxos = index.utxos(height(START_HEIGHT + 1)).unwrap();
+    assert_eq!(utxos.len(), 2);
+    assert!(utxos.iter().all(|utxo| utxo.scriptpubkey.is_p2tr()));
+    assert!(utxos.iter().all(|utxo| !utxo.spent));
+
+    assert_eq!(
+        index.tip(),
+        Some((height(START_HEIGHT + 1), block1.block_hash()))
+    );
+}
+
+#[test]
+fn dust_limit_and_cutthrough() {
+    let chain = TestChain::new();
+    let blkheight = height(START_HEIGHT + 1);
+
+    // before the silent payment is spent
+    let index = chain.index(2);
+    assert_eq!(
+        index.tweaks(blkheight, Amount::ZERO, true).unwrap().len(),
+        2
+    );
+    assert_eq!(index.tweaks(blkheight, DUST_LIMIT, false).unwrap().len(), 1);
+
+    // after the silent payment is spent
+    let index = chain.index(3);
+    assert_eq!(
+        index.tweaks(blkheight, Amount::ZERO, false).unwrap().len(),
+        2
+    );
+    assert_eq!(index.tweaks(blkheight, DUST_LIMIT, false).unwrap().len(), 1);
+    assert_eq!(
+        index.tweaks(blkheight, Amount::ZERO, true).unwrap().len(),
+        1
+    );
+    assert!(
+        index
+            .tweaks(blkheight, DUST_LIMIT, true)
+            .unwrap()
+            .is_empty()
+    );
+
+    let utxos = index.utxos(blkheight).unwrap();
+    let spent: Vec<_> = utxos.iter().filter(|utxo| utxo.spent).collect();
+    assert_eq!(spent.len(), 1);
+    assert_eq!(spent[0].txid, chain.sp_outpoint.txid);
+    assert_eq!(spent[0].vout, chain.sp_outpoint.vout);
+}
+
+#[test]
+fn filters_match_outputs_and_spent_outpoints() {
+    let chain = TestChain::new();
+    let index = chain.index(3);
+
+    let block_data = index
+        .block_data(height(START_HEIGHT + 1), DUST_LIMIT, false)
+        .unwrap();
+    let blkhash = block_data.blkhash;
+    let filter = BlockFilter::new(&block_data.new_utxo_filter.data);
+    let sp_key = &chain.payment.output[0].script_pubkey.as_bytes()[2..];
+    assert!(
+        filter
+            .match_any(&blkhash, &mut [sp_key].into_iter())
+            .unwrap()
+    );
+
+    let blkheight = height(START_HEIGHT + 2);
+    let block_data = index.block_data(blkheight, DUST_LIMIT, false).unwrap();
+    let blkhash = block_data.blkhash;
+    let hash = spent_index_hash(&chain.sp_outpoint, &blkhash);
+    let filter = BlockFilter::new(&block_data.spent_filter.data);
+    assert!(
+        filter
+            .match_any(&blkhash, &mut [hash.as_slice()].into_iter())
+            .unwrap()
+    );
+    let spent_index = index.spent_index(blkheight).unwrap();
+    assert_eq!(spent_index.data, vec![hash.to_vec()]);
+    let sweep = &chain.blocks[2].0.txdata[1];
+    assert_eq!(
+        spent_index.spending_txs,
+        vec![SpendingTx {
+            txid: sweep.compute_txid(),
+            vin: 0,
+        }]
+    );
+}
+
+#[test]
+fn spent_filter_matches_fixture() {
+    let path = "tests/resources/blocks/295147";
+    let spent_index: SpentIndexResponse =
+        serde_json::from_reader(File::open(format!("{path}/spent-index.json")).unwrap()).unwrap();
+    let spent_filter: FilterResponse =
+        serde_json::from_reader(File::open(format!("{path}/filter-spent.json")).unwrap()).unwrap();
+
+    let elements: Vec<Vec<u8>> = spent_index.data.into_iter().map(|x| x.hex).collect();
+    let filter = build_filter(&spent_index.block_hash, &elements).unwrap();
+
+    assert_eq!(filter, spent_filter.data.hex);
+}
+
+#[test]
+fn add_block_errors() {
+    let chain = TestChain::new();
+    let mut index = chain.index(1);
+
+    let (block1, prevouts1) = &chain.blocks[1];
+    let (block2, prevouts2) = &chain.blocks[2];
+
+    // blocks must be added in order
+    assert!(
+        index
+            .add_block(height(START_HEIGHT + 2), block2, prevouts2)
+            .is_err()
+    );
+    assert!(
+        index
+            .add_block(height(START_HEIGHT + 1), block2, prevouts2)
+            .is_err()
+    );
+
+    // all prevouts must be known
+    assert!(
+        index
+            .add_block(height(START_HEIGHT + 1), block1, &HashMap::new())
+            .is_err()
+    );
+    assert_eq!(index.tip().unwrap().0, height(START_HEIGHT));
+
+    // the prevouts must match the inputs
+    let mut wrong_prevouts = prevouts1.clone();
+    let funding = chain.payment.input[0].previous_output;
+    wrong_prevouts.get_mut(&funding).unwrap().script_pubkey = ScriptBuf::new_p2pkh(
+        &bitcoin::PubkeyHash::hash(&chain.payment.input[0].witness[1]),
+    );
+    assert!(
+        index
+            .add_block(height(START_HEIGHT + 1), block1, &wrong_prevouts)
+            .is_err()
+    );
+    assert_eq!(index.tip().unwrap().0, height(START_HEIGHT));
+
+    index
+        .add_block(height(START_HEIGHT + 1), block1, prevouts1)
+        .unwrap();
+    assert!(index.utxos(height(START_HEIGHT + 2)).is_err());
+}
+
+#[test]
+fn transactions_spending_unknown_witness_versions_are_skipped() {
+    let chain = TestChain::new();
+    let mut index = chain.index(1);
+
+    // the dust payment spends a segwit v2 output
+    let (block1, prevouts1) = &chain.blocks[1];
+    let mut prevouts = prevouts1.clone();
+    let external = chain.dust_payment.input[0].previous_output;
+    prevouts.get_mut(&external).unwrap().script_pubkey =
+        ScriptBuf::from_bytes([&[0x52, 0x20][..], &[0x01; 32]].concat());
+
+    index
+        .add_block(height(START_HEIGHT + 1), block1, &prevouts)
+        .unwrap();
+
+    let tweaks = index
+        .tweaks(height(START_HEIGHT + 1), Amount::ZERO, false)
+        .unwrap();
+    let payment_prevouts = [prevouts1[&chain.payment.input[0].previous_output].clone()];
+    assert_eq!(tweaks, vec![tweak(&chain.payment, &payment_prevouts)]);
+}
+
+#[test]
+fn disconnect_blocks_from_the_tip() {
+    let chain = TestChain::new();
+    let mut index = chain.index(3);
+    let blkheight = height(START_HEIGHT + 1);
+
+    let (block1, _) = &chain.blocks[1];
+    let (block2, prevouts2) = &chain.blocks[2];
+
+    let disconnected = index.disconnect_block().unwrap();
+    assert_eq!(disconnected.hash(), block2.block_hash());
+    assert_eq!(index.tip(), Some((blkheight, block1.block_hash())));
+    assert!(index.utxos(height(START_HEIGHT + 2)).is_err());
+
+    // the silent payment output is unspent again
+    assert!(
+        index
+            .utxos(blkheight)
+            .unwrap()
+            .iter()
+            .all(|utxo| !utxo.spent)
+    );
+    assert_eq!(
+        index.tweaks(blkheight, Amount::ZERO, true).unwrap().len(),
+        2
+    );
+
+    // the block can be connected again, and spends the output again
+    index
+        .add_block(height(START_HEIGHT + 2), block2, prevouts2)
+        .unwrap();
+    assert_eq!(
+        index.tweaks(blkheight, Amount::ZERO, true).unwrap().len(),
+        1
+    );
+
+    for _ in 0..3 {
+        assert!(index.disconnect_block().is_some());
+    }
+    assert!(index.disconnect_block().is_none());
+    assert_eq!(index.tip(), None);
+}
+
+type Update = (
+    Height,
+    HashSet<OutPoint>,
+    HashMap<OutPoint, DiscoveredOutput>,
+);
+
+#[derive(Clone, Default)]
+struct TestUpdater {
+    updates: Arc<Mutex<Vec<Update>>>,
+    spending_txs: Arc<Mutex<HashMap<OutPoint, SpendingTx>>>,
+}
+
+impl Updater for TestUpdater {
+    fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        _blkhash: BlockHash,
+        discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()> {
+        self.updates
+            .lock()
+            .unwrap()
+            .push((blkheight, discovered_inputs, discovered_outputs));
+        Ok(())
+    }
+
+    fn rollback_to_block(&mut self, blkheight: Height, _blkhash: BlockHash) -> Result<()> {
+        self.updates
+            .lock()
+            .unwrap()
+            .retain(|(height, _, _)| *height <= blkheight);
+        Ok(())
+    }
+
+    fn record_spending_txs(
+        &mut self,
+        _blkheight: Height,
+        spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()> {
+        self.spending_txs.lock().unwrap().extend(spending_txs);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn scan_with_index_backend() {
+    let chain = TestChain::new();
+    let backend = IndexBackend::new(Arc::new(RwLock::new(chain.index(2))));
+
+    let updater = TestUpdater::default();
+    let updates = updater.updates.clone();
+    let spending_txs = updater.spending_txs.clone();
+    let cancel = CancellationToken::new();
+
+    let mut scanner = SpScanner::new(
+        receiver(),
+        Box::new(updater),
+        Box::new(backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    scanner
+        .scan_blocks(
+            height(START_HEIGHT)..=height(START_HEIGHT + 1),
+            DUST_LIMIT,
+            false,
+        )
+        .await
+        .unwrap();
+
+    // the index is shared, so the backend serves blocks that are added later
+    let (block2, prevouts2) = &chain.blocks[2];
+    backend
+        .index()
+        .write()
+        .unwrap()
+        .add_block(height(START_HEIGHT + 2), block2, prevouts2)
+        .unwrap();
+
+    scanner
+        .scan_blocks(
+            height(START_HEIGHT + 2)..=height(START_HEIGHT + 2),
+            DUST_LIMIT,
+            false,
+        )
+        .await
+        .unwrap();
+
+    // blocks outside the index are an error
+    assert!(backend.utxos(height(START_HEIGHT + 3)).await.is_err());
+
+    let updates = updates.lock().unwrap();
+    assert_eq!(updates.len(), 3);
+
+    // the payment is found
+    let (blkheight, inputs, outputs) = &updates[1];
+    assert_eq!(*blkheight, height(START_HEIGHT + 1));
+    assert!(inputs.is_empty());
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[&chain.sp_outpoint].value, Amount::from_sat(50_000));
+
+    // and spent in the next block
+    let (_, inputs, outputs) = &updates[2];
+    assert_eq!(inputs, &HashSet::from([chain.sp_outpoint]));
+    assert!(outputs.is_empty());
+
+    // by the sweep transaction
+    let sweep = &chain.blocks[2].0.txdata[1];
+    assert_eq!(
+        *spending_txs.lock().unwrap(),
+        HashMap::from([(
+            chain.sp_outpoint,
+            SpendingTx {
+                txid: sweep.compute_txid(),
+                vin: 0,
+            }
+        )])
+    );
+}
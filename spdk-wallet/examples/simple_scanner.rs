@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    sync::{Arc, Mutex, atomic::AtomicBool},
+    sync::{Arc, Mutex},
 };
 
 use backend_blindbit_v1::{BlindbitBackend, BlindbitClient};
@@ -8,7 +8,7 @@ use bitcoin::{Amount, BlockHash, Network, OutPoint, absolute::Height, secp256k1:
 use spdk_core::updater::{DiscoveredOutput, Updater};
 use spdk_wallet::{
     client::{SpClient, SpendKey},
-    scanner::SpScanner,
+    scanner::{CancellationToken, SpScanner},
 };
 
 // in this example, we use the public signet silentpayments.dev blindbit server
@@ -72,6 +72,16 @@ impl Updater for InMemoryUpdater {
 
         Ok(())
     }
+
+    fn rollback_to_block(&mut self, blkheight: Height, _blkhash: BlockHash) -> anyhow::Result<()> {
+        // forget the updates of the blocks that are no longer part of the chain
+        self.received_updates
+            .lock()
+            .unwrap()
+            .retain(|update| update.blkheight <= blkheight);
+
+        Ok(())
+    }
 }
 
 #[tokio::main]
@@ -79,9 +89,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let scan_sk = SecretKey::from_slice(&SCAN_SK_BYTES)?;
     let spend_sk = SecretKey::from_slice(&SPEND_SK_BYTES)?;
 
-    // for a real scan, we can set this bool to false to interrupt the scan process
-    // in this example, we keep it set to true
-    let keep_scanning = AtomicBool::new(true);
+    // for a real scan, a clone of this token can be cancelled to interrupt the scan process
+    // in this example, we never cancel it
+    let cancel = CancellationToken::new();
 
     let backend = BlindbitBackend::new(BlindbitClient::new(BLINDBIT_BACKEND_URL)?);
 
@@ -110,7 +120,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Box::new(updater.clone()),
         Box::new(backend),
         HashSet::new(),
-        &keep_scanning,
+        cancel,
     );
 
     let start = Height::from_consensus(SCAN_START_HEIGHT)?;
@@ -1,42 +1,183 @@
-use std::{collections::HashMap, io::Write};
+use std::{collections::HashMap, fmt, io::Write};
 
 use bitcoin::{
     Network,
     secp256k1::{PublicKey, Secp256k1, SecretKey},
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use silentpayments::bitcoin_hashes::Hash;
 use silentpayments::{Network as SpNetwork, SharedSecret, SpVersion};
 use silentpayments::{
     SilentPaymentAddress,
     bitcoin_hashes::sha256,
     receiving::{Label, Receiver},
 };
-use silentpayments::{bitcoin_hashes::Hash, utils as sp_utils};
 
 use anyhow::{Error, Result};
 
-use super::SpendKey;
+use super::{LocalScanKeyProvider, ScanKeyProvider, SpendKey};
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(PartialEq, Clone)]
 pub struct SpClient {
-    scan_sk: SecretKey,
+    // None if the scan key is held by a ScanKeyProvider
+    scan_sk: Option<SecretKey>,
     spend_key: SpendKey,
     pub sp_receiver: Receiver,
     network: Network,
 }
 
+impl fmt::Debug for SpClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpClient")
+            .field("scan_sk", &self.scan_sk.map(|_| "<redacted>"))
+            .field("spend_key", &self.spend_key)
+            .field("sp_receiver", &self.sp_receiver)
+            .field("network", &self.network)
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SpClient {
+    fn drop(&mut self) {
+        if let Some(scan_sk) = &mut self.scan_sk {
+            scan_sk.non_secure_erase();
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SpClientRef<'a> {
+    schema_version: u8,
+    scan_sk: &'a Option<SecretKey>,
+    spend_key: &'a SpendKey,
+    sp_receiver: &'a Receiver,
+    network: Network,
+}
+
+#[derive(Deserialize)]
+struct SpClientHelper {
+    // absent in the original format, which is schema version 0
+    #[serde(default)]
+    schema_version: u8,
+    scan_sk: Option<SecretKey>,
+    spend_key: SpendKey,
+    sp_receiver: Receiver,
+    network: Network,
+}
+
+impl Serialize for SpClient {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        SpClientRef {
+            schema_version: Self::SCHEMA_VERSION,
+            scan_sk: &self.scan_sk,
+            spend_key: &self.spend_key,
+            sp_receiver: &self.sp_receiver,
+            network: self.network,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SpClient {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let helper = SpClientHelper::deserialize(deserializer)?;
+        SpClient::try_from(helper).map_err(de::Error::custom)
+    }
+}
+
+impl TryFrom<SpClientHelper> for SpClient {
+    type Error = Error;
+
+    fn try_from(helper: SpClientHelper) -> Result<Self> {
+        match helper.schema_version {
+            // version 1 only added the schema version itself, the fields are the same
+            0 | 1 => (),
+            v => {
+                return Err(Error::msg(format!(
+                    "Unsupported client schema version: {}",
+                    v
+                )));
+            }
+        }
+
+        // the receiver must belong to the keys and network of this client
+        let address = helper.sp_receiver.get_receiving_address();
+        if let Some(scan_sk) = helper.scan_sk
+            && scan_sk.public_key(&Secp256k1::signing_only()) != address.scan_key()
+        {
+            return Err(Error::msg("Scan key doesn't match the receiver"));
+        }
+        if PublicKey::from(&helper.spend_key) != address.m_pubkey() {
+            return Err(Error::msg("Spend key doesn't match the receiver"));
+        }
+        if Some(helper.sp_receiver.network) != sp_network(helper.network) {
+            return Err(Error::msg("Network doesn't match the receiver"));
+        }
+
+        Ok(Self {
+            scan_sk: helper.scan_sk,
+            spend_key: helper.spend_key,
+            sp_receiver: helper.sp_receiver,
+            network: helper.network,
+        })
+    }
+}
+
+pub(super) fn sp_network(network: Network) -> Option<SpNetwork> {
+    match network {
+        Network::Bitcoin => Some(SpNetwork::Mainnet),
+        Network::Regtest => Some(SpNetwork::Regtest),
+        Network::Testnet | Network::Signet => Some(SpNetwork::Testnet),
+        _ => None,
+    }
+}
+
 impl SpClient {
+    /// Version of the serialization format of a [SpClient].
+    ///
+    /// Version 0 is the original format, without a `schema_version` field.
+    /// Older versions are still accepted when deserializing, and are migrated to the current version.
+    pub const SCHEMA_VERSION: u8 = 1;
+
     pub fn new(scan_sk: SecretKey, spend_key: SpendKey, network: Network) -> Result<Self> {
         let secp = Secp256k1::signing_only();
         let scan_pubkey = scan_sk.public_key(&secp);
         let change_label = Label::new(scan_sk, 0);
 
-        let sp_network = match network {
-            Network::Bitcoin => SpNetwork::Mainnet,
-            Network::Regtest => SpNetwork::Regtest,
-            Network::Testnet | Network::Signet => SpNetwork::Testnet,
-            _ => unreachable!(),
-        };
+        Self::from_parts(Some(scan_sk), scan_pubkey, change_label, spend_key, network)
+    }
+
+    /// Create a new client whose scan secret key is held by a [ScanKeyProvider].
+    ///
+    /// Such a client can't compute shared secrets by itself,
+    /// so scanning requires passing the provider to [SpScanner::with_scan_key_provider](crate::scanner::SpScanner::with_scan_key_provider).
+    pub fn new_with_scan_key_provider(
+        provider: &dyn ScanKeyProvider,
+        spend_key: SpendKey,
+        network: Network,
+    ) -> Result<Self> {
+        let scan_pubkey = provider.scan_pubkey()?;
+        let change_label = provider.label(0)?;
+
+        Self::from_parts(None, scan_pubkey, change_label, spend_key, network)
+    }
+
+    fn from_parts(
+        scan_sk: Option<SecretKey>,
+        scan_pubkey: PublicKey,
+        change_label: Label,
+        spend_key: SpendKey,
+        network: Network,
+    ) -> Result<Self> {
+        let sp_network = sp_network(network)
+            .ok_or_else(|| Error::msg(format!("Unknown network {}", network)))?;
 
         let sp_receiver = Receiver::new(
             SpVersion::ZERO,
@@ -58,8 +199,16 @@ impl SpClient {
         self.sp_receiver.get_receiving_address()
     }
 
+    /// Panics if the scan key is held by a scan key provider.
+    #[deprecated(note = "use `try_get_scan_key` instead")]
     pub fn get_scan_key(&self) -> SecretKey {
+        self.try_get_scan_key()
+            .expect("Scan key is held by a scan key provider")
+    }
+
+    pub fn try_get_scan_key(&self) -> Result<SecretKey> {
         self.scan_sk
+            .ok_or_else(|| Error::msg("Scan key is held by a scan key provider"))
     }
 
     pub fn get_spend_key(&self) -> SpendKey {
@@ -80,24 +229,42 @@ impl SpClient {
     pub fn get_script_to_secret_map(
         &self,
         tweak_data_vec: Vec<PublicKey>,
+    ) -> Result<HashMap<[u8; 34], SharedSecret>> {
+        let provider = LocalScanKeyProvider::new(self.try_get_scan_key()?);
+
+        self.get_script_to_secret_map_with_provider(&provider, tweak_data_vec)
+    }
+
+    /// Same as [get_script_to_secret_map](Self::get_script_to_secret_map), but the shared secrets are computed by the given [ScanKeyProvider].
+    pub fn get_script_to_secret_map_with_provider(
+        &self,
+        provider: &dyn ScanKeyProvider,
+        tweak_data_vec: Vec<PublicKey>,
     ) -> Result<HashMap<[u8; 34], SharedSecret>> {
         // if using rayon feature, import the preludes
         #[cfg(feature = "rayon")]
         use rayon::prelude::*;
 
-        let b_scan = &self.get_scan_key();
+        let secrets = provider.ecdh_batch(&tweak_data_vec)?;
+
+        if secrets.len() != tweak_data_vec.len() {
+            return Err(Error::msg(format!(
+                "Scan key provider returned {} shared secrets for {} tweaks",
+                secrets.len(),
+                tweak_data_vec.len()
+            )));
+        }
 
         // parallel iterator using rayon
         #[cfg(feature = "rayon")]
-        let tweak_data_iterator = tweak_data_vec.into_par_iter();
+        let secrets_iterator = secrets.into_par_iter();
 
         // regular iterator
         #[cfg(not(feature = "rayon"))]
-        let tweak_data_iterator = tweak_data_vec.into_iter();
+        let secrets_iterator = secrets.into_iter();
 
-        let items: Result<Vec<_>> = tweak_data_iterator
-            .map(|tweak| {
-                let secret = sp_utils::receiving::calculate_ecdh_shared_secret(&tweak, b_scan);
+        let items: Result<Vec<_>> = secrets_iterator
+            .map(|secret| {
                 let spks = self.sp_receiver.get_spks_from_shared_secret(&secret)?;
 
                 Ok((secret, spks.into_values()))
@@ -107,7 +274,7 @@ impl SpClient {
         let mut res = HashMap::new();
         for (secret, spks) in items? {
             for spk in spks {
-                res.insert(spk, secret);
+                res.insert(spk, secret.clone());
             }
         }
         Ok(res)
@@ -0,0 +1,129 @@
+use bip39::Mnemonic;
+use bitcoin::{
+    Network, NetworkKind,
+    bip32::{ChildNumber, DerivationPath, Xpriv, Xpub},
+    secp256k1::Secp256k1,
+};
+
+use anyhow::{Error, Result};
+
+use super::{SpClient, SpendKey};
+
+const PURPOSE: u32 = 352;
+const SCAN_BRANCH: u32 = 1;
+const SPEND_BRANCH: u32 = 0;
+
+/// Returns the path of the branch key for either the scan or spend key, `m/352'/coin_type'/account'/branch'`.
+fn branch_path(network: Network, account: u32, branch: u32) -> Result<DerivationPath> {
+    let coin_type = match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    };
+
+    let path = [
+        ChildNumber::from_hardened_idx(PURPOSE)?,
+        ChildNumber::from_hardened_idx(coin_type)?,
+        ChildNumber::from_hardened_idx(account)?,
+        ChildNumber::from_hardened_idx(branch)?,
+    ];
+
+    Ok(DerivationPath::from(path.to_vec()))
+}
+
+fn check_network(network_kind: NetworkKind, network: Network) -> Result<()> {
+    if network_kind != NetworkKind::from(network) {
+        return Err(Error::msg(format!(
+            "Extended key is not valid for network {}",
+            network
+        )));
+    }
+    Ok(())
+}
+
+/// The BIP352 derivation path of the scan key, `m/352'/coin_type'/account'/1'/0`.
+pub fn scan_derivation_path(network: Network, account: u32) -> Result<DerivationPath> {
+    Ok(branch_path(network, account, SCAN_BRANCH)?.child(ChildNumber::from(0)))
+}
+
+/// The BIP352 derivation path of the spend key, `m/352'/coin_type'/account'/0'/0`.
+pub fn spend_derivation_path(network: Network, account: u32) -> Result<DerivationPath> {
+    Ok(branch_path(network, account, SPEND_BRANCH)?.child(ChildNumber::from(0)))
+}
+
+/// Derive the extended keys that are needed for a watch-only client, see [SpClient::new_watch_only_from_xkeys].
+///
+/// The scan key is returned as the xprv at `m/352'/coin_type'/account'/1'`,
+/// the spend key as the xpub at `m/352'/coin_type'/account'/0'`.
+pub fn derive_watch_only_xkeys(
+    master_xprv: &Xpriv,
+    account: u32,
+    network: Network,
+) -> Result<(Xpriv, Xpub)> {
+    check_network(master_xprv.network, network)?;
+
+    let secp = Secp256k1::new();
+
+    let scan_xprv = master_xprv.derive_priv(&secp, &branch_path(network, account, SCAN_BRANCH)?)?;
+    let spend_xprv =
+        master_xprv.derive_priv(&secp, &branch_path(network, account, SPEND_BRANCH)?)?;
+
+    Ok((scan_xprv, Xpub::from_priv(&secp, &spend_xprv)))
+}
+
+impl SpClient {
+    /// Create a new client from a BIP39 mnemonic and an optional passphrase (use `""` for none).
+    ///
+    /// The keys are derived using the BIP352 derivation paths for the given account and network.
+    pub fn from_mnemonic(
+        mnemonic: &str,
+        passphrase: &str,
+        account: u32,
+        network: Network,
+    ) -> Result<Self> {
+        let mnemonic = Mnemonic::parse(mnemonic)?;
+        let master_xprv = Xpriv::new_master(network, &mnemonic.to_seed(passphrase))?;
+
+        Self::from_xprv(&master_xprv, account, network)
+    }
+
+    /// Create a new client from a master xprv.
+    ///
+    /// The keys are derived using the BIP352 derivation paths for the given account and network.
+    pub fn from_xprv(master_xprv: &Xpriv, account: u32, network: Network) -> Result<Self> {
+        check_network(master_xprv.network, network)?;
+
+        let secp = Secp256k1::signing_only();
+
+        let scan_sk = master_xprv
+            .derive_priv(&secp, &scan_derivation_path(network, account)?)?
+            .private_key;
+        let spend_sk = master_xprv
+            .derive_priv(&secp, &spend_derivation_path(network, account)?)?
+            .private_key;
+
+        Self::new(scan_sk, SpendKey::Secret(spend_sk), network)
+    }
+
+    /// Create a new watch-only client from the extended keys returned by [derive_watch_only_xkeys].
+    ///
+    /// The scan and spend keys are derived as the first non-hardened child of the given extended keys.
+    pub fn new_watch_only_from_xkeys(
+        scan_xprv: &Xpriv,
+        spend_xpub: &Xpub,
+        network: Network,
+    ) -> Result<Self> {
+        check_network(scan_xprv.network, network)?;
+        check_network(spend_xpub.network, network)?;
+
+        let secp = Secp256k1::new();
+
+        let scan_sk = scan_xprv
+            .derive_priv(&secp, &[ChildNumber::from(0)])?
+            .private_key;
+        let spend_pk = spend_xpub
+            .derive_pub(&secp, &[ChildNumber::from(0)])?
+            .public_key;
+
+        Self::new(scan_sk, SpendKey::Public(spend_pk), network)
+    }
+}
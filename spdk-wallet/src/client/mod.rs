@@ -1,8 +1,14 @@
 mod bip321_parsing;
 mod client;
+mod keys;
+mod psbt;
+mod psbt_v2;
+mod scan_key;
 mod spend;
 mod structs;
 
 pub use bip321_parsing::{SpUriExtension, SpUriParseError, parse_sp, parse_tsp};
 pub use client::SpClient;
+pub use keys::{derive_watch_only_xkeys, scan_derivation_path, spend_derivation_path};
+pub use scan_key::{LocalScanKeyProvider, ScanKeyProvider};
 pub use structs::*;
@@ -0,0 +1,475 @@
+use anyhow::{Error, Result};
+use bitcoin::psbt::{Psbt, raw};
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::{Parity, PublicKey, Scalar, SecretKey, XOnlyPublicKey};
+use bitcoin::{Address, OutPoint, ScriptBuf, TxOut};
+use silentpayments::dleq::{DleqProof, verify_dleq_proof};
+use silentpayments::utils as sp_utils;
+use silentpayments::{SilentPaymentAddress, SilentPaymentAddressDisplay};
+
+use spdk_core::constants::{
+    PSBT_GLOBAL_SP_DLEQ, PSBT_GLOBAL_SP_ECDH_SHARE, PSBT_IN_SP_DLEQ, PSBT_IN_SP_ECDH_SHARE,
+    PSBT_OUT_SP_V0_INFO, PSBT_OUT_SP_V0_LABEL, PSBT_SP_ADDRESS_KEY, PSBT_SP_PREFIX,
+    PSBT_SP_SUBTYPE, PSBT_SP_TWEAK_KEY,
+};
+use spdk_core::updater::DiscoveredOutput;
+
+use super::client::sp_network;
+use super::psbt_v2;
+use super::{Recipient, RecipientAddress, SilentPaymentUnsignedTransaction, SpClient};
+
+fn sp_proprietary_key(key: &str) -> raw::ProprietaryKey {
+    raw::ProprietaryKey {
+        prefix: PSBT_SP_PREFIX.as_bytes().to_vec(),
+        subtype: PSBT_SP_SUBTYPE,
+        key: key.as_bytes().to_vec(),
+    }
+}
+
+/// Returns the data pushed by an OP_RETURN script, if this is one.
+fn op_return_data(script_pubkey: &ScriptBuf) -> Option<Vec<u8>> {
+    if !script_pubkey.is_op_return() {
+        return None;
+    }
+
+    let mut data = vec![];
+    for instruction in script_pubkey.instructions().skip(1) {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => data.extend_from_slice(bytes.as_bytes()),
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+/// The input public keys, where each taproot key is taken with an even y-coordinate.
+fn input_pubkeys(selected_utxos: &[(OutPoint, DiscoveredOutput)]) -> Result<Vec<PublicKey>> {
+    selected_utxos
+        .iter()
+        .map(|(_, output)| {
+            if !output.script_pubkey.is_p2tr() {
+                return Err(Error::msg("Only taproot inputs are supported"));
+            }
+            let xonly = XOnlyPublicKey::from_slice(&output.script_pubkey.as_bytes()[2..])?;
+            Ok(xonly.public_key(Parity::Even))
+        })
+        .collect()
+}
+
+/// Check an ECDH share against our own computation, and against its DLEQ proof.
+///
+/// `input_pubkey` is the public key of the inputs that the share was computed for.
+fn check_ecdh_share(
+    input_privkeys: &[(SecretKey, bool)],
+    input_pubkey: &PublicKey,
+    scan_key: &PublicKey,
+    share: &PublicKey,
+    proof: Option<&Vec<u8>>,
+) -> Result<()> {
+    if *share != sp_utils::sending::calculate_ecdh_share(input_privkeys, scan_key)? {
+        return Err(Error::msg(format!(
+            "Invalid ECDH share for scan key {}",
+            scan_key
+        )));
+    }
+
+    let proof =
+        proof.ok_or_else(|| Error::msg(format!("Missing DLEQ proof for scan key {}", scan_key)))?;
+    let proof = DleqProof::from_slice(proof)?;
+    if !verify_dleq_proof(input_pubkey, scan_key, share, &proof, None) {
+        return Err(Error::msg(format!(
+            "Invalid DLEQ proof for scan key {}",
+            scan_key
+        )));
+    }
+
+    Ok(())
+}
+
+impl SpClient {
+    /// Convert a [SilentPaymentUnsignedTransaction] into a PSBT, so that it can be handed over to other signers.
+    ///
+    /// If the transaction was not finalized yet, the silent payment outputs are computed first.
+    /// The silent payment fields from [BIP375](https://github.com/bitcoin/bips/blob/master/bip-0375.mediawiki) are added as follows:
+    ///
+    /// * every silent payment output gets a `PSBT_OUT_SP_V0_INFO` field, and the encoded address as a proprietary field.
+    ///   Outputs to one of our labelled addresses, such as the change, also get a `PSBT_OUT_SP_V0_LABEL` field.
+    /// * every recipient scan key gets a global `PSBT_GLOBAL_SP_ECDH_SHARE` field, along with a
+    ///   `PSBT_GLOBAL_SP_DLEQ` proof. `aux_rand` is the auxiliary randomness used for these proofs.
+    /// * every input gets a `PSBT_IN_SP_ECDH_SHARE` field and a `PSBT_IN_SP_DLEQ` proof for every recipient scan key,
+    ///   and its spend tweak as a proprietary field.
+    ///
+    /// Note: rust-bitcoin only supports version 0 PSBTs, while BIP375 requires version 2 PSBTs.
+    /// The result is therefore not a BIP375 PSBT, use [unsigned_transaction_to_psbt_v2](SpClient::unsigned_transaction_to_psbt_v2)
+    /// to hand the transaction over to other BIP375 signers.
+    pub fn unsigned_transaction_to_psbt(
+        &self,
+        unsigned_transaction: &SilentPaymentUnsignedTransaction,
+        aux_rand: &[u8; 32],
+    ) -> Result<Psbt> {
+        let unsigned_transaction = match unsigned_transaction.unsigned_tx {
+            Some(_) => unsigned_transaction.clone(),
+            None => Self::finalize_transaction(unsigned_transaction.clone())?,
+        };
+
+        let tx = unsigned_transaction
+            .unsigned_tx
+            .clone()
+            .ok_or(Error::msg("Missing unsigned transaction"))?;
+
+        if tx.input.len() != unsigned_transaction.selected_utxos.len()
+            || tx.output.len() != unsigned_transaction.recipients.len()
+        {
+            return Err(Error::msg(
+                "Unsigned transaction doesn't match selected utxos and recipients",
+            ));
+        }
+
+        let mut psbt = Psbt::from_unsigned_tx(tx)?;
+
+        for (input, (_, output)) in psbt
+            .inputs
+            .iter_mut()
+            .zip(unsigned_transaction.selected_utxos.iter())
+        {
+            input.witness_utxo = Some(TxOut {
+                value: output.value,
+                script_pubkey: output.script_pubkey.clone(),
+            });
+            input.proprietary.insert(
+                sp_proprietary_key(PSBT_SP_TWEAK_KEY),
+                output.tweak.to_be_bytes().to_vec(),
+            );
+        }
+
+        let input_privkeys =
+            self.get_input_privkeys_for_selected_utxos(&unsigned_transaction.selected_utxos)?;
+
+        for (output, recipient) in psbt
+            .outputs
+            .iter_mut()
+            .zip(unsigned_transaction.recipients.iter())
+        {
+            if let RecipientAddress::SpAddress(sp_address) = &recipient.address {
+                let scan_key = sp_address.scan_key();
+
+                let mut sp_info = scan_key.serialize().to_vec();
+                sp_info.extend_from_slice(&sp_address.m_pubkey().serialize());
+
+                output.unknown.insert(
+                    raw::Key {
+                        type_value: PSBT_OUT_SP_V0_INFO,
+                        key: vec![],
+                    },
+                    sp_info,
+                );
+                output.proprietary.insert(
+                    sp_proprietary_key(PSBT_SP_ADDRESS_KEY),
+                    sp_address.to_string().into_bytes(),
+                );
+                if let Some(m) = self.own_label_index(&SilentPaymentAddress::from(*sp_address))? {
+                    output.unknown.insert(
+                        raw::Key {
+                            type_value: PSBT_OUT_SP_V0_LABEL,
+                            key: vec![],
+                        },
+                        m.to_le_bytes().to_vec(),
+                    );
+                }
+
+                for (input, input_privkey) in psbt.inputs.iter_mut().zip(input_privkeys.iter()) {
+                    let share =
+                        sp_utils::sending::calculate_ecdh_share(&[*input_privkey], &scan_key)?;
+                    let proof = sp_utils::sending::calculate_ecdh_share_proof(
+                        &[*input_privkey],
+                        &scan_key,
+                        aux_rand,
+                    )?;
+
+                    input.unknown.insert(
+                        raw::Key {
+                            type_value: PSBT_IN_SP_ECDH_SHARE,
+                            key: scan_key.serialize().to_vec(),
+                        },
+                        share.serialize().to_vec(),
+                    );
+                    input.unknown.insert(
+                        raw::Key {
+                            type_value: PSBT_IN_SP_DLEQ,
+                            key: scan_key.serialize().to_vec(),
+                        },
+                        proof.to_bytes().to_vec(),
+                    );
+                }
+
+                let share = sp_utils::sending::calculate_ecdh_share(&input_privkeys, &scan_key)?;
+
+                psbt.unknown.insert(
+                    raw::Key {
+                        type_value: PSBT_GLOBAL_SP_ECDH_SHARE,
+                        key: scan_key.serialize().to_vec(),
+                    },
+                    share.serialize().to_vec(),
+                );
+
+                let proof = sp_utils::sending::calculate_ecdh_share_proof(
+                    &input_privkeys,
+                    &scan_key,
+                    aux_rand,
+                )?;
+
+                psbt.unknown.insert(
+                    raw::Key {
+                        type_value: PSBT_GLOBAL_SP_DLEQ,
+                        key: scan_key.serialize().to_vec(),
+                    },
+                    proof.to_bytes().to_vec(),
+                );
+            }
+        }
+
+        Ok(psbt)
+    }
+
+    /// Convert a PSBT created by [unsigned_transaction_to_psbt](SpClient::unsigned_transaction_to_psbt) back into a [SilentPaymentUnsignedTransaction].
+    ///
+    /// All inputs must be silent payment outputs owned by this client, since the partial secret is recomputed using our spend key.
+    /// Any ECDH shares present in the PSBT are checked against our own computation and must have a valid DLEQ proof.
+    /// The silent payment output scripts are checked as well, and the ones that are empty are filled in.
+    /// A `PSBT_OUT_SP_V0_LABEL` field must match the label of one of our addresses.
+    ///
+    /// Note: labels of the spent outputs are not stored in the PSBT, so the resulting [DiscoveredOutput]s don't have a label.
+    pub fn unsigned_transaction_from_psbt(
+        &self,
+        psbt: &Psbt,
+    ) -> Result<SilentPaymentUnsignedTransaction> {
+        let network = self.get_network();
+        let address_sp_network = sp_network(network)
+            .ok_or_else(|| Error::msg(format!("Unknown network {}", network)))?;
+
+        let selected_utxos = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter())
+            .enumerate()
+            .map(|(i, (txin, input))| {
+                let prevout = input
+                    .witness_utxo
+                    .as_ref()
+                    .ok_or(Error::msg(format!("Missing witness utxo for input {}", i)))?;
+
+                let tweak_bytes: [u8; 32] = input
+                    .proprietary
+                    .get(&sp_proprietary_key(PSBT_SP_TWEAK_KEY))
+                    .ok_or(Error::msg(format!("Missing spend tweak for input {}", i)))?
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| Error::msg(format!("Invalid spend tweak for input {}", i)))?;
+
+                let output = DiscoveredOutput {
+                    tweak: Scalar::from_be_bytes(tweak_bytes)?,
+                    value: prevout.value,
+                    script_pubkey: prevout.script_pubkey.clone(),
+                    label: None,
+                };
+
+                Ok((txin.previous_output, output))
+            })
+            .collect::<Result<Vec<(OutPoint, DiscoveredOutput)>>>()?;
+
+        let recipients =
+            psbt.unsigned_tx
+                .output
+                .iter()
+                .zip(psbt.outputs.iter())
+                .enumerate()
+                .map(|(i, (txout, output))| {
+                    let sp_info = output.unknown.get(&raw::Key {
+                        type_value: PSBT_OUT_SP_V0_INFO,
+                        key: vec![],
+                    });
+                    let sp_address = output
+                        .proprietary
+                        .get(&sp_proprietary_key(PSBT_SP_ADDRESS_KEY));
+
+                    let label = output.unknown.get(&raw::Key {
+                        type_value: PSBT_OUT_SP_V0_LABEL,
+                        key: vec![],
+                    });
+
+                    let address = if let Some(sp_address) = sp_address {
+                        let sp_address = SilentPaymentAddressDisplay::try_from(String::from_utf8(
+                            sp_address.clone(),
+                        )?)?;
+                        if sp_address.network() != address_sp_network {
+                            return Err(Error::msg(format!(
+                                "Wrong network for address {}",
+                                sp_address
+                            )));
+                        }
+                        RecipientAddress::SpAddress(sp_address)
+                    } else if let Some(sp_info) = sp_info {
+                        let sp_info: &[u8; 66] = sp_info
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| Error::msg("Invalid silent payment output info"))?;
+                        let sp_address = SilentPaymentAddress::try_from_byte_array_v0(sp_info)?;
+                        RecipientAddress::SpAddress(
+                            sp_address.to_display_for_network(address_sp_network),
+                        )
+                    } else if let Some(data) = op_return_data(&txout.script_pubkey) {
+                        RecipientAddress::Data(data)
+                    } else {
+                        let address =
+                            Address::from_script(&txout.script_pubkey, self.get_network())?;
+                        RecipientAddress::LegacyAddress(address.as_unchecked().clone())
+                    };
+
+                    if let Some(label) = label {
+                        let m =
+                            u32::from_le_bytes(label.as_slice().try_into().map_err(|_| {
+                                Error::msg(format!("Invalid label for output {}", i))
+                            })?);
+                        let own_label = match &address {
+                            RecipientAddress::SpAddress(sp_address) => {
+                                self.own_label_index(&SilentPaymentAddress::from(*sp_address))?
+                            }
+                            _ => None,
+                        };
+                        if own_label != Some(m) {
+                            return Err(Error::msg(format!(
+                                "Label {} doesn't match the address of output {}",
+                                m, i
+                            )));
+                        }
+                    }
+
+                    Ok(Recipient {
+                        address,
+                        amount: txout.value,
+                    })
+                })
+                .collect::<Result<Vec<Recipient>>>()?;
+
+        let input_privkeys = self.get_input_privkeys_for_selected_utxos(&selected_utxos)?;
+
+        // check the global ECDH shares and their DLEQ proofs
+        let input_pubkeys = input_pubkeys(&selected_utxos)?;
+        let input_pubkey_sum =
+            PublicKey::combine_keys(&input_pubkeys.iter().collect::<Vec<&PublicKey>>())?;
+        for (key, value) in psbt.unknown.iter() {
+            if key.type_value != PSBT_GLOBAL_SP_ECDH_SHARE {
+                continue;
+            }
+            let proof = psbt.unknown.get(&raw::Key {
+                type_value: PSBT_GLOBAL_SP_DLEQ,
+                key: key.key.clone(),
+            });
+            check_ecdh_share(
+                &input_privkeys,
+                &input_pubkey_sum,
+                &PublicKey::from_slice(&key.key)?,
+                &PublicKey::from_slice(value)?,
+                proof,
+            )?;
+        }
+
+        // check the per-input ECDH shares and their DLEQ proofs
+        for ((input, input_privkey), input_pubkey) in psbt
+            .inputs
+            .iter()
+            .zip(input_privkeys.iter())
+            .zip(input_pubkeys.iter())
+        {
+            for (key, value) in input.unknown.iter() {
+                if key.type_value != PSBT_IN_SP_ECDH_SHARE {
+                    continue;
+                }
+                let proof = input.unknown.get(&raw::Key {
+                    type_value: PSBT_IN_SP_DLEQ,
+                    key: key.key.clone(),
+                });
+                check_ecdh_share(
+                    &[*input_privkey],
+                    input_pubkey,
+                    &PublicKey::from_slice(&key.key)?,
+                    &PublicKey::from_slice(value)?,
+                    proof,
+                )?;
+            }
+        }
+
+        let partial_secret = self.get_partial_secret_for_selected_utxos(&selected_utxos)?;
+
+        let finalized = Self::finalize_transaction(SilentPaymentUnsignedTransaction {
+            selected_utxos,
+            recipients,
+            partial_secret,
+            unsigned_tx: None,
+            network: self.get_network(),
+        })?;
+
+        // keep the transaction as given, but fill in (or check) the output scripts
+        let computed_outputs = finalized
+            .unsigned_tx
+            .ok_or(Error::msg("Missing unsigned transaction"))?
+            .output;
+
+        let mut unsigned_tx = psbt.unsigned_tx.clone();
+        for (txout, computed) in unsigned_tx.output.iter_mut().zip(computed_outputs) {
+            if txout.script_pubkey.is_empty() {
+                txout.script_pubkey = computed.script_pubkey;
+            } else if txout.script_pubkey != computed.script_pubkey {
+                return Err(Error::msg(format!(
+                    "Output script {} doesn't match recipient",
+                    txout.script_pubkey
+                )));
+            }
+        }
+
+        Ok(SilentPaymentUnsignedTransaction {
+            unsigned_tx: Some(unsigned_tx),
+            ..finalized
+        })
+    }
+
+    /// Same as [unsigned_transaction_to_psbt](SpClient::unsigned_transaction_to_psbt), but serialized as a version 2 PSBT
+    /// as defined in [BIP370](https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki), which is what BIP375 requires.
+    pub fn unsigned_transaction_to_psbt_v2(
+        &self,
+        unsigned_transaction: &SilentPaymentUnsignedTransaction,
+        aux_rand: &[u8; 32],
+    ) -> Result<Vec<u8>> {
+        let psbt = self.unsigned_transaction_to_psbt(unsigned_transaction, aux_rand)?;
+
+        psbt_v2::serialize_v2(&psbt)
+    }
+
+    /// Same as [unsigned_transaction_from_psbt](SpClient::unsigned_transaction_from_psbt), but for a serialized version 2 PSBT.
+    ///
+    /// The `PSBT_OUT_SCRIPT` of a silent payment output may be left out, it is then computed.
+    pub fn unsigned_transaction_from_psbt_v2(
+        &self,
+        psbt: &[u8],
+    ) -> Result<SilentPaymentUnsignedTransaction> {
+        let psbt = psbt_v2::deserialize_v2(psbt)?;
+
+        self.unsigned_transaction_from_psbt(&psbt)
+    }
+
+    /// The index of the label that one of our addresses receives to,
+    /// None if the address isn't ours or has no label.
+    fn own_label_index(&self, address: &SilentPaymentAddress) -> Result<Option<u32>> {
+        let receiver = &self.sp_receiver;
+        for label in receiver.list_labels() {
+            if receiver.get_receiving_address_for_label(&label)? == *address {
+                return Ok(label
+                    .m()
+                    .or_else(|| receiver.get_label_index(label.as_inner())));
+            }
+        }
+
+        Ok(None)
+    }
+}
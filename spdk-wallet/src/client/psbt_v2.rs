@@ -0,0 +1,363 @@
+//! Conversion between the version 0 PSBTs of rust-bitcoin and the version 2 PSBTs from
+//! [BIP370](https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki), which BIP375 requires.
+//!
+//! rust-bitcoin can't parse or serialize version 2 PSBTs, so the conversion works on the raw key-value maps.
+//! Version 2 replaces the global unsigned transaction with per-input and per-output fields,
+//! the other fields are the same in both versions.
+use anyhow::{Error, Result};
+use bitcoin::consensus::encode::{self, VarInt};
+use bitcoin::psbt::Psbt;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, absolute};
+
+use spdk_core::constants::PSBT_OUT_SP_V0_INFO;
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0f;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// The key-value pairs of a PSBT map, in the order they are serialized.
+///
+/// The key includes its type, which is its first byte.
+#[derive(Default)]
+struct Map(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl Map {
+    fn get(&self, key_type: u8) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.as_slice() == [key_type])
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn insert(&mut self, key_type: u8, value: Vec<u8>) {
+        self.0.push((vec![key_type], value));
+    }
+
+    /// Remove the fields of the given types, including the ones with key data.
+    fn remove(&mut self, key_types: &[u8]) {
+        self.0.retain(|(key, _)| !key_types.contains(&key[0]));
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        for (key, value) in &self.0 {
+            buf.extend(encode::serialize(&VarInt(key.len() as u64)));
+            buf.extend_from_slice(key);
+            buf.extend(encode::serialize(&VarInt(value.len() as u64)));
+            buf.extend_from_slice(value);
+        }
+        buf.push(0x00);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self> {
+        let bytes = bytes
+            .strip_prefix(PSBT_MAGIC)
+            .ok_or_else(|| Error::msg("Invalid PSBT magic bytes"))?;
+
+        Ok(Self { bytes })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(Error::msg("Unexpected end of PSBT"));
+        }
+        let (data, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(data)
+    }
+
+    fn var_int(&mut self) -> Result<usize> {
+        let (VarInt(n), len) = encode::deserialize_partial::<VarInt>(self.bytes)?;
+        self.bytes = &self.bytes[len..];
+        Ok(n as usize)
+    }
+
+    fn map(&mut self) -> Result<Map> {
+        let mut map = Map::default();
+        loop {
+            let key_len = self.var_int()?;
+            if key_len == 0 {
+                return Ok(map);
+            }
+            let key = self.take(key_len)?.to_vec();
+            if map.0.iter().any(|(k, _)| *k == key) {
+                return Err(Error::msg("Duplicate key in PSBT"));
+            }
+            let value_len = self.var_int()?;
+            let value = self.take(value_len)?.to_vec();
+            map.0.push((key, value));
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        if !self.bytes.is_empty() {
+            return Err(Error::msg("Trailing data after PSBT"));
+        }
+        Ok(())
+    }
+}
+
+fn var_int_value(value: &[u8]) -> Result<usize> {
+    Ok(encode::deserialize::<VarInt>(value)?.0 as usize)
+}
+
+fn u32_value(value: &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(value.try_into().map_err(|_| {
+        Error::msg("PSBT field must be 4 bytes long")
+    })?))
+}
+
+fn required(map: &Map, key_type: u8, name: &str) -> Result<Vec<u8>> {
+    map.get(key_type)
+        .map(|value| value.to_vec())
+        .ok_or_else(|| Error::msg(format!("Missing {} in PSBT", name)))
+}
+
+/// Serialize a PSBT as version 2.
+pub(super) fn serialize_v2(psbt: &Psbt) -> Result<Vec<u8>> {
+    let v0 = psbt.serialize();
+    let mut reader = Reader::new(&v0)?;
+
+    let tx = &psbt.unsigned_tx;
+
+    let mut global = reader.map()?;
+    global.remove(&[PSBT_GLOBAL_UNSIGNED_TX, PSBT_GLOBAL_VERSION]);
+    global.insert(PSBT_GLOBAL_TX_VERSION, tx.version.0.to_le_bytes().to_vec());
+    global.insert(
+        PSBT_GLOBAL_FALLBACK_LOCKTIME,
+        tx.lock_time.to_consensus_u32().to_le_bytes().to_vec(),
+    );
+    global.insert(
+        PSBT_GLOBAL_INPUT_COUNT,
+        encode::serialize(&VarInt(tx.input.len() as u64)),
+    );
+    global.insert(
+        PSBT_GLOBAL_OUTPUT_COUNT,
+        encode::serialize(&VarInt(tx.output.len() as u64)),
+    );
+    // the silent payment outputs are computed from the inputs, so nothing can be changed anymore
+    global.insert(PSBT_GLOBAL_TX_MODIFIABLE, vec![0x00]);
+    global.insert(PSBT_GLOBAL_VERSION, 2u32.to_le_bytes().to_vec());
+
+    let mut buf = PSBT_MAGIC.to_vec();
+    global.write(&mut buf);
+
+    for txin in &tx.input {
+        let mut input = reader.map()?;
+        input.insert(
+            PSBT_IN_PREVIOUS_TXID,
+            encode::serialize(&txin.previous_output.txid),
+        );
+        input.insert(
+            PSBT_IN_OUTPUT_INDEX,
+            txin.previous_output.vout.to_le_bytes().to_vec(),
+        );
+        input.insert(PSBT_IN_SEQUENCE, txin.sequence.0.to_le_bytes().to_vec());
+        input.write(&mut buf);
+    }
+
+    for txout in &tx.output {
+        let mut output = reader.map()?;
+        output.insert(
+            PSBT_OUT_AMOUNT,
+            (txout.value.to_sat() as i64).to_le_bytes().to_vec(),
+        );
+        output.insert(PSBT_OUT_SCRIPT, txout.script_pubkey.to_bytes());
+        output.write(&mut buf);
+    }
+
+    reader.finish()?;
+
+    Ok(buf)
+}
+
+/// Parse a version 2 PSBT.
+///
+/// The script of a silent payment output may be left out, it is then empty in the unsigned transaction.
+pub(super) fn deserialize_v2(bytes: &[u8]) -> Result<Psbt> {
+    let mut reader = Reader::new(bytes)?;
+
+    let mut global = reader.map()?;
+    match global.get(PSBT_GLOBAL_VERSION).map(u32_value).transpose()? {
+        Some(2) => (),
+        version => {
+            return Err(Error::msg(format!(
+                "Unsupported PSBT version {}, expected version 2",
+                version.unwrap_or(0)
+            )));
+        }
+    }
+    if global.get(PSBT_GLOBAL_UNSIGNED_TX).is_some() {
+        return Err(Error::msg(
+            "Version 2 PSBT can't have an unsigned transaction",
+        ));
+    }
+
+    let version = i32::from_le_bytes(
+        required(&global, PSBT_GLOBAL_TX_VERSION, "transaction version")?
+            .try_into()
+            .map_err(|_| Error::msg("Transaction version must be 4 bytes long"))?,
+    );
+    let fallback_locktime = global
+        .get(PSBT_GLOBAL_FALLBACK_LOCKTIME)
+        .map(u32_value)
+        .transpose()?
+        .unwrap_or(0);
+    let input_count = var_int_value(&required(&global, PSBT_GLOBAL_INPUT_COUNT, "input count")?)?;
+    let output_count = var_int_value(&required(
+        &global,
+        PSBT_GLOBAL_OUTPUT_COUNT,
+        "output count",
+    )?)?;
+
+    let mut inputs = Vec::with_capacity(input_count);
+    let mut txins = Vec::with_capacity(input_count);
+    let mut time_locktimes = Vec::new();
+    let mut height_locktimes = Vec::new();
+    for i in 0..input_count {
+        let mut input = reader.map()?;
+
+        let txid: Txid =
+            encode::deserialize(&required(&input, PSBT_IN_PREVIOUS_TXID, "previous txid")?)?;
+        let vout = u32_value(&required(&input, PSBT_IN_OUTPUT_INDEX, "output index")?)?;
+        let sequence = input
+            .get(PSBT_IN_SEQUENCE)
+            .map(u32_value)
+            .transpose()?
+            .map_or(Sequence::MAX, Sequence);
+
+        let time_locktime = input
+            .get(PSBT_IN_REQUIRED_TIME_LOCKTIME)
+            .map(u32_value)
+            .transpose()?;
+        let height_locktime = input
+            .get(PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)
+            .map(u32_value)
+            .transpose()?;
+        match (time_locktime, height_locktime) {
+            (None, None) => (),
+            (time, height) => {
+                time_locktimes.push(
+                    time.ok_or_else(|| {
+                        Error::msg(format!("Input {} requires a height locktime", i))
+                    }),
+                );
+                height_locktimes
+                    .push(height.ok_or_else(|| {
+                        Error::msg(format!("Input {} requires a time locktime", i))
+                    }));
+            }
+        }
+
+        input.remove(&[
+            PSBT_IN_PREVIOUS_TXID,
+            PSBT_IN_OUTPUT_INDEX,
+            PSBT_IN_SEQUENCE,
+            PSBT_IN_REQUIRED_TIME_LOCKTIME,
+            PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+        ]);
+        inputs.push(input);
+        txins.push(TxIn {
+            previous_output: OutPoint::new(txid, vout),
+            sequence,
+            ..Default::default()
+        });
+    }
+
+    // a height locktime is preferred if the inputs allow both, see BIP370
+    let lock_time = if time_locktimes.is_empty() {
+        fallback_locktime
+    } else if let Ok(heights) = height_locktimes.into_iter().collect::<Result<Vec<u32>>>() {
+        heights.into_iter().max().unwrap_or_default()
+    } else {
+        time_locktimes
+            .into_iter()
+            .collect::<Result<Vec<u32>>>()?
+            .into_iter()
+            .max()
+            .unwrap_or_default()
+    };
+
+    let mut outputs = Vec::with_capacity(output_count);
+    let mut txouts = Vec::with_capacity(output_count);
+    for _ in 0..output_count {
+        let mut output = reader.map()?;
+
+        let amount = i64::from_le_bytes(
+            required(&output, PSBT_OUT_AMOUNT, "output amount")?
+                .try_into()
+                .map_err(|_| Error::msg("Output amount must be 8 bytes long"))?,
+        );
+        let value = Amount::from_sat(
+            u64::try_from(amount).map_err(|_| Error::msg("Negative output amount"))?,
+        );
+
+        let script_pubkey = match output.get(PSBT_OUT_SCRIPT) {
+            Some(script) => ScriptBuf::from_bytes(script.to_vec()),
+            // computed later from the silent payment info
+            None if output.get(PSBT_OUT_SP_V0_INFO).is_some() => ScriptBuf::new(),
+            None => return Err(Error::msg("Missing output script in PSBT")),
+        };
+
+        output.remove(&[PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT]);
+        outputs.push(output);
+        txouts.push(TxOut {
+            value,
+            script_pubkey,
+        });
+    }
+
+    reader.finish()?;
+
+    let unsigned_tx = Transaction {
+        version: Version(version),
+        lock_time: absolute::LockTime::from_consensus(lock_time),
+        input: txins,
+        output: txouts,
+    };
+
+    global.remove(&[
+        PSBT_GLOBAL_TX_VERSION,
+        PSBT_GLOBAL_FALLBACK_LOCKTIME,
+        PSBT_GLOBAL_INPUT_COUNT,
+        PSBT_GLOBAL_OUTPUT_COUNT,
+        PSBT_GLOBAL_TX_MODIFIABLE,
+        PSBT_GLOBAL_VERSION,
+    ]);
+    global.0.insert(
+        0,
+        (
+            vec![PSBT_GLOBAL_UNSIGNED_TX],
+            encode::serialize(&unsigned_tx),
+        ),
+    );
+
+    let mut v0 = PSBT_MAGIC.to_vec();
+    global.write(&mut v0);
+    for map in inputs.iter().chain(outputs.iter()) {
+        map.write(&mut v0);
+    }
+
+    Ok(Psbt::deserialize(&v0)?)
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+
+use anyhow::Result;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use silentpayments::{SharedSecret, receiving::Label, utils as sp_utils};
+
+/// Holds the scan secret key and performs the operations that need it.
+///
+/// This allows the scan key to live outside of the process, e.g. on a hardware device or a remote signer.
+/// [LocalScanKeyProvider] implements this trait for a key that is kept in memory.
+pub trait ScanKeyProvider: Send + Sync {
+    /// Returns the scan public key `B_scan`.
+    fn scan_pubkey(&self) -> Result<PublicKey>;
+
+    /// Returns the label for the integer `m`, see [Label::new].
+    fn label(&self, m: u32) -> Result<Label>;
+
+    /// Computes the ECDH shared secrets `b_scan * tweak` for a batch of tweaks.
+    ///
+    /// The returned secrets must be in the same order as the given tweaks.
+    fn ecdh_batch(&self, tweaks: &[PublicKey]) -> Result<Vec<SharedSecret>>;
+}
+
+/// A [ScanKeyProvider] that keeps the scan secret key in memory.
+#[derive(Clone)]
+pub struct LocalScanKeyProvider {
+    scan_sk: SecretKey,
+}
+
+impl LocalScanKeyProvider {
+    pub fn new(scan_sk: SecretKey) -> Self {
+        Self { scan_sk }
+    }
+}
+
+impl fmt::Debug for LocalScanKeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LocalScanKeyProvider(<redacted>)")
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for LocalScanKeyProvider {
+    fn drop(&mut self) {
+        self.scan_sk.non_secure_erase();
+    }
+}
+
+impl ScanKeyProvider for LocalScanKeyProvider {
+    fn scan_pubkey(&self) -> Result<PublicKey> {
+        let secp = Secp256k1::signing_only();
+        Ok(self.scan_sk.public_key(&secp))
+    }
+
+    fn label(&self, m: u32) -> Result<Label> {
+        Ok(Label::new(self.scan_sk, m))
+    }
+
+    fn ecdh_batch(&self, tweaks: &[PublicKey]) -> Result<Vec<SharedSecret>> {
+        // if using rayon feature, import the preludes
+        #[cfg(feature = "rayon")]
+        use rayon::prelude::*;
+
+        // parallel iterator using rayon
+        #[cfg(feature = "rayon")]
+        let tweak_iterator = tweaks.par_iter();
+
+        // regular iterator
+        #[cfg(not(feature = "rayon"))]
+        let tweak_iterator = tweaks.iter();
+
+        Ok(tweak_iterator
+            .map(|tweak| sp_utils::receiving::calculate_ecdh_shared_secret(tweak, &self.scan_sk))
+            .collect())
+    }
+}
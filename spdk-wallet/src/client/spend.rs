@@ -9,7 +9,7 @@ use bitcoin::absolute::LockTime;
 use bitcoin::hashes::Hash;
 use bitcoin::key::TapTweak;
 use bitcoin::script::PushBytesBuf;
-use bitcoin::secp256k1::{Keypair, Message, Secp256k1};
+use bitcoin::secp256k1::{Keypair, Message, Secp256k1, SecretKey};
 use bitcoin::sighash::{Prevouts, SighashCache};
 use bitcoin::taproot::Signature;
 use bitcoin::transaction::Version;
@@ -282,7 +282,7 @@ impl SpClient {
 
         let sp_address2xonlypubkeys = silentpayments::sending::generate_recipient_pubkeys(
             sp_addresses,
-            unsigned_transaction.partial_secret,
+            unsigned_transaction.partial_secret.clone(),
         )?;
 
         let tx_outs = unsigned_transaction
@@ -444,8 +444,6 @@ impl SpClient {
         &self,
         selected_utxos: &[(OutPoint, DiscoveredOutput)],
     ) -> Result<PartialSecret> {
-        let b_spend = self.try_get_secret_spend_key()?;
-
         let outpoints = selected_utxos
             .iter()
             .map(|(outpoint, _)| {
@@ -455,14 +453,24 @@ impl SpClient {
                 )?)
             })
             .collect::<Result<Vec<sp_utils::OutPoint>>>()?;
-        let input_privkeys = selected_utxos
-            .iter()
-            .map(|(_, output)| Ok((b_spend.add_tweak(&output.tweak)?, true)))
-            .collect::<Result<Vec<_>>>()?;
+        let input_privkeys = self.get_input_privkeys_for_selected_utxos(selected_utxos)?;
 
         let partial_secret =
             sp_utils::sending::calculate_partial_secret(&input_privkeys, &outpoints)?;
 
         Ok(partial_secret)
     }
+
+    /// Get the private keys of the selected utxos, along with whether they are taproot.
+    pub(super) fn get_input_privkeys_for_selected_utxos(
+        &self,
+        selected_utxos: &[(OutPoint, DiscoveredOutput)],
+    ) -> Result<Vec<(SecretKey, bool)>> {
+        let b_spend = self.try_get_secret_spend_key()?;
+
+        selected_utxos
+            .iter()
+            .map(|(_, output)| Ok((b_spend.add_tweak(&output.tweak)?, true)))
+            .collect()
+    }
 }
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 use anyhow::Error;
@@ -64,12 +65,30 @@ pub struct SilentPaymentUnsignedTransaction {
     pub network: Network,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub enum SpendKey {
     Secret(SecretKey),
     Public(PublicKey),
 }
 
+impl fmt::Debug for SpendKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Secret(_) => f.write_str("Secret(<redacted>)"),
+            Self::Public(pk) => f.debug_tuple("Public").field(pk).finish(),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for SpendKey {
+    fn drop(&mut self) {
+        if let Self::Secret(sk) = self {
+            sk.non_secure_erase();
+        }
+    }
+}
+
 impl TryInto<SecretKey> for SpendKey {
     type Error = anyhow::Error;
     fn try_into(self) -> std::prelude::v1::Result<SecretKey, Error> {
@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{Error, Result};
+use bitcoin::{Amount, OutPoint, Transaction, Txid, absolute::Height};
+use serde::{Deserialize, Serialize};
+use silentpayments::SilentPaymentAddress;
+use silentpayments::receiving::Label;
+
+use spdk_core::chain::SpendingTx;
+use spdk_core::updater::DiscoveredOutput;
+
+use super::{OwnedInput, OwnedOutput, TxRecord};
+use crate::client::{RecipientAddress, SilentPaymentUnsignedTransaction, SpClient};
+
+/// The transactions of a wallet, built from the scan results and the transactions that we sign.
+///
+/// The methods for the scan results take the same arguments as the [Updater](spdk_core::updater::Updater),
+/// so an updater can pass its results on to the history.
+/// A spent output is linked to the transaction that spends it if the backend knows the spending transaction,
+/// see [Updater::record_spending_txs](spdk_core::updater::Updater::record_spending_txs), or if we signed it.
+/// Other spends are kept in [unlinked_spends](Self::unlinked_spends).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TxHistoryHelper")]
+pub struct TxHistory {
+    records: Vec<TxRecord>,
+    unlinked_spends: BTreeMap<OutPoint, Height>,
+    // the position of every record, and the record that spends each of our outputs
+    // these are rebuilt from the records when deserializing
+    #[serde(skip)]
+    positions: HashMap<Txid, usize>,
+    #[serde(skip)]
+    spenders: HashMap<OutPoint, Txid>,
+}
+
+#[derive(Deserialize)]
+struct TxHistoryHelper {
+    records: Vec<TxRecord>,
+    unlinked_spends: BTreeMap<OutPoint, Height>,
+}
+
+impl From<TxHistoryHelper> for TxHistory {
+    fn from(helper: TxHistoryHelper) -> Self {
+        let mut history = Self {
+            records: helper.records,
+            unlinked_spends: helper.unlinked_spends,
+            ..Default::default()
+        };
+        history.reindex();
+        history
+    }
+}
+
+impl TxHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The transactions, in the order they were first seen.
+    pub fn records(&self) -> &[TxRecord] {
+        &self.records
+    }
+
+    pub fn get(&self, txid: &Txid) -> Option<&TxRecord> {
+        self.positions.get(txid).map(|&pos| &self.records[pos])
+    }
+
+    /// Our outputs that were spent by an unknown transaction, with the height of the block that spent them.
+    pub fn unlinked_spends(&self) -> &BTreeMap<OutPoint, Height> {
+        &self.unlinked_spends
+    }
+
+    /// See [Updater::record_block_scan_result](spdk_core::updater::Updater::record_block_scan_result).
+    pub fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        discovered_inputs: &HashSet<OutPoint>,
+        discovered_outputs: &HashMap<OutPoint, DiscoveredOutput>,
+    ) {
+        for (outpoint, output) in discovered_outputs {
+            let record = self.record_mut(outpoint.txid);
+            record.add_output(OwnedOutput {
+                vout: outpoint.vout,
+                value: output.value,
+                label: output.label.clone(),
+            });
+            record.confirmation_height = Some(blkheight);
+        }
+
+        for outpoint in discovered_inputs {
+            let signed = self
+                .spenders
+                .get(outpoint)
+                .and_then(|txid| self.positions.get(txid))
+                .map(|&pos| &mut self.records[pos]);
+
+            match signed {
+                Some(record) => record.confirmation_height = Some(blkheight),
+                None => {
+                    self.unlinked_spends.insert(*outpoint, blkheight);
+                }
+            }
+        }
+    }
+
+    /// See [Updater::record_spending_txs](spdk_core::updater::Updater::record_spending_txs).
+    pub fn record_spending_txs(
+        &mut self,
+        blkheight: Height,
+        spending_txs: &HashMap<OutPoint, SpendingTx>,
+    ) {
+        for (outpoint, spending_tx) in spending_txs {
+            self.unlinked_spends.remove(outpoint);
+
+            let value = self.output_value(outpoint);
+            self.spenders.insert(*outpoint, spending_tx.txid);
+            let record = self.record_mut(spending_tx.txid);
+            record.add_input(OwnedInput {
+                outpoint: *outpoint,
+                value,
+            });
+            record.confirmation_height = Some(blkheight);
+        }
+    }
+
+    /// See [Updater::rollback_to_block](spdk_core::updater::Updater::rollback_to_block).
+    ///
+    /// The transactions that we signed are kept as unconfirmed, the others are removed until they are scanned again.
+    pub fn rollback_to_block(&mut self, blkheight: Height) {
+        self.unlinked_spends
+            .retain(|_, height| *height <= blkheight);
+
+        self.records
+            .retain_mut(|record| match record.confirmation_height {
+                Some(height) if height > blkheight => {
+                    record.confirmation_height = None;
+                    record.is_signed_by_us()
+                }
+                _ => true,
+            });
+        self.reindex();
+    }
+
+    /// See [Updater::record_mempool_scan_result](spdk_core::updater::Updater::record_mempool_scan_result).
+    ///
+    /// The outputs that are found become unconfirmed transactions. Dropped outputs are removed,
+    /// except from the transactions that we signed.
+    pub fn record_mempool_scan_result(
+        &mut self,
+        new_outputs: &HashMap<OutPoint, DiscoveredOutput>,
+        dropped_outputs: &HashSet<OutPoint>,
+    ) {
+        for (outpoint, output) in new_outputs {
+            self.record_mut(outpoint.txid).add_output(OwnedOutput {
+                vout: outpoint.vout,
+                value: output.value,
+                label: output.label.clone(),
+            });
+        }
+
+        for record in self.records.iter_mut() {
+            if record.confirmation_height.is_none() && !record.is_signed_by_us() {
+                record.outputs.retain(|output| {
+                    !dropped_outputs.contains(&OutPoint::new(record.txid, output.vout))
+                });
+            }
+        }
+
+        self.records.retain(|record| {
+            record.is_signed_by_us() || !record.outputs.is_empty() || !record.inputs.is_empty()
+        });
+        self.reindex();
+    }
+
+    /// Add a transaction that was signed with [SpClient::sign_transaction], returns its txid.
+    ///
+    /// All the inputs are ours, so the fee is known. The outputs to our own addresses,
+    /// including the change, are ours and the others are kept as recipients.
+    /// If the transaction was already found by a scan, the record is completed.
+    pub fn record_signed_transaction(
+        &mut self,
+        client: &SpClient,
+        unsigned_tx: &SilentPaymentUnsignedTransaction,
+        signed_tx: &Transaction,
+    ) -> Result<Txid> {
+        let txid = signed_tx.compute_txid();
+
+        match unsigned_tx.unsigned_tx.as_ref() {
+            Some(tx) if tx.compute_txid() == txid => (),
+            Some(_) => {
+                return Err(Error::msg(
+                    "The signed transaction doesn't match the unsigned transaction",
+                ));
+            }
+            None => return Err(Error::msg("Missing unsigned transaction")),
+        }
+
+        if signed_tx.output.len() != unsigned_tx.recipients.len() {
+            return Err(Error::msg("Every output must have a recipient"));
+        }
+
+        let own_addresses = own_addresses(client)?;
+
+        let mut outputs = vec![];
+        let mut recipients = vec![];
+        for (vout, (recipient, txout)) in unsigned_tx
+            .recipients
+            .iter()
+            .zip(&signed_tx.output)
+            .enumerate()
+        {
+            let own_label = match &recipient.address {
+                RecipientAddress::SpAddress(address) => {
+                    own_addresses.get(&SilentPaymentAddress::from(*address))
+                }
+                _ => None,
+            };
+
+            match own_label {
+                Some(label) => outputs.push(OwnedOutput {
+                    vout: vout as u32,
+                    value: txout.value,
+                    label: label.clone(),
+                }),
+                None => recipients.push(recipient.clone()),
+            }
+        }
+
+        let inputs_value: Amount = unsigned_tx
+            .selected_utxos
+            .iter()
+            .map(|(_, output)| output.value)
+            .sum();
+        let outputs_value: Amount = signed_tx.output.iter().map(|output| output.value).sum();
+        let fee = inputs_value
+            .checked_sub(outputs_value)
+            .ok_or_else(|| Error::msg("The outputs are worth more than the inputs"))?;
+
+        // the spends may have been found by a scan before
+        let mut confirmation_height = None;
+        for (outpoint, _) in &unsigned_tx.selected_utxos {
+            if let Some(height) = self.unlinked_spends.remove(outpoint) {
+                confirmation_height = confirmation_height.max(Some(height));
+            }
+        }
+
+        for (outpoint, _) in &unsigned_tx.selected_utxos {
+            self.spenders.insert(*outpoint, txid);
+        }
+
+        let record = self.record_mut(txid);
+        for (outpoint, output) in &unsigned_tx.selected_utxos {
+            record.add_input(OwnedInput {
+                outpoint: *outpoint,
+                value: Some(output.value),
+            });
+        }
+        for output in outputs {
+            record.add_output(output);
+        }
+        record.recipients = recipients;
+        record.fee = Some(fee);
+        record.confirmation_height = record.confirmation_height.or(confirmation_height);
+
+        Ok(txid)
+    }
+
+    fn record_mut(&mut self, txid: Txid) -> &mut TxRecord {
+        let pos = match self.positions.get(&txid) {
+            Some(&pos) => pos,
+            None => {
+                self.records.push(TxRecord::new(txid));
+                let pos = self.records.len() - 1;
+                self.positions.insert(txid, pos);
+                pos
+            }
+        };
+
+        &mut self.records[pos]
+    }
+
+    /// Rebuild the indexes after records were removed.
+    fn reindex(&mut self) {
+        self.positions = self
+            .records
+            .iter()
+            .enumerate()
+            .map(|(pos, record)| (record.txid, pos))
+            .collect();
+        self.spenders = self
+            .records
+            .iter()
+            .flat_map(|record| {
+                record
+                    .inputs
+                    .iter()
+                    .map(move |input| (input.outpoint, record.txid))
+            })
+            .collect();
+    }
+
+    /// The value of one of our outputs, if it is in the history.
+    fn output_value(&self, outpoint: &OutPoint) -> Option<Amount> {
+        self.get(&outpoint.txid)?
+            .outputs
+            .iter()
+            .find(|output| output.vout == outpoint.vout)
+            .map(|output| output.value)
+    }
+}
+
+/// Our addresses, with the label that they receive to.
+fn own_addresses(client: &SpClient) -> Result<HashMap<SilentPaymentAddress, Option<Label>>> {
+    let receiver = &client.sp_receiver;
+
+    let mut addresses = HashMap::from([(receiver.get_receiving_address(), None)]);
+    for label in receiver.list_labels() {
+        let address = receiver.get_receiving_address_for_label(&label)?;
+        addresses.insert(address, Some(label));
+    }
+
+    Ok(addresses)
+}
@@ -0,0 +1,5 @@
+mod history;
+mod structs;
+
+pub use history::TxHistory;
+pub use structs::*;
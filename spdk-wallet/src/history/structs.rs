@@ -0,0 +1,123 @@
+use bitcoin::absolute::Height;
+use bitcoin::{Amount, OutPoint, SignedAmount, Txid};
+use serde::{Deserialize, Serialize};
+use silentpayments::receiving::Label;
+
+use crate::client::{Recipient, RecipientAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxDirection {
+    /// We received outputs, without spending any of ours.
+    Incoming,
+    /// We spent our outputs, and paid someone else.
+    Outgoing,
+    /// We spent our outputs, and everything except the fee came back to us.
+    /// Data outputs don't pay anyone, so they can be part of a self transfer.
+    SelfTransfer,
+}
+
+/// An output of a transaction that we own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedOutput {
+    pub vout: u32,
+    pub value: Amount,
+    pub label: Option<Label>,
+}
+
+/// An input of a transaction that spends one of our outputs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedInput {
+    pub outpoint: OutPoint,
+    /// None if the output was received before the history started.
+    pub value: Option<Amount>,
+}
+
+/// A transaction of the wallet, as far as we know it.
+///
+/// Scanning only finds our own outputs and inputs. The fee and the payments to others
+/// are only known for transactions that we signed, see [TxHistory::record_signed_transaction](super::TxHistory::record_signed_transaction).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxRecord {
+    pub txid: Txid,
+    pub outputs: Vec<OwnedOutput>,
+    pub inputs: Vec<OwnedInput>,
+    /// The payments to others, only known for transactions that we signed.
+    pub recipients: Vec<Recipient>,
+    pub fee: Option<Amount>,
+    /// None while the transaction is unconfirmed.
+    pub confirmation_height: Option<Height>,
+}
+
+impl TxRecord {
+    pub(super) fn new(txid: Txid) -> Self {
+        Self {
+            txid,
+            outputs: vec![],
+            inputs: vec![],
+            recipients: vec![],
+            fee: None,
+            confirmation_height: None,
+        }
+    }
+
+    /// True if the transaction was added with [record_signed_transaction](super::TxHistory::record_signed_transaction).
+    pub fn is_signed_by_us(&self) -> bool {
+        self.fee.is_some()
+    }
+
+    pub fn direction(&self) -> TxDirection {
+        let pays_others = self.recipients.iter().any(|recipient| {
+            recipient.amount > Amount::ZERO
+                && !matches!(recipient.address, RecipientAddress::Data(_))
+        });
+
+        if self.inputs.is_empty() {
+            TxDirection::Incoming
+        } else if self.is_signed_by_us() && !pays_others {
+            TxDirection::SelfTransfer
+        } else {
+            TxDirection::Outgoing
+        }
+    }
+
+    /// The total value of our outputs.
+    pub fn received(&self) -> Amount {
+        self.outputs.iter().map(|output| output.value).sum()
+    }
+
+    /// The total value of our inputs, leaving out the inputs of unknown value.
+    pub fn sent(&self) -> Amount {
+        self.inputs.iter().filter_map(|input| input.value).sum()
+    }
+
+    /// The change to our balance, including the fee.
+    pub fn net(&self) -> SignedAmount {
+        // amounts of bitcoin always fit in an i64
+        SignedAmount::from_sat(self.received().to_sat() as i64 - self.sent().to_sat() as i64)
+    }
+
+    /// The labels of the addresses that received our outputs.
+    pub fn labels(&self) -> impl Iterator<Item = &Label> {
+        self.outputs
+            .iter()
+            .filter_map(|output| output.label.as_ref())
+    }
+
+    pub(super) fn add_output(&mut self, output: OwnedOutput) {
+        if !self.outputs.iter().any(|known| known.vout == output.vout) {
+            self.outputs.push(output);
+            self.outputs.sort_by_key(|output| output.vout);
+        }
+    }
+
+    pub(super) fn add_input(&mut self, input: OwnedInput) {
+        match self
+            .inputs
+            .iter_mut()
+            .find(|known| known.outpoint == input.outpoint)
+        {
+            Some(known) => known.value = known.value.or(input.value),
+            None => self.inputs.push(input),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod client;
+pub mod history;
 pub mod scanner;
 
 // re-export traits for consumers who need to provide valid implementors
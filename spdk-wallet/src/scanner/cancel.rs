@@ -0,0 +1,100 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::pin,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result};
+use futures::{
+    channel::oneshot,
+    future::{self, Either, FutureExt, Shared},
+};
+
+/// A handle to cancel a scan, that can be cloned and moved to other tasks.
+///
+/// Cancelling aborts the pending requests to the chain backend. Blocks are recorded as a whole,
+/// so the [Updater](spdk_core::updater::Updater) is left at the last fully scanned block.
+/// A cancelled token stays cancelled, a new scan needs a new token,
+/// see [SpScanner::set_cancellation_token](super::SpScanner::set_cancellation_token).
+///
+/// A cancelled scan returns `Ok(())`, use [is_cancelled](Self::is_cancelled) to tell it apart from a finished scan.
+#[derive(Clone)]
+pub struct CancellationToken {
+    // dropping the sender resolves all the receivers
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = oneshot::channel();
+
+        Self {
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: receiver.shared(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.lock().unwrap().take();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.lock().unwrap().is_none()
+    }
+
+    /// Completes when the token is cancelled.
+    pub async fn cancelled(&self) {
+        // the sender is never used, so this always returns an error once it is dropped
+        let _ = self.receiver.clone().await;
+    }
+
+    /// Run `fut` until it completes, or until the token is cancelled.
+    ///
+    /// Returns a [Cancelled] error if the token is cancelled first.
+    pub(super) async fn run<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        if self.is_cancelled() {
+            return Err(Error::new(Cancelled));
+        }
+
+        match future::select(pin!(fut), pin!(self.cancelled())).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => Err(Error::new(Cancelled)),
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// The error of a request that was aborted by a [CancellationToken].
+#[derive(Debug)]
+pub(super) struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The scan was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// A cancelled scan is not an error, the results up to the cancellation are recorded.
+pub(super) fn ignore_cancelled(res: Result<()>) -> Result<()> {
+    match res {
+        Err(e) if e.is::<Cancelled>() => Ok(()),
+        res => res,
+    }
+}
@@ -1,3 +1,12 @@
+mod cancel;
+mod multi;
+mod progress;
 mod scanner;
+mod updater;
+mod wallet;
 
+pub use cancel::CancellationToken;
+pub use multi::SpMultiScanner;
+pub use progress::ScanProgress;
 pub use scanner::SpScanner;
+pub use wallet::ScanWallet;
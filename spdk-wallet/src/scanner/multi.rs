@@ -0,0 +1,572 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+};
+
+use anyhow::{Error, Result};
+use bitcoin::{Amount, BlockHash, OutPoint, absolute::Height, secp256k1::PublicKey};
+use futures::{Stream, StreamExt, channel::mpsc::UnboundedReceiver, pin_mut};
+use log::info;
+
+use spdk_core::chain::{BlockData, ChainBackend, SpendingTx};
+use spdk_core::updater::DiscoveredOutput;
+
+use super::cancel::{CancellationToken, ignore_cancelled};
+use super::progress::{ProgressSenders, ProgressTracker, ScanProgress};
+use super::wallet::ScanWallet;
+
+// the number of scanned blocks that are kept to handle reorgs
+const MAX_REORG_DEPTH: usize = 100;
+
+/// The outpoints that were found for a wallet in a block.
+#[derive(Default)]
+struct WalletChanges {
+    outputs: HashSet<OutPoint>,
+    inputs: HashSet<OutPoint>,
+}
+
+/// A scanned block, with the outpoints that were found in it for every wallet.
+struct ScannedBlock {
+    blkhash: BlockHash,
+    changes: Vec<WalletChanges>, // empty for checkpoints
+}
+
+impl ScannedBlock {
+    fn checkpoint(blkhash: BlockHash) -> Self {
+        Self {
+            blkhash,
+            changes: vec![],
+        }
+    }
+}
+
+/// The discovered outputs and inputs of a wallet in a block.
+#[derive(Clone, Default)]
+struct WalletResults {
+    outputs: HashMap<OutPoint, DiscoveredOutput>,
+    inputs: HashSet<OutPoint>,
+    // the transactions that spend the inputs, if the backend knows them
+    spending_txs: HashMap<OutPoint, SpendingTx>,
+}
+
+/// A scanner for many wallets, that fetches the data of every block only once.
+///
+/// The tweaks and filters of a block are shared by all wallets, but the shared secrets and filter matches
+/// are computed for every wallet. The utxos and spent index of a block are fetched once when any wallet matches.
+/// Every wallet passes its results to its own updater.
+///
+/// All wallets are scanned over the same blocks, so they should have been scanned up to the same height.
+/// [SpScanner](super::SpScanner) is the same scanner for a single wallet, see its documentation for the details.
+pub struct SpMultiScanner {
+    wallets: Vec<ScanWallet>,
+    backend: Box<dyn ChainBackend + Sync + Send>,
+    cancel: CancellationToken, // used to interrupt scanning
+    scanned_blocks: BTreeMap<Height, ScannedBlock>, // used to detect reorgs
+    progress: ProgressSenders,
+}
+
+impl SpMultiScanner {
+    pub fn new(
+        wallets: Vec<ScanWallet>,
+        backend: Box<dyn ChainBackend + Sync + Send>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            wallets,
+            backend,
+            cancel,
+            scanned_blocks: BTreeMap::new(),
+            progress: ProgressSenders::default(),
+        }
+    }
+
+    /// See [SpScanner::with_checkpoints](super::SpScanner::with_checkpoints).
+    pub fn with_checkpoints(
+        mut self,
+        checkpoints: impl IntoIterator<Item = (Height, BlockHash)>,
+    ) -> Self {
+        for (blkheight, blkhash) in checkpoints {
+            self.insert_scanned_block(blkheight, ScannedBlock::checkpoint(blkhash));
+        }
+        self
+    }
+
+    /// See [SpScanner::set_cancellation_token](super::SpScanner::set_cancellation_token).
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        self.cancel = cancel;
+    }
+
+    /// See [SpScanner::progress](super::SpScanner::progress).
+    pub fn progress(&mut self) -> UnboundedReceiver<ScanProgress> {
+        self.progress.subscribe()
+    }
+
+    pub fn wallets(&self) -> &[ScanWallet] {
+        &self.wallets
+    }
+
+    pub(super) fn wallets_mut(&mut self) -> &mut [ScanWallet] {
+        &mut self.wallets
+    }
+
+    /// The last block that was fully processed.
+    pub fn last_scanned_block(&self) -> Option<(Height, BlockHash)> {
+        self.scanned_blocks
+            .last_key_value()
+            .map(|(blkheight, scanned)| (*blkheight, scanned.blkhash))
+    }
+
+    /// See [SpScanner::scan_to_tip](super::SpScanner::scan_to_tip).
+    ///
+    /// Like there, a cancelled scan returns `Ok(())`.
+    pub async fn scan_to_tip(&mut self, dust_limit: Amount, with_cutthrough: bool) -> Result<()> {
+        ignore_cancelled(self.scan_to_tip_inner(dust_limit, with_cutthrough).await)
+    }
+
+    async fn scan_to_tip_inner(&mut self, dust_limit: Amount, with_cutthrough: bool) -> Result<()> {
+        let (last_scanned, _) = self
+            .last_scanned_block()
+            .ok_or_else(|| Error::msg("No checkpoint to resume scanning from"))?;
+        let tip = self.cancel.run(self.backend.block_height()).await?;
+
+        let mut start = next_height(last_scanned)?;
+
+        // the chain got shorter, the blocks above the tip were replaced
+        if last_scanned > tip {
+            let replaced_height = next_height(tip)?;
+            let replaced_height = self
+                .scanned_blocks
+                .range(replaced_height..)
+                .next()
+                .map(|(blkheight, _)| *blkheight)
+                .unwrap_or(replaced_height);
+            start = next_height(self.handle_reorg(replaced_height).await?)?;
+        }
+
+        // an empty range still checks the last scanned block for reorgs
+        self.scan_blocks_inner(start..=tip, dust_limit, with_cutthrough)
+            .await
+    }
+
+    /// See [SpScanner::follow_tip](super::SpScanner::follow_tip).
+    ///
+    /// Like there, a cancelled scan returns `Ok(())`.
+    pub async fn follow_tip(
+        &mut self,
+        ticks: impl Stream,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<()> {
+        pin_mut!(ticks);
+
+        let res = async {
+            loop {
+                if self.interrupt_requested() {
+                    break;
+                }
+
+                self.scan_to_tip_inner(dust_limit, with_cutthrough).await?;
+
+                if self
+                    .cancel
+                    .run(async { Ok(ticks.next().await) })
+                    .await?
+                    .is_none()
+                {
+                    break;
+                }
+            }
+
+            Ok(())
+        };
+
+        ignore_cancelled(res.await)
+    }
+
+    /// See [SpScanner::scan_mempool](super::SpScanner::scan_mempool).
+    ///
+    /// Like there, a cancelled scan returns `Ok(())`.
+    ///
+    /// The transactions in the mempool are fetched once, and scanned for every wallet.
+    pub async fn scan_mempool(&mut self, dust_limit: Amount) -> Result<()> {
+        ignore_cancelled(self.scan_mempool_inner(dust_limit).await)
+    }
+
+    async fn scan_mempool_inner(&mut self, dust_limit: Amount) -> Result<()> {
+        let txs = self.cancel.run(self.backend.mempool(dust_limit)).await?;
+
+        // the tip is fetched after the mempool, so a transaction that left the mempool because it was mined
+        // is in a block up to the tip
+        let tip = self.cancel.run(self.backend.block_height()).await?;
+        let caught_up = self
+            .last_scanned_block()
+            .is_some_and(|(last_scanned, _)| last_scanned >= tip);
+
+        let tweaks: Vec<PublicKey> = txs.iter().map(|tx| tx.tweak).collect();
+        let utxos: Vec<_> = txs.into_iter().flat_map(|tx| tx.outputs).collect();
+
+        for wallet in self.wallets.iter_mut() {
+            let mut found = HashMap::new();
+            if !tweaks.is_empty() {
+                let secrets_map = wallet.get_script_to_secret_map(tweaks.clone())?;
+                found = wallet.find_owned_utxos(&utxos, &secrets_map)?;
+            }
+
+            let (new_outputs, dropped_outputs) = wallet.update_pending_outputs(found, caught_up);
+
+            info!(
+                "mempool: {} new pending outputs, {} dropped",
+                new_outputs.len(),
+                dropped_outputs.len()
+            );
+
+            wallet
+                .updater
+                .record_mempool_scan_result(new_outputs, dropped_outputs)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// See [SpScanner::scan_blocks](super::SpScanner::scan_blocks).
+    ///
+    /// Like there, a cancelled scan returns `Ok(())`.
+    ///
+    /// If the updater of a wallet returns an error, the scan stops for all wallets.
+    /// The wallets before it have recorded the last block, the wallets after it haven't.
+    pub async fn scan_blocks(
+        &mut self,
+        range: RangeInclusive<Height>,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<()> {
+        ignore_cancelled(
+            self.scan_blocks_inner(range, dust_limit, with_cutthrough)
+                .await,
+        )
+    }
+
+    async fn scan_blocks_inner(
+        &mut self,
+        range: RangeInclusive<Height>,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<()> {
+        info!(
+            "start: {} end: {}",
+            range.start().to_consensus_u32(),
+            range.end().to_consensus_u32(),
+        );
+        let (mut start, end) = range.into_inner();
+
+        // the last scanned block may have been replaced since the previous scan
+        if let Some(fork_height) = self.check_last_scanned_block().await? {
+            start = start.min(next_height(fork_height)?);
+        }
+
+        let mut tracker = ProgressTracker::new(start, end);
+
+        loop {
+            // get block data stream
+            let block_data_stream =
+                self.backend
+                    .get_block_data_for_range(start..=end, dust_limit, with_cutthrough);
+
+            // process blocks using block data stream, until a replaced block is found
+            match self.process_blocks(block_data_stream, &mut tracker).await? {
+                Some(replaced_height) => {
+                    let fork_height = self.handle_reorg(replaced_height).await?;
+                    start = next_height(fork_height)?;
+                    tracker.restart_from(start);
+                }
+                None => break,
+            }
+        }
+
+        info!(
+            "Total number of tweaks processed: {}",
+            tracker.tweaks_processed()
+        );
+
+        // time elapsed for the scan
+        info!("Scan complete in {} seconds", tracker.elapsed().as_secs());
+
+        Ok(())
+    }
+
+    /// Process the blocks of the stream.
+    ///
+    /// Returns the height of the first block that replaced a scanned block, if any.
+    /// This block and the ones after it are not processed.
+    async fn process_blocks(
+        &mut self,
+        block_data_stream: impl Stream<Item = Result<BlockData>>,
+        tracker: &mut ProgressTracker,
+    ) -> Result<Option<Height>> {
+        pin_mut!(block_data_stream);
+
+        while let Some(blockdata) = self
+            .cancel
+            .run(async { Ok(block_data_stream.next().await) })
+            .await?
+        {
+            // stop scanning and return if interrupted
+            if self.interrupt_requested() {
+                break;
+            }
+
+            let blockdata = blockdata?;
+            let blkhash = blockdata.blkhash;
+            let blkheight = blockdata.blkheight;
+
+            if let Some(scanned) = self.scanned_blocks.get(&blkheight)
+                && scanned.blkhash != blkhash
+            {
+                info!("block {} was replaced by {}", scanned.blkhash, blkhash);
+                return Ok(Some(blkheight));
+            }
+
+            let tweak_count = blockdata.tweaks.len();
+
+            // from here on, the block is recorded as a whole
+            let (results, matched) = self.process_block(blockdata).await?;
+
+            let changes = results
+                .iter()
+                .map(|results| WalletChanges {
+                    outputs: results.outputs.keys().cloned().collect(),
+                    inputs: results.inputs.clone(),
+                })
+                .collect();
+
+            // the block is tracked before the updaters run, so that a reorg rolls it back for every wallet,
+            // even if one of the updaters fails
+            self.insert_scanned_block(blkheight, ScannedBlock { blkhash, changes });
+
+            for (wallet, results) in self.wallets.iter_mut().zip(results) {
+                let WalletResults {
+                    outputs: discovered_outputs,
+                    inputs: discovered_inputs,
+                    spending_txs,
+                } = results;
+
+                let new_outpoints: Vec<OutPoint> = discovered_outputs.keys().cloned().collect();
+                let spent_outpoints = discovered_inputs.clone();
+
+                wallet
+                    .updater
+                    .record_block_scan_result(
+                        blkheight,
+                        blkhash,
+                        discovered_inputs,
+                        discovered_outputs,
+                    )
+                    .await?;
+                if !spending_txs.is_empty() {
+                    wallet
+                        .updater
+                        .record_spending_txs(blkheight, spending_txs)
+                        .await?;
+                }
+                wallet.updater.record_progress(blkheight, blkhash).await?;
+
+                // only once the updater has the block: add the found outputs to our list, and remove the spent ones
+                wallet.owned_outpoints.extend(&new_outpoints);
+                wallet
+                    .owned_outpoints
+                    .retain(|outpoint| !spent_outpoints.contains(outpoint));
+
+                // pending outputs that are confirmed
+                wallet
+                    .pending_outputs
+                    .retain(|outpoint| !new_outpoints.contains(outpoint));
+            }
+
+            let progress = tracker.block_scanned(blkheight, tweak_count, matched);
+            self.progress.send(&progress);
+        }
+
+        Ok(None)
+    }
+
+    /// Check that the last scanned block is still part of the chain.
+    ///
+    /// If it isn't, the reorg is handled and the height of the fork is returned.
+    async fn check_last_scanned_block(&mut self) -> Result<Option<Height>> {
+        let (blkheight, blkhash) = match self.scanned_blocks.last_key_value() {
+            Some((blkheight, scanned)) => (*blkheight, scanned.blkhash),
+            None => return Ok(None),
+        };
+
+        if self.cancel.run(self.backend.block_hash(blkheight)).await? == blkhash {
+            return Ok(None);
+        }
+
+        info!("block {} was replaced", blkhash);
+        Ok(Some(self.handle_reorg(blkheight).await?))
+    }
+
+    /// Find the last scanned block below `replaced_height` that is still part of the chain,
+    /// and roll back the results of the blocks above it.
+    ///
+    /// Returns the height of the fork.
+    async fn handle_reorg(&mut self, replaced_height: Height) -> Result<Height> {
+        let mut fork = None;
+        for (blkheight, scanned) in self.scanned_blocks.range(..replaced_height).rev() {
+            if self.cancel.run(self.backend.block_hash(*blkheight)).await? == scanned.blkhash {
+                fork = Some((*blkheight, scanned.blkhash));
+                break;
+            }
+        }
+
+        let (fork_height, fork_hash) = match fork {
+            Some(fork) => fork,
+            None if self.scanned_blocks.len() >= MAX_REORG_DEPTH => {
+                return Err(Error::msg(format!(
+                    "Reorg at height {} is deeper than {} blocks",
+                    replaced_height, MAX_REORG_DEPTH
+                )));
+            }
+            // the hashes below the first tracked block are unknown, so the fork can't be found there
+            None => {
+                return Err(Error::msg(format!(
+                    "Reorg at height {} replaced every tracked block, the fork is unknown",
+                    replaced_height
+                )));
+            }
+        };
+
+        info!(
+            "rolling back to block {} at height {}",
+            fork_hash, fork_height
+        );
+
+        // the fork is known, the rollback is not interrupted
+
+        // undo the changes to the owned outpoints, starting from the last block
+        let orphaned = self.scanned_blocks.split_off(&next_height(fork_height)?);
+        for scanned in orphaned.into_values().rev() {
+            for (wallet, changes) in self.wallets.iter_mut().zip(scanned.changes) {
+                wallet
+                    .owned_outpoints
+                    .retain(|outpoint| !changes.outputs.contains(outpoint));
+                wallet.owned_outpoints.extend(changes.inputs);
+            }
+        }
+
+        for wallet in self.wallets.iter_mut() {
+            wallet
+                .updater
+                .rollback_to_block(fork_height, fork_hash)
+                .await?;
+            wallet
+                .updater
+                .record_progress(fork_height, fork_hash)
+                .await?;
+        }
+
+        Ok(fork_height)
+    }
+
+    fn insert_scanned_block(&mut self, blkheight: Height, scanned: ScannedBlock) {
+        self.scanned_blocks.insert(blkheight, scanned);
+
+        while self.scanned_blocks.len() > MAX_REORG_DEPTH {
+            self.scanned_blocks.pop_first();
+        }
+    }
+
+    /// Find the outputs and inputs of every wallet in a block.
+    ///
+    /// The utxos and the spent index are only fetched if the filters match for any of the wallets,
+    /// in which case the block is matched. The wallets are not changed, so this can be cancelled.
+    async fn process_block(&self, blockdata: BlockData) -> Result<(Vec<WalletResults>, bool)> {
+        let BlockData {
+            blkheight,
+            tweaks,
+            new_utxo_filter,
+            spent_filter,
+            ..
+        } = blockdata;
+
+        let mut results = vec![WalletResults::default(); self.wallets.len()];
+
+        // the shared secrets of the wallets whose outputs may be in this block
+        let mut matched_outputs = vec![];
+        for (i, wallet) in self.wallets.iter().enumerate() {
+            if let Some(secrets_map) = wallet.match_outputs(&tweaks, &new_utxo_filter)? {
+                matched_outputs.push((i, secrets_map));
+            }
+        }
+
+        let mut matched = !matched_outputs.is_empty();
+
+        //if match: fetch and scan utxos
+        if matched {
+            info!(
+                "matched outputs on: {} for {} wallets",
+                blkheight,
+                matched_outputs.len()
+            );
+            let utxos = self.cancel.run(self.backend.utxos(blkheight)).await?;
+
+            for (i, secrets_map) in matched_outputs {
+                results[i].outputs = self.wallets[i].find_owned_utxos(&utxos, &secrets_map)?;
+            }
+        }
+
+        // the hashes of the owned outputs of the wallets whose inputs may be in this block,
+        // including the outputs that were just found
+        let mut matched_inputs = vec![];
+        for (i, wallet) in self.wallets.iter().enumerate() {
+            let new_outputs = results[i].outputs.keys();
+            if let Some(input_hashes_map) = wallet.match_inputs(&spent_filter, new_outputs)? {
+                matched_inputs.push((i, input_hashes_map));
+            }
+        }
+
+        // if match: download spent data, collect the outpoints that are spent
+        if !matched_inputs.is_empty() {
+            matched = true;
+            info!(
+                "matched inputs on: {} for {} wallets",
+                blkheight,
+                matched_inputs.len()
+            );
+            let spent = self.cancel.run(self.backend.spent_index(blkheight)).await?;
+
+            // the spends would be linked to the wrong transactions
+            if !spent.spending_txs.is_empty() && spent.spending_txs.len() != spent.data.len() {
+                return Err(Error::msg(format!(
+                    "Spent index of block {} has {} outputs but {} spending transactions",
+                    blkheight,
+                    spent.data.len(),
+                    spent.spending_txs.len()
+                )));
+            }
+
+            for (i, input_hashes_map) in matched_inputs {
+                for (j, hash) in spent.data.iter().enumerate() {
+                    let hex: &[u8] = hash.as_ref();
+                    if let Some(outpoint) = input_hashes_map.get(hex) {
+                        results[i].inputs.insert(*outpoint);
+                        if let Some(spending_tx) = spent.spending_txs.get(j) {
+                            results[i].spending_txs.insert(*outpoint, *spending_tx);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok((results, matched))
+    }
+
+    fn interrupt_requested(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
+
+fn next_height(height: Height) -> Result<Height> {
+    Ok(Height::from_consensus(height.to_consensus_u32() + 1)?)
+}
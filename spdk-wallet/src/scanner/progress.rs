@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use bitcoin::absolute::Height;
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded};
+
+/// The progress of a call to [scan_blocks](super::SpScanner::scan_blocks), sent after every scanned block.
+///
+/// If a reorg is found during the scan, the blocks above the fork are scanned again,
+/// so `blocks_done` can go down.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanProgress {
+    /// The height of the block that was just scanned.
+    pub blkheight: Height,
+    /// The number of blocks of the range up to and including `blkheight`.
+    pub blocks_done: u32,
+    /// The number of blocks in the range.
+    pub blocks_total: u32,
+    /// The number of tweaks in the blocks that were scanned so far.
+    pub tweaks_processed: usize,
+    /// The number of blocks where the filters matched for any wallet, so the utxos or spent index were fetched.
+    pub matched_blocks: u32,
+    /// The time since the scan started.
+    pub elapsed: Duration,
+    // the number of blocks that were processed, including blocks that were scanned again after a reorg
+    processed: u32,
+}
+
+impl ScanProgress {
+    /// The number of blocks that are scanned per second.
+    pub fn blocks_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.processed as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// The estimated time until the end of the range is scanned, based on the blocks per second so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.processed == 0 {
+            return None;
+        }
+
+        let remaining = self.blocks_total.saturating_sub(self.blocks_done);
+        Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.processed as f64),
+        )
+    }
+}
+
+/// Tracks the progress of a scan, and sends it to the subscribers.
+pub(super) struct ProgressTracker {
+    start: Height,
+    end: Height,
+    start_time: Instant,
+    tweaks_processed: usize,
+    matched_blocks: u32,
+    processed: u32,
+}
+
+impl ProgressTracker {
+    pub(super) fn new(start: Height, end: Height) -> Self {
+        Self {
+            start,
+            end,
+            start_time: Instant::now(),
+            tweaks_processed: 0,
+            matched_blocks: 0,
+            processed: 0,
+        }
+    }
+
+    /// Blocks from `start` are scanned again, e.g. after a reorg.
+    pub(super) fn restart_from(&mut self, start: Height) {
+        self.start = self.start.min(start);
+    }
+
+    pub(super) fn tweaks_processed(&self) -> usize {
+        self.tweaks_processed
+    }
+
+    pub(super) fn elapsed(&self) -> Duration {
+        self.start_time.elapsed()
+    }
+
+    /// Record a scanned block, and return the progress.
+    pub(super) fn block_scanned(
+        &mut self,
+        blkheight: Height,
+        tweaks: usize,
+        matched: bool,
+    ) -> ScanProgress {
+        self.tweaks_processed += tweaks;
+        self.matched_blocks += matched as u32;
+        self.processed += 1;
+
+        let start = self.start.to_consensus_u32();
+        let end = self.end.to_consensus_u32();
+
+        ScanProgress {
+            blkheight,
+            blocks_done: (blkheight.to_consensus_u32() + 1).saturating_sub(start),
+            blocks_total: (end + 1).saturating_sub(start),
+            tweaks_processed: self.tweaks_processed,
+            matched_blocks: self.matched_blocks,
+            elapsed: self.start_time.elapsed(),
+            processed: self.processed,
+        }
+    }
+}
+
+/// The subscribers to the progress of a scanner.
+#[derive(Default)]
+pub(super) struct ProgressSenders(Vec<UnboundedSender<ScanProgress>>);
+
+impl ProgressSenders {
+    pub(super) fn subscribe(&mut self) -> UnboundedReceiver<ScanProgress> {
+        let (sender, receiver) = unbounded();
+        self.0.push(sender);
+        receiver
+    }
+
+    /// Send the progress to every subscriber, and forget the subscribers that dropped their receiver.
+    pub(super) fn send(&mut self, progress: &ScanProgress) {
+        self.0
+            .retain(|sender| sender.unbounded_send(progress.clone()).is_ok());
+    }
+}
@@ -1,357 +1,167 @@
-use std::{
-    collections::{HashMap, HashSet},
-    ops::RangeInclusive,
-    sync::atomic::AtomicBool,
-    time::Instant,
-};
+use std::{collections::HashSet, ops::RangeInclusive};
 
-use anyhow::{Error, Result};
-use bitcoin::{
-    Amount, BlockHash, OutPoint, Txid, XOnlyPublicKey,
-    absolute::Height,
-    bip158::BlockFilter,
-    hashes::{Hash, sha256},
-    secp256k1::{PublicKey, Scalar},
-};
-use futures::{Stream, StreamExt, pin_mut};
-use log::info;
-use silentpayments::{SharedSecret, receiving::Label};
+use anyhow::Result;
+use bitcoin::{Amount, BlockHash, OutPoint, absolute::Height};
+use futures::{Stream, channel::mpsc::UnboundedReceiver};
 
-use spdk_core::chain::{BlockData, ChainBackend, FilterData, UtxoData};
-use spdk_core::updater::{DiscoveredOutput, Updater};
+use spdk_core::chain::ChainBackend;
+use spdk_core::updater::{AsyncUpdater, Updater};
 
-use crate::client::SpClient;
+use super::{CancellationToken, ScanProgress, ScanWallet, SpMultiScanner};
+use crate::client::{ScanKeyProvider, SpClient};
 
-pub struct SpScanner<'a> {
-    updater: Box<dyn Updater + Sync + Send>,
-    backend: Box<dyn ChainBackend + Sync + Send>,
-    client: SpClient,
-    keep_scanning: &'a AtomicBool,      // used to interrupt scanning
-    owned_outpoints: HashSet<OutPoint>, // used to scan block inputs
+/// A scanner for a single wallet, see [SpMultiScanner] to scan many wallets at once.
+pub struct SpScanner {
+    inner: SpMultiScanner,
 }
 
-impl<'a> SpScanner<'a> {
+impl SpScanner {
     pub fn new(
         client: SpClient,
         updater: Box<dyn Updater + Sync + Send>,
         backend: Box<dyn ChainBackend + Sync + Send>,
         owned_outpoints: HashSet<OutPoint>,
-        keep_scanning: &'a AtomicBool,
+        cancel: CancellationToken,
     ) -> Self {
-        Self {
-            client,
-            updater,
+        Self::with_wallet(
+            ScanWallet::new(client, updater, owned_outpoints),
             backend,
-            owned_outpoints,
-            keep_scanning,
-        }
+            cancel,
+        )
     }
 
-    pub async fn scan_blocks(
-        &mut self,
-        range: RangeInclusive<Height>,
-        dust_limit: Amount,
-        with_cutthrough: bool,
-    ) -> Result<()> {
-        info!(
-            "start: {} end: {}",
-            range.start().to_consensus_u32(),
-            range.end().to_consensus_u32(),
-        );
-        let start_time: Instant = Instant::now();
-
-        // get block data stream
-        let block_data_stream =
-            self.backend
-                .get_block_data_for_range(range, dust_limit, with_cutthrough);
-
-        // process blocks using block data stream
-        self.process_blocks(block_data_stream).await?;
-
-        // time elapsed for the scan
-        info!(
-            "Blindbit scan complete in {} seconds",
-            start_time.elapsed().as_secs()
-        );
-
-        Ok(())
+    /// Create a scanner that passes the results to an [AsyncUpdater].
+    pub fn new_with_async_updater(
+        client: SpClient,
+        updater: Box<dyn AsyncUpdater + Sync + Send>,
+        backend: Box<dyn ChainBackend + Sync + Send>,
+        owned_outpoints: HashSet<OutPoint>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self::with_wallet(
+            ScanWallet::new_with_async_updater(client, updater, owned_outpoints),
+            backend,
+            cancel,
+        )
     }
 
-    async fn process_blocks(
-        &mut self,
-        block_data_stream: impl Stream<Item = Result<BlockData>>,
-    ) -> Result<()> {
-        pin_mut!(block_data_stream);
-
-        let mut tweak_count = 0;
-
-        while let Some(blockdata) = block_data_stream.next().await {
-            // stop scanning and return if interrupted
-            if self.interrupt_requested() {
-                break;
-            }
-
-            let blockdata = blockdata?;
-            let blkhash = blockdata.blkhash;
-            let blkheight = blockdata.blkheight;
-
-            tweak_count += blockdata.tweaks.len();
-
-            let (discovered_outputs, discovered_inputs) = self.process_block(blockdata).await?;
-
-            self.updater.record_block_scan_result(
-                blkheight,
-                blkhash,
-                discovered_inputs,
-                discovered_outputs,
-            )?;
+    fn with_wallet(
+        wallet: ScanWallet,
+        backend: Box<dyn ChainBackend + Sync + Send>,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            inner: SpMultiScanner::new(vec![wallet], backend, cancel),
         }
-
-        info!("Total number of tweaks processed: {tweak_count}");
-
-        Ok(())
     }
 
-    async fn process_block(
-        &mut self,
-        blockdata: BlockData,
-    ) -> Result<(HashMap<OutPoint, DiscoveredOutput>, HashSet<OutPoint>)> {
-        let BlockData {
-            blkheight,
-            tweaks,
-            new_utxo_filter,
-            spent_filter,
-            ..
-        } = blockdata;
-
-        let outs = self
-            .process_block_outputs(blkheight, tweaks, new_utxo_filter)
-            .await?;
-
-        // after processing outputs, we add the found outputs to our list
-        self.owned_outpoints.extend(outs.keys());
-
-        let ins = self.process_block_inputs(blkheight, spent_filter).await?;
-
-        // after processing inputs, we remove the found inputs
-        self.owned_outpoints.retain(|item| !ins.contains(item));
-
-        Ok((outs, ins))
+    /// Use a [ScanKeyProvider] to compute the shared secrets, instead of the scan key of the client.
+    ///
+    /// This fails if the provider holds a different scan key than the client.
+    pub fn with_scan_key_provider(mut self, provider: Box<dyn ScanKeyProvider>) -> Result<Self> {
+        self.inner.wallets_mut()[0].set_scan_key_provider(provider)?;
+        Ok(self)
     }
 
-    async fn process_block_outputs(
-        &self,
-        blkheight: Height,
-        tweaks: Vec<PublicKey>,
-        new_utxo_filter: FilterData,
-    ) -> Result<HashMap<OutPoint, DiscoveredOutput>> {
-        let mut res = HashMap::new();
-
-        if !tweaks.is_empty() {
-            let secrets_map = self.client.get_script_to_secret_map(tweaks)?;
-
-            //last_scan = last_scan.max(n as u32);
-            let candidate_spks: Vec<&[u8; 34]> = secrets_map.keys().collect();
-
-            //get block gcs & check match
-            let blkfilter = BlockFilter::new(&new_utxo_filter.data);
-            let blkhash = new_utxo_filter.block_hash;
-
-            let matched_outputs = Self::check_block_outputs(blkfilter, blkhash, candidate_spks)?;
-
-            //if match: fetch and scan utxos
-            if matched_outputs {
-                info!("matched outputs on: {}", blkheight);
-                let found = self.scan_utxos(blkheight, secrets_map).await?;
-
-                if !found.is_empty() {
-                    for (label, utxo, tweak) in found {
-                        let outpoint = OutPoint {
-                            txid: utxo.txid,
-                            vout: utxo.vout,
-                        };
-
-                        let out = DiscoveredOutput {
-                            tweak,
-                            value: utxo.value,
-                            script_pubkey: utxo.scriptpubkey,
-                            label,
-                        };
-
-                        res.insert(outpoint, out);
-                    }
-                }
-            }
-        }
-        Ok(res)
+    /// Resume from blocks that were scanned before, as recorded with [Updater::record_progress].
+    ///
+    /// The hashes are used to detect reorgs, the last one is where [scan_to_tip](Self::scan_to_tip) resumes.
+    /// Passing the last 100 scanned blocks lets the scanner find the fork of any reorg up to that depth.
+    /// If a reorg replaces every checkpoint, the fork is unknown and scanning fails.
+    pub fn with_checkpoints(
+        mut self,
+        checkpoints: impl IntoIterator<Item = (Height, BlockHash)>,
+    ) -> Self {
+        self.inner = self.inner.with_checkpoints(checkpoints);
+        self
     }
 
-    async fn process_block_inputs(
-        &self,
-        blkheight: Height,
-        spent_filter: FilterData,
-    ) -> Result<HashSet<OutPoint>> {
-        let mut res = HashSet::new();
-
-        let blkhash = spent_filter.block_hash;
-
-        // first get the 8-byte hashes used to construct the input filter
-        let input_hashes_map = self.get_input_hashes(blkhash)?;
-
-        // check against filter
-        let blkfilter = BlockFilter::new(&spent_filter.data);
-        let matched_inputs = self.check_block_inputs(
-            blkfilter,
-            blkhash,
-            input_hashes_map.keys().cloned().collect(),
-        )?;
-
-        // if match: download spent data, collect the outpoints that are spent
-        if matched_inputs {
-            info!("matched inputs on: {}", blkheight);
-            let spent = self.backend.spent_index(blkheight).await?.data;
-
-            for spent in spent {
-                let hex: &[u8] = spent.as_ref();
-
-                if let Some(outpoint) = input_hashes_map.get(hex) {
-                    res.insert(*outpoint);
-                }
-            }
-        }
-        Ok(res)
+    /// Replace the [CancellationToken], e.g. to scan again after the previous token was cancelled.
+    ///
+    /// A cancelled token stays cancelled, so every scan returns right away until the token is replaced.
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        self.inner.set_cancellation_token(cancel);
     }
 
-    async fn scan_utxos(
-        &self,
-        blkheight: Height,
-        secrets_map: HashMap<[u8; 34], SharedSecret>,
-    ) -> Result<Vec<(Option<Label>, UtxoData, Scalar)>> {
-        let utxos = self.backend.utxos(blkheight).await?;
-
-        let mut res: Vec<(Option<Label>, UtxoData, Scalar)> = vec![];
-
-        // group utxos by the txid
-        let mut txmap: HashMap<Txid, Vec<UtxoData>> = HashMap::new();
-        for utxo in utxos {
-            txmap.entry(utxo.txid).or_default().push(utxo);
-        }
-
-        for utxos in txmap.into_values() {
-            // check if we know the secret to any of the spks
-            let mut secret = None;
-            for utxo in utxos.iter() {
-                let spk = utxo.scriptpubkey.as_bytes();
-                if let Some(s) = secrets_map.get(spk) {
-                    secret = Some(s);
-                    break;
-                }
-            }
-
-            // skip this tx if no secret is found
-            let secret = match secret {
-                Some(secret) => secret,
-                None => continue,
-            };
-
-            let output_keys: Result<Vec<XOnlyPublicKey>> = utxos
-                .iter()
-                .filter_map(|x| {
-                    if x.scriptpubkey.is_p2tr() {
-                        Some(
-                            XOnlyPublicKey::from_slice(&x.scriptpubkey.as_bytes()[2..])
-                                .map_err(Error::new),
-                        )
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-
-            let ours = self
-                .client
-                .sp_receiver
-                .scan_transaction(secret, &output_keys?)?;
-
-            for utxo in utxos {
-                if !utxo.scriptpubkey.is_p2tr() || utxo.spent {
-                    continue;
-                }
-
-                match XOnlyPublicKey::from_slice(&utxo.scriptpubkey.as_bytes()[2..]) {
-                    Ok(xonly) => {
-                        for (label, map) in ours.iter() {
-                            if let Some(scalar) = map.get(&xonly) {
-                                res.push((label.clone(), utxo, *scalar));
-                                break;
-                            }
-                        }
-                    }
-                    Err(_) => todo!(),
-                }
-            }
-        }
-
-        Ok(res)
+    /// Receive the [ScanProgress] of [scan_blocks](Self::scan_blocks) after every scanned block,
+    /// e.g. to show a progress bar.
+    ///
+    /// The receiver is a [Stream], it ends when the scanner is dropped.
+    /// This can be called more than once, every receiver gets all progress updates.
+    pub fn progress(&mut self) -> UnboundedReceiver<ScanProgress> {
+        self.inner.progress()
     }
 
-    // Check if this block contains relevant transactions
-    fn check_block_outputs(
-        created_utxo_filter: BlockFilter,
-        blkhash: BlockHash,
-        candidate_spks: Vec<&[u8; 34]>,
-    ) -> Result<bool> {
-        // check output scripts
-        let output_keys: Vec<_> = candidate_spks
-            .into_iter()
-            .map(|spk| spk[2..].as_ref())
-            .collect();
-
-        // note: match will always return true for an empty query!
-        if !output_keys.is_empty() {
-            Ok(created_utxo_filter.match_any(&blkhash, &mut output_keys.into_iter())?)
-        } else {
-            Ok(false)
-        }
+    /// The last block that was fully processed.
+    pub fn last_scanned_block(&self) -> Option<(Height, BlockHash)> {
+        self.inner.last_scanned_block()
     }
 
-    fn get_input_hashes(&self, blkhash: BlockHash) -> Result<HashMap<[u8; 8], OutPoint>> {
-        let mut map: HashMap<[u8; 8], OutPoint> = HashMap::new();
-
-        for outpoint in &self.owned_outpoints {
-            let mut arr = [0u8; 68];
-            arr[..32].copy_from_slice(&outpoint.txid.to_raw_hash().to_byte_array());
-            arr[32..36].copy_from_slice(&outpoint.vout.to_le_bytes());
-            arr[36..].copy_from_slice(&blkhash.to_byte_array());
-            let hash = sha256::Hash::hash(&arr);
-
-            let mut res = [0u8; 8];
-            res.copy_from_slice(&hash[..8]);
-
-            map.insert(res, *outpoint);
-        }
+    /// Scan from the block after the last scanned block up to the tip of the backend.
+    ///
+    /// The last scanned block comes from an earlier scan, or from [with_checkpoints](Self::with_checkpoints).
+    ///
+    /// A cancelled scan returns `Ok(())` too, check [last_scanned_block](Self::last_scanned_block)
+    /// or [CancellationToken::is_cancelled] to tell it apart from a finished scan.
+    pub async fn scan_to_tip(&mut self, dust_limit: Amount, with_cutthrough: bool) -> Result<()> {
+        self.inner.scan_to_tip(dust_limit, with_cutthrough).await
+    }
 
-        Ok(map)
+    /// Follow the tip of the chain: scan up to the tip, and again every time `ticks` yields an item.
+    ///
+    /// `ticks` decides when to look for new blocks, e.g. an interval timer to poll the backend,
+    /// or a stream of new block notifications. Reorgs are handled as in [scan_blocks](Self::scan_blocks).
+    /// This returns when `ticks` ends or the scan is interrupted.
+    ///
+    /// Like [scan_to_tip](Self::scan_to_tip), this starts from the last scanned block.
+    /// If an error is returned, following can be resumed by calling this again.
+    ///
+    /// A cancelled scan returns `Ok(())` too, check [CancellationToken::is_cancelled]
+    /// to tell it apart from the end of `ticks`.
+    pub async fn follow_tip(
+        &mut self,
+        ticks: impl Stream,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<()> {
+        self.inner
+            .follow_tip(ticks, dust_limit, with_cutthrough)
+            .await
     }
 
-    // Check if this block contains relevant transactions
-    fn check_block_inputs(
-        &self,
-        spent_filter: BlockFilter,
-        blkhash: BlockHash,
-        input_hashes: Vec<[u8; 8]>,
-    ) -> Result<bool> {
-        // note: match will always return true for an empty query!
-        if !input_hashes.is_empty() {
-            Ok(spent_filter.match_any(&blkhash, &mut input_hashes.into_iter())?)
-        } else {
-            Ok(false)
-        }
+    /// Scan the transactions in the mempool of the backend, see [ChainBackend::mempool].
+    ///
+    /// The outputs that are found are passed to [Updater::record_mempool_scan_result] as pending outputs.
+    /// Pending outputs are confirmed when [scan_blocks](Self::scan_blocks) finds them in a block,
+    /// and are reported as dropped if their transaction is no longer in the mempool on the next call.
+    /// Outputs are only reported as dropped once the blocks up to the tip are scanned,
+    /// before that their transaction may be in a block that wasn't scanned yet.
+    ///
+    /// A cancelled scan returns `Ok(())` too, without recording any result,
+    /// check [CancellationToken::is_cancelled] to tell it apart from a finished scan.
+    pub async fn scan_mempool(&mut self, dust_limit: Amount) -> Result<()> {
+        self.inner.scan_mempool(dust_limit).await
     }
 
-    fn interrupt_requested(&self) -> bool {
-        !self
-            .keep_scanning
-            .load(std::sync::atomic::Ordering::Relaxed)
+    /// Scan the blocks in `range`, and pass the results to the [Updater] or [AsyncUpdater].
+    ///
+    /// The scanner keeps the hashes of the last scanned blocks, to detect reorgs.
+    /// If one of these blocks is no longer part of the chain, the results of the blocks above the fork are rolled back
+    /// with [Updater::rollback_to_block], and these blocks are scanned again.
+    ///
+    /// If the [CancellationToken] is cancelled, pending requests to the backend are aborted and this returns.
+    /// The updater is left at the last fully scanned block, from where the scan can be resumed
+    /// after replacing the token with [set_cancellation_token](Self::set_cancellation_token).
+    /// This returns `Ok(())` when cancelled, check [last_scanned_block](Self::last_scanned_block)
+    /// to know if the whole range was scanned.
+    pub async fn scan_blocks(
+        &mut self,
+        range: RangeInclusive<Height>,
+        dust_limit: Amount,
+        with_cutthrough: bool,
+    ) -> Result<()> {
+        self.inner
+            .scan_blocks(range, dust_limit, with_cutthrough)
+            .await
     }
 }
@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use bitcoin::{BlockHash, OutPoint, absolute::Height};
+
+use spdk_core::chain::SpendingTx;
+use spdk_core::updater::{AsyncUpdater, DiscoveredOutput, Updater};
+
+/// The updater of a scanner, which can be blocking or async.
+pub(crate) enum ScanUpdater {
+    Blocking(Box<dyn Updater + Sync + Send>),
+    Async(Box<dyn AsyncUpdater + Sync + Send>),
+}
+
+impl ScanUpdater {
+    pub(crate) async fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        blkhash: BlockHash,
+        discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()> {
+        match self {
+            Self::Blocking(updater) => updater.record_block_scan_result(
+                blkheight,
+                blkhash,
+                discovered_inputs,
+                discovered_outputs,
+            ),
+            Self::Async(updater) => {
+                updater
+                    .record_block_scan_result(
+                        blkheight,
+                        blkhash,
+                        discovered_inputs,
+                        discovered_outputs,
+                    )
+                    .await
+            }
+        }
+    }
+
+    pub(crate) async fn rollback_to_block(
+        &mut self,
+        blkheight: Height,
+        blkhash: BlockHash,
+    ) -> Result<()> {
+        match self {
+            Self::Blocking(updater) => updater.rollback_to_block(blkheight, blkhash),
+            Self::Async(updater) => updater.rollback_to_block(blkheight, blkhash).await,
+        }
+    }
+
+    pub(crate) async fn record_progress(
+        &mut self,
+        blkheight: Height,
+        blkhash: BlockHash,
+    ) -> Result<()> {
+        match self {
+            Self::Blocking(updater) => updater.record_progress(blkheight, blkhash),
+            Self::Async(updater) => updater.record_progress(blkheight, blkhash).await,
+        }
+    }
+
+    pub(crate) async fn record_spending_txs(
+        &mut self,
+        blkheight: Height,
+        spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()> {
+        match self {
+            Self::Blocking(updater) => updater.record_spending_txs(blkheight, spending_txs),
+            Self::Async(updater) => updater.record_spending_txs(blkheight, spending_txs).await,
+        }
+    }
+
+    pub(crate) async fn record_mempool_scan_result(
+        &mut self,
+        new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+        dropped_outputs: HashSet<OutPoint>,
+    ) -> Result<()> {
+        match self {
+            Self::Blocking(updater) => {
+                updater.record_mempool_scan_result(new_outputs, dropped_outputs)
+            }
+            Self::Async(updater) => {
+                updater
+                    .record_mempool_scan_result(new_outputs, dropped_outputs)
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Error, Result};
+use bitcoin::{
+    BlockHash, OutPoint, Txid, XOnlyPublicKey,
+    bip158::BlockFilter,
+    hashes::{Hash, sha256},
+    secp256k1::PublicKey,
+};
+use silentpayments::SharedSecret;
+
+use spdk_core::chain::{FilterData, UtxoData};
+use spdk_core::updater::{AsyncUpdater, DiscoveredOutput, Updater};
+
+use super::updater::ScanUpdater;
+use crate::client::{ScanKeyProvider, SpClient};
+
+/// A wallet that is scanned by a [SpMultiScanner](super::SpMultiScanner).
+///
+/// This holds the client and the updater of the wallet, and the outputs that it owns.
+pub struct ScanWallet {
+    client: SpClient,
+    pub(super) updater: ScanUpdater,
+    scan_key_provider: Option<Box<dyn ScanKeyProvider>>, // if None, the client scan key is used
+    pub(super) owned_outpoints: HashSet<OutPoint>,       // used to scan block inputs
+    pub(super) pending_outputs: HashSet<OutPoint>,       // found in the mempool, not yet confirmed
+}
+
+impl ScanWallet {
+    pub fn new(
+        client: SpClient,
+        updater: Box<dyn Updater + Sync + Send>,
+        owned_outpoints: HashSet<OutPoint>,
+    ) -> Self {
+        Self::with_updater(client, ScanUpdater::Blocking(updater), owned_outpoints)
+    }
+
+    /// Create a wallet that passes the results to an [AsyncUpdater].
+    pub fn new_with_async_updater(
+        client: SpClient,
+        updater: Box<dyn AsyncUpdater + Sync + Send>,
+        owned_outpoints: HashSet<OutPoint>,
+    ) -> Self {
+        Self::with_updater(client, ScanUpdater::Async(updater), owned_outpoints)
+    }
+
+    fn with_updater(
+        client: SpClient,
+        updater: ScanUpdater,
+        owned_outpoints: HashSet<OutPoint>,
+    ) -> Self {
+        Self {
+            client,
+            updater,
+            scan_key_provider: None,
+            owned_outpoints,
+            pending_outputs: HashSet::new(),
+        }
+    }
+
+    /// Use a [ScanKeyProvider] to compute the shared secrets, instead of the scan key of the client.
+    ///
+    /// This fails if the provider holds a different scan key than the client.
+    pub fn with_scan_key_provider(mut self, provider: Box<dyn ScanKeyProvider>) -> Result<Self> {
+        self.set_scan_key_provider(provider)?;
+        Ok(self)
+    }
+
+    pub(super) fn set_scan_key_provider(
+        &mut self,
+        provider: Box<dyn ScanKeyProvider>,
+    ) -> Result<()> {
+        if provider.scan_pubkey()? != self.client.get_receiving_address().scan_key() {
+            return Err(Error::msg(
+                "Scan key provider doesn't match the client scan key",
+            ));
+        }
+
+        self.scan_key_provider = Some(provider);
+        Ok(())
+    }
+
+    pub fn client(&self) -> &SpClient {
+        &self.client
+    }
+
+    /// The outputs of this wallet that are not known to be spent.
+    pub fn owned_outpoints(&self) -> &HashSet<OutPoint> {
+        &self.owned_outpoints
+    }
+
+    pub(super) fn get_script_to_secret_map(
+        &self,
+        tweaks: Vec<PublicKey>,
+    ) -> Result<HashMap<[u8; 34], SharedSecret>> {
+        match &self.scan_key_provider {
+            Some(provider) => self
+                .client
+                .get_script_to_secret_map_with_provider(provider.as_ref(), tweaks),
+            None => self.client.get_script_to_secret_map(tweaks),
+        }
+    }
+
+    /// Check if the new utxo filter of a block may contain outputs of this wallet.
+    ///
+    /// Returns the shared secrets of the tweaks if it does, these are needed to find the outputs.
+    pub(super) fn match_outputs(
+        &self,
+        tweaks: &[PublicKey],
+        new_utxo_filter: &FilterData,
+    ) -> Result<Option<HashMap<[u8; 34], SharedSecret>>> {
+        if tweaks.is_empty() {
+            return Ok(None);
+        }
+
+        let secrets_map = self.get_script_to_secret_map(tweaks.to_vec())?;
+
+        let candidate_spks: Vec<&[u8; 34]> = secrets_map.keys().collect();
+
+        //get block gcs & check match
+        let blkfilter = BlockFilter::new(&new_utxo_filter.data);
+        let blkhash = new_utxo_filter.block_hash;
+
+        if check_block_outputs(blkfilter, blkhash, candidate_spks)? {
+            Ok(Some(secrets_map))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Check if the spent filter of a block may contain outputs of this wallet.
+    ///
+    /// Returns the hashes of the owned outputs if it does, these are needed to find the spent outputs in the spent index.
+    /// `new_outputs` are owned outputs of the same block, which may be spent in it as well.
+    pub(super) fn match_inputs<'a>(
+        &'a self,
+        spent_filter: &FilterData,
+        new_outputs: impl Iterator<Item = &'a OutPoint>,
+    ) -> Result<Option<HashMap<[u8; 8], OutPoint>>> {
+        let blkhash = spent_filter.block_hash;
+
+        // first get the 8-byte hashes used to construct the input filter
+        let input_hashes_map = self.get_input_hashes(blkhash, new_outputs);
+
+        // check against filter
+        let blkfilter = BlockFilter::new(&spent_filter.data);
+        let matched_inputs = check_block_inputs(
+            blkfilter,
+            blkhash,
+            input_hashes_map.keys().cloned().collect(),
+        )?;
+
+        if matched_inputs {
+            Ok(Some(input_hashes_map))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Find the unspent outputs in `utxos` that belong to us.
+    pub(super) fn find_owned_utxos(
+        &self,
+        utxos: &[UtxoData],
+        secrets_map: &HashMap<[u8; 34], SharedSecret>,
+    ) -> Result<HashMap<OutPoint, DiscoveredOutput>> {
+        let mut res = HashMap::new();
+
+        // group utxos by the txid
+        let mut txmap: HashMap<Txid, Vec<&UtxoData>> = HashMap::new();
+        for utxo in utxos {
+            txmap.entry(utxo.txid).or_default().push(utxo);
+        }
+
+        // the transactions that may contain outputs for us, and their secret and output keys
+        let mut candidates: Vec<Vec<&UtxoData>> = vec![];
+        let mut to_scan: Vec<(SharedSecret, Vec<XOnlyPublicKey>)> = vec![];
+
+        for utxos in txmap.into_values() {
+            // check if we know the secret to any of the spks
+            let mut secret = None;
+            for utxo in utxos.iter() {
+                let spk = utxo.scriptpubkey.as_bytes();
+                if let Some(s) = secrets_map.get(spk) {
+                    secret = Some(s);
+                    break;
+                }
+            }
+
+            // skip this tx if no secret is found
+            let secret = match secret {
+                Some(secret) => secret,
+                None => continue,
+            };
+
+            // outputs whose key isn't valid can't be ours, they are skipped as in BIP352
+            let output_keys: Vec<XOnlyPublicKey> = utxos
+                .iter()
+                .filter(|x| x.scriptpubkey.is_p2tr())
+                .filter_map(|x| XOnlyPublicKey::from_slice(&x.scriptpubkey.as_bytes()[2..]).ok())
+                .collect();
+
+            to_scan.push((secret.clone(), output_keys));
+            candidates.push(utxos);
+        }
+
+        let scanned = self.client.sp_receiver.scan_transactions(&to_scan);
+
+        for (utxos, ours) in candidates.into_iter().zip(scanned) {
+            let ours = ours?;
+
+            for utxo in utxos {
+                if !utxo.scriptpubkey.is_p2tr() || utxo.spent {
+                    continue;
+                }
+
+                let Ok(xonly) = XOnlyPublicKey::from_slice(&utxo.scriptpubkey.as_bytes()[2..])
+                else {
+                    continue;
+                };
+
+                for (label, map) in ours.iter() {
+                    if let Some(scalar) = map.get(&xonly) {
+                        let outpoint = OutPoint {
+                            txid: utxo.txid,
+                            vout: utxo.vout,
+                        };
+
+                        let out = DiscoveredOutput {
+                            tweak: *scalar,
+                            value: utxo.value,
+                            script_pubkey: utxo.scriptpubkey.clone(),
+                            label: label.clone(),
+                        };
+
+                        res.insert(outpoint, out);
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Update the pending outputs with the outputs that are found in the mempool.
+    ///
+    /// Returns the outputs that are newly pending, and the outputs that are no longer pending without being confirmed.
+    /// Unless `caught_up` is set, the outputs that are no longer in the mempool may be confirmed in a block
+    /// that wasn't scanned yet, so they stay pending until the block scan or a later mempool scan decides.
+    pub(super) fn update_pending_outputs(
+        &mut self,
+        mut found: HashMap<OutPoint, DiscoveredOutput>,
+        caught_up: bool,
+    ) -> (HashMap<OutPoint, DiscoveredOutput>, HashSet<OutPoint>) {
+        // outputs that are already confirmed are not pending
+        found.retain(|outpoint, _| !self.owned_outpoints.contains(outpoint));
+
+        let vanished: HashSet<OutPoint> = self
+            .pending_outputs
+            .iter()
+            .filter(|outpoint| !found.contains_key(outpoint))
+            .cloned()
+            .collect();
+
+        let mut pending_outputs: HashSet<OutPoint> = found.keys().cloned().collect();
+        found.retain(|outpoint, _| !self.pending_outputs.contains(outpoint));
+
+        let dropped_outputs = if caught_up {
+            vanished
+        } else {
+            pending_outputs.extend(vanished);
+            HashSet::new()
+        };
+        self.pending_outputs = pending_outputs;
+
+        (found, dropped_outputs)
+    }
+
+    fn get_input_hashes<'a>(
+        &'a self,
+        blkhash: BlockHash,
+        new_outputs: impl Iterator<Item = &'a OutPoint>,
+    ) -> HashMap<[u8; 8], OutPoint> {
+        let mut map: HashMap<[u8; 8], OutPoint> = HashMap::new();
+
+        for outpoint in self.owned_outpoints.iter().chain(new_outputs) {
+            let mut arr = [0u8; 68];
+            arr[..32].copy_from_slice(&outpoint.txid.to_raw_hash().to_byte_array());
+            arr[32..36].copy_from_slice(&outpoint.vout.to_le_bytes());
+            arr[36..].copy_from_slice(&blkhash.to_byte_array());
+            let hash = sha256::Hash::hash(&arr);
+
+            let mut res = [0u8; 8];
+            res.copy_from_slice(&hash[..8]);
+
+            map.insert(res, *outpoint);
+        }
+
+        map
+    }
+}
+
+// Check if this block contains relevant transactions
+fn check_block_outputs(
+    created_utxo_filter: BlockFilter,
+    blkhash: BlockHash,
+    candidate_spks: Vec<&[u8; 34]>,
+) -> Result<bool> {
+    // check output scripts
+    let output_keys: Vec<_> = candidate_spks
+        .into_iter()
+        .map(|spk| spk[2..].as_ref())
+        .collect();
+
+    // note: match will always return true for an empty query!
+    if !output_keys.is_empty() {
+        Ok(created_utxo_filter.match_any(&blkhash, &mut output_keys.into_iter())?)
+    } else {
+        Ok(false)
+    }
+}
+
+// Check if this block contains relevant transactions
+fn check_block_inputs(
+    spent_filter: BlockFilter,
+    blkhash: BlockHash,
+    input_hashes: Vec<[u8; 8]>,
+) -> Result<bool> {
+    // note: match will always return true for an empty query!
+    if !input_hashes.is_empty() {
+        Ok(spent_filter.match_any(&blkhash, &mut input_hashes.into_iter())?)
+    } else {
+        Ok(false)
+    }
+}
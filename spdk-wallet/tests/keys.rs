@@ -0,0 +1,139 @@
+use std::str::FromStr;
+
+use bitcoin::bip32::{DerivationPath, Xpriv};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Network, NetworkKind};
+use silentpayments::utils::{MAIN_SCAN_PATH, MAIN_SPEND_PATH, TEST_SCAN_PATH, TEST_SPEND_PATH};
+use spdk_wallet::client::{
+    SpClient, SpendKey, derive_watch_only_xkeys, scan_derivation_path, spend_derivation_path,
+};
+
+const MNEMONIC: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn master_xprv(network: Network) -> Xpriv {
+    let seed = bip39::Mnemonic::parse(MNEMONIC).unwrap().to_seed("");
+    Xpriv::new_master(network, &seed).unwrap()
+}
+
+#[test]
+fn derivation_paths() {
+    let scan_path = |network, account| scan_derivation_path(network, account).unwrap();
+    let spend_path = |network, account| spend_derivation_path(network, account).unwrap();
+
+    assert_eq!(
+        scan_path(Network::Bitcoin, 0),
+        DerivationPath::from_str(MAIN_SCAN_PATH).unwrap()
+    );
+    assert_eq!(
+        spend_path(Network::Bitcoin, 0),
+        DerivationPath::from_str(MAIN_SPEND_PATH).unwrap()
+    );
+    assert_eq!(
+        scan_path(Network::Signet, 0),
+        DerivationPath::from_str(TEST_SCAN_PATH).unwrap()
+    );
+    assert_eq!(
+        spend_path(Network::Regtest, 0),
+        DerivationPath::from_str(TEST_SPEND_PATH).unwrap()
+    );
+    assert_eq!(
+        scan_path(Network::Testnet, 5),
+        DerivationPath::from_str("m/352h/1h/5h/1h/0").unwrap()
+    );
+
+    // the account index must fit in a hardened child number
+    assert!(scan_derivation_path(Network::Bitcoin, 1 << 31).is_err());
+}
+
+#[test]
+fn client_from_mnemonic() {
+    let network = Network::Signet;
+    let secp = Secp256k1::new();
+    let master = master_xprv(network);
+
+    let client = SpClient::from_mnemonic(MNEMONIC, "", 0, network).unwrap();
+
+    // same keys as deriving by hand
+    let scan_sk = master
+        .derive_priv(&secp, &DerivationPath::from_str(TEST_SCAN_PATH).unwrap())
+        .unwrap()
+        .private_key;
+    let spend_sk = master
+        .derive_priv(&secp, &DerivationPath::from_str(TEST_SPEND_PATH).unwrap())
+        .unwrap()
+        .private_key;
+    let expected = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), network).unwrap();
+
+    assert_eq!(client, expected);
+    assert_eq!(client, SpClient::from_xprv(&master, 0, network).unwrap());
+
+    // a passphrase or different account gives a different wallet
+    let with_passphrase = SpClient::from_mnemonic(MNEMONIC, "passphrase", 0, network).unwrap();
+    assert_ne!(
+        client.get_receiving_address(),
+        with_passphrase.get_receiving_address()
+    );
+    let other_account = SpClient::from_mnemonic(MNEMONIC, "", 1, network).unwrap();
+    assert_ne!(
+        client.get_receiving_address(),
+        other_account.get_receiving_address()
+    );
+
+    assert!(SpClient::from_mnemonic("abandon abandon", "", 0, network).is_err());
+}
+
+#[test]
+fn client_from_xprv_wrong_network() {
+    let master = master_xprv(Network::Bitcoin);
+    assert_eq!(master.network, NetworkKind::Main);
+
+    assert!(SpClient::from_xprv(&master, 0, Network::Signet).is_err());
+    assert!(SpClient::from_xprv(&master, 0, Network::Bitcoin).is_ok());
+}
+
+#[test]
+fn watch_only_client() {
+    let network = Network::Bitcoin;
+    let master = master_xprv(network);
+
+    let client = SpClient::from_xprv(&master, 3, network).unwrap();
+
+    let (scan_xprv, spend_xpub) = derive_watch_only_xkeys(&master, 3, network).unwrap();
+    let watch_only = SpClient::new_watch_only_from_xkeys(&scan_xprv, &spend_xpub, network).unwrap();
+
+    assert_eq!(
+        client.get_receiving_address(),
+        watch_only.get_receiving_address()
+    );
+    assert_eq!(
+        client.try_get_scan_key().unwrap(),
+        watch_only.try_get_scan_key().unwrap()
+    );
+    assert!(matches!(watch_only.get_spend_key(), SpendKey::Public(_)));
+    assert!(watch_only.try_get_secret_spend_key().is_err());
+
+    assert!(
+        SpClient::new_watch_only_from_xkeys(&scan_xprv, &spend_xpub, Network::Testnet).is_err()
+    );
+}
+
+#[test]
+fn debug_output_is_redacted() {
+    let client = SpClient::from_mnemonic(MNEMONIC, "", 0, Network::Bitcoin).unwrap();
+
+    let debug = format!("{:?}", client);
+    let scan_sk = client
+        .try_get_scan_key()
+        .unwrap()
+        .display_secret()
+        .to_string();
+    let spend_sk = client
+        .try_get_secret_spend_key()
+        .unwrap()
+        .display_secret()
+        .to_string();
+
+    assert!(!debug.contains(&scan_sk));
+    assert!(!debug.contains(&spend_sk));
+}
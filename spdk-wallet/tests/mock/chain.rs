@@ -1,17 +1,59 @@
 use anyhow::Result;
 use backend_blindbit_v1::api_structs::{FilterResponse, SpentIndexResponse, UtxoResponse};
-use std::{fs::File, ops::RangeInclusive, pin::Pin};
+use std::{
+    collections::HashSet,
+    fs::File,
+    ops::RangeInclusive,
+    path::Path,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use async_trait::async_trait;
-use bitcoin::{Amount, absolute::Height, secp256k1::PublicKey};
-use futures::{Stream, stream};
+use bitcoin::{
+    Amount, BlockHash, ScriptBuf, Txid,
+    absolute::Height,
+    hashes::{Hash, sha256d},
+    secp256k1::PublicKey,
+};
+use futures::{Stream, channel::oneshot, future, stream};
 
-use spdk_core::chain::{BlockData, ChainBackend, SpentIndexData, UtxoData};
+use spdk_core::chain::{
+    BlockData, ChainBackend, FilterData, MempoolTxData, SpendingTx, SpentIndexData, UtxoData,
+};
 
 const BLOCK_DATA_PATH: &str = "tests/resources/blocks";
 
+/// The last block of the fixtures.
+pub const TIP_HEIGHT: u32 = 295147;
+
 pub struct MockChainBackend {}
 
+fn block_data(n: u32) -> Result<BlockData> {
+    let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/tweaks.json")).unwrap();
+    let tweaks: Vec<PublicKey> = serde_json::from_reader(file).unwrap();
+
+    let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/filter-new-utxos.json")).unwrap();
+    let new_utxo_filter: FilterResponse = serde_json::from_reader(file).unwrap();
+
+    let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/filter-spent.json")).unwrap();
+    let spent_filter: FilterResponse = serde_json::from_reader(file).unwrap();
+
+    let blkhash = new_utxo_filter.block_hash;
+    let blkheight = new_utxo_filter.block_height;
+
+    Ok(BlockData {
+        blkheight,
+        blkhash,
+        tweaks,
+        new_utxo_filter: new_utxo_filter.into(),
+        spent_filter: spent_filter.into(),
+    })
+}
+
 #[async_trait]
 impl ChainBackend for MockChainBackend {
     fn get_block_data_for_range(
@@ -22,27 +64,7 @@ impl ChainBackend for MockChainBackend {
     ) -> Pin<Box<dyn Stream<Item = Result<BlockData>> + Send>> {
         let range = range.start().to_consensus_u32()..=range.end().to_consensus_u32();
 
-        let values = range.map(move |n| {
-            let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/tweaks.json")).unwrap();
-            let tweaks: Vec<PublicKey> = serde_json::from_reader(file).unwrap();
-
-            let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/filter-new-utxos.json")).unwrap();
-            let new_utxo_filter: FilterResponse = serde_json::from_reader(file).unwrap();
-
-            let file = File::open(format!("{BLOCK_DATA_PATH}/{n}/filter-spent.json")).unwrap();
-            let spent_filter: FilterResponse = serde_json::from_reader(file).unwrap();
-
-            let blkhash = new_utxo_filter.block_hash;
-            let blkheight = new_utxo_filter.block_height;
-
-            Ok(BlockData {
-                blkheight,
-                blkhash,
-                tweaks,
-                new_utxo_filter: new_utxo_filter.into(),
-                spent_filter: spent_filter.into(),
-            })
-        });
+        let values = range.map(block_data);
 
         let stream = stream::iter(values);
 
@@ -63,4 +85,231 @@ impl ChainBackend for MockChainBackend {
 
         Ok(utxos.into_iter().map(Into::into).collect())
     }
+
+    async fn block_height(&self) -> Result<Height> {
+        Ok(Height::from_consensus(TIP_HEIGHT)?)
+    }
+}
+
+/// The blocks above this height are replaced by the reorg of [MockReorgBackend].
+pub const FORK_HEIGHT: u32 = 295124;
+
+/// A chain backend that serves the fixtures, and switches to another chain after a reorg.
+///
+/// Heights without fixtures are served as empty blocks, so ranges around the fixtures can be scanned.
+/// After the reorg, all blocks above [FORK_HEIGHT] are empty blocks with a new hash, except for block 295147.
+/// Unlike blindbit, the spent index comes with the spending transactions, which are made up from the hashes.
+#[derive(Clone, Default)]
+pub struct MockReorgBackend {
+    reorged: Arc<AtomicBool>,
+    blocks_until_reorg: Arc<Mutex<Option<usize>>>,
+    tip: Arc<Mutex<Option<u32>>>, // if None, the tip is TIP_HEIGHT
+    mempool: Arc<Mutex<Vec<u32>>>,
+    requests: Arc<Mutex<Requests>>,
+    stall_utxos: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    misaligned_spending_txs: Arc<AtomicBool>,
+    invalid_utxo: Arc<AtomicBool>,
+}
+
+/// The heights of the blocks that were requested from a [MockReorgBackend].
+#[derive(Clone, Default)]
+pub struct Requests {
+    pub block_data: Vec<u32>,
+    pub utxos: Vec<u32>,
+    pub spent_index: Vec<u32>,
+}
+
+impl MockReorgBackend {
+    /// Mine blocks up to the given height.
+    pub fn set_tip(&self, n: u32) {
+        *self.tip.lock().unwrap() = Some(n);
+    }
+
+    /// Never answer the next request for utxos, the receiver completes when it is made.
+    pub fn stall_utxos(&self) -> oneshot::Receiver<()> {
+        let (sender, receiver) = oneshot::channel();
+        *self.stall_utxos.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Leave out the first spending transaction of the spent index, so they no longer match the hashes.
+    pub fn misalign_spending_txs(&self) {
+        self.misaligned_spending_txs.store(true, Ordering::SeqCst);
+    }
+
+    /// Add a taproot output whose key isn't on the curve to every transaction with utxos.
+    pub fn add_invalid_utxo(&self) {
+        self.invalid_utxo.store(true, Ordering::SeqCst);
+    }
+
+    pub fn requests(&self) -> Requests {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Put the transactions of the given blocks in the mempool, as if they weren't mined yet.
+    pub fn set_mempool(&self, heights: Vec<u32>) {
+        *self.mempool.lock().unwrap() = heights;
+    }
+
+    /// Switch to the other chain.
+    pub fn reorg(&self) {
+        self.reorged.store(true, Ordering::SeqCst);
+    }
+
+    /// Switch to the other chain after serving `n` more blocks, i.e. in the middle of a scan.
+    pub fn reorg_after(&self, n: usize) {
+        *self.blocks_until_reorg.lock().unwrap() = Some(n);
+    }
+
+    fn is_replaced(reorged: &AtomicBool, n: u32) -> bool {
+        reorged.load(Ordering::SeqCst) && n > FORK_HEIGHT && n != 295147
+    }
+
+    fn has_fixture(n: u32) -> bool {
+        Path::new(&format!("{BLOCK_DATA_PATH}/{n}")).exists()
+    }
+
+    fn empty_block_hash(n: u32, replaced: bool) -> BlockHash {
+        let mut data = n.to_le_bytes().to_vec();
+        data.push(replaced as u8);
+        BlockHash::from_raw_hash(sha256d::Hash::hash(&data))
+    }
+
+    fn block_data(reorged: &AtomicBool, n: u32) -> Result<BlockData> {
+        let replaced = Self::is_replaced(reorged, n);
+        if Self::has_fixture(n) && !replaced {
+            return block_data(n);
+        }
+
+        let blkhash = Self::empty_block_hash(n, replaced);
+        Ok(BlockData {
+            blkheight: Height::from_consensus(n)?,
+            blkhash,
+            tweaks: vec![],
+            // a filter without elements
+            new_utxo_filter: FilterData {
+                block_hash: blkhash,
+                data: vec![0],
+            },
+            spent_filter: FilterData {
+                block_hash: blkhash,
+                data: vec![0],
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl ChainBackend for MockReorgBackend {
+    fn get_block_data_for_range(
+        &self,
+        range: RangeInclusive<Height>,
+        _dust_limit: Amount,
+        _with_cutthrough: bool,
+    ) -> Pin<Box<dyn Stream<Item = Result<BlockData>> + Send>> {
+        let range = range.start().to_consensus_u32()..=range.end().to_consensus_u32();
+        let reorged = self.reorged.clone();
+        let blocks_until_reorg = self.blocks_until_reorg.clone();
+        let requests = self.requests.clone();
+
+        let values = range.map(move |n| {
+            requests.lock().unwrap().block_data.push(n);
+
+            let mut blocks_until_reorg = blocks_until_reorg.lock().unwrap();
+            match *blocks_until_reorg {
+                Some(0) => {
+                    reorged.store(true, Ordering::SeqCst);
+                    *blocks_until_reorg = None;
+                }
+                Some(blocks) => *blocks_until_reorg = Some(blocks - 1),
+                None => (),
+            }
+
+            Self::block_data(&reorged, n)
+        });
+
+        Box::pin(stream::iter(values))
+    }
+
+    async fn spent_index(&self, block_height: Height) -> Result<SpentIndexData> {
+        let n = block_height.to_consensus_u32();
+        self.requests.lock().unwrap().spent_index.push(n);
+
+        let mut spent_index = MockChainBackend {}.spent_index(block_height).await?;
+        spent_index.spending_txs = spent_index
+            .data
+            .iter()
+            .map(|hash| SpendingTx {
+                txid: Txid::from_raw_hash(sha256d::Hash::hash(hash)),
+                vin: 0,
+            })
+            .collect();
+        if self.misaligned_spending_txs.load(Ordering::SeqCst) {
+            spent_index.spending_txs.remove(0);
+        }
+
+        Ok(spent_index)
+    }
+
+    async fn utxos(&self, block_height: Height) -> Result<Vec<UtxoData>> {
+        let n = block_height.to_consensus_u32();
+        self.requests.lock().unwrap().utxos.push(n);
+
+        let stall = self.stall_utxos.lock().unwrap().take();
+        if let Some(stalled) = stall {
+            stalled.send(()).unwrap();
+            future::pending::<()>().await;
+        }
+
+        let mut utxos = MockChainBackend {}.utxos(block_height).await?;
+        if self.invalid_utxo.load(Ordering::SeqCst) {
+            let txids: HashSet<Txid> = utxos.iter().map(|utxo| utxo.txid).collect();
+            for txid in txids {
+                utxos.push(UtxoData {
+                    txid,
+                    vout: 1000,
+                    value: Amount::from_sat(1000),
+                    scriptpubkey: ScriptBuf::from_bytes([&[0x51, 0x20][..], &[0; 32]].concat()),
+                    spent: false,
+                });
+            }
+        }
+
+        Ok(utxos)
+    }
+
+    async fn block_height(&self) -> Result<Height> {
+        let tip = self.tip.lock().unwrap().unwrap_or(TIP_HEIGHT);
+        Ok(Height::from_consensus(tip)?)
+    }
+
+    async fn block_hash(&self, block_height: Height) -> Result<BlockHash> {
+        Ok(Self::block_data(&self.reorged, block_height.to_consensus_u32())?.blkhash)
+    }
+
+    async fn mempool(&self, _dust_limit: Amount) -> Result<Vec<MempoolTxData>> {
+        let heights = self.mempool.lock().unwrap().clone();
+
+        let mut txs = vec![];
+        for n in heights {
+            let block_height = Height::from_consensus(n)?;
+            let tweaks = block_data(n)?.tweaks;
+            let mut utxos = MockChainBackend {}.utxos(block_height).await?;
+
+            // the fixtures don't link the tweaks to the transactions,
+            // the scanner tries all tweaks on all outputs, so the outputs are added to the first one
+            for tweak in tweaks {
+                txs.push(MempoolTxData {
+                    txid: utxos
+                        .first()
+                        .map(|utxo| utxo.txid)
+                        .unwrap_or(Txid::all_zeros()),
+                    tweak,
+                    outputs: std::mem::take(&mut utxos),
+                });
+            }
+        }
+
+        Ok(txs)
+    }
 }
@@ -1,2 +1,3 @@
 pub mod chain;
+pub mod scan_key;
 pub mod updater;
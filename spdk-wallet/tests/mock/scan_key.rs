@@ -0,0 +1,47 @@
+use anyhow::Result;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use silentpayments::{
+    SharedSecret, receiving::Label, utils::receiving::calculate_ecdh_shared_secret,
+};
+
+use spdk_wallet::client::ScanKeyProvider;
+
+/// Stand-in for a scan key that lives outside of the wallet, e.g. on a hardware device.
+#[derive(Clone)]
+pub struct MockScanKeyProvider {
+    scan_sk: SecretKey,
+    pub ecdh_calls: Arc<AtomicUsize>,
+}
+
+impl MockScanKeyProvider {
+    pub fn new(scan_sk: SecretKey) -> Self {
+        Self {
+            scan_sk,
+            ecdh_calls: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl ScanKeyProvider for MockScanKeyProvider {
+    fn scan_pubkey(&self) -> Result<PublicKey> {
+        Ok(self.scan_sk.public_key(&Secp256k1::signing_only()))
+    }
+
+    fn label(&self, m: u32) -> Result<Label> {
+        Ok(Label::new(self.scan_sk, m))
+    }
+
+    fn ecdh_batch(&self, tweaks: &[PublicKey]) -> Result<Vec<SharedSecret>> {
+        self.ecdh_calls.fetch_add(1, Ordering::SeqCst);
+
+        Ok(tweaks
+            .iter()
+            .map(|tweak| calculate_ecdh_shared_secret(tweak, &self.scan_sk))
+            .collect())
+    }
+}
@@ -4,9 +4,13 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use async_trait::async_trait;
 use bitcoin::{BlockHash, OutPoint, absolute::Height};
+use tokio::sync::mpsc;
 
-use spdk_core::updater::{DiscoveredOutput, Updater};
+use spdk_core::chain::SpendingTx;
+use spdk_core::updater::{AsyncUpdater, DiscoveredOutput, Updater};
+use spdk_wallet::history::TxHistory;
 
 pub struct UpdateResult {
     pub blkheight: Height,
@@ -15,9 +19,18 @@ pub struct UpdateResult {
     pub discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
 }
 
+pub struct MempoolUpdate {
+    pub new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    pub dropped_outputs: HashSet<OutPoint>,
+}
+
 #[derive(Clone, Default)]
 pub struct MockUpdater {
     pub updates: Arc<Mutex<Vec<UpdateResult>>>,
+    pub rollbacks: Arc<Mutex<Vec<(Height, BlockHash)>>>,
+    pub progress: Arc<Mutex<Vec<(Height, BlockHash)>>>,
+    pub mempool: Arc<Mutex<Vec<MempoolUpdate>>>,
+    pub spending_txs: Arc<Mutex<HashMap<OutPoint, SpendingTx>>>,
 }
 
 impl Updater for MockUpdater {
@@ -37,4 +50,176 @@ impl Updater for MockUpdater {
 
         Ok(())
     }
+
+    fn rollback_to_block(&mut self, blkheight: Height, blkhash: BlockHash) -> Result<()> {
+        self.updates
+            .lock()
+            .unwrap()
+            .retain(|update| update.blkheight <= blkheight);
+        self.rollbacks.lock().unwrap().push((blkheight, blkhash));
+
+        Ok(())
+    }
+
+    fn record_progress(&mut self, blkheight: Height, blkhash: BlockHash) -> Result<()> {
+        self.progress.lock().unwrap().push((blkheight, blkhash));
+
+        Ok(())
+    }
+
+    fn record_spending_txs(
+        &mut self,
+        _blkheight: Height,
+        spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()> {
+        self.spending_txs.lock().unwrap().extend(spending_txs);
+
+        Ok(())
+    }
+
+    fn record_mempool_scan_result(
+        &mut self,
+        new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+        dropped_outputs: HashSet<OutPoint>,
+    ) -> Result<()> {
+        self.mempool.lock().unwrap().push(MempoolUpdate {
+            new_outputs,
+            dropped_outputs,
+        });
+
+        Ok(())
+    }
+}
+
+/// An [Updater] that fails to record a block with outputs.
+pub struct FailingUpdater;
+
+impl Updater for FailingUpdater {
+    fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        _blkhash: BlockHash,
+        _discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()> {
+        if discovered_outputs.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(format!(
+                "Failed to record block {}",
+                blkheight
+            )))
+        }
+    }
+
+    fn rollback_to_block(&mut self, _blkheight: Height, _blkhash: BlockHash) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An [AsyncUpdater] that sends the results over a channel.
+#[derive(Clone)]
+pub struct ChannelUpdater {
+    sender: mpsc::Sender<UpdateResult>,
+    pub rollbacks: Arc<Mutex<Vec<(Height, BlockHash)>>>,
+}
+
+impl ChannelUpdater {
+    pub fn new(sender: mpsc::Sender<UpdateResult>) -> Self {
+        Self {
+            sender,
+            rollbacks: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncUpdater for ChannelUpdater {
+    async fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        blkhash: BlockHash,
+        discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()> {
+        self.sender
+            .send(UpdateResult {
+                blkheight,
+                blkhash,
+                discovered_inputs,
+                discovered_outputs,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rollback_to_block(&mut self, blkheight: Height, blkhash: BlockHash) -> Result<()> {
+        self.rollbacks.lock().unwrap().push((blkheight, blkhash));
+
+        Ok(())
+    }
+}
+
+/// An [Updater] that keeps the [TxHistory] and the unspent outputs, like a wallet would.
+///
+/// The unspent outputs are not rolled back, they're only used to create transactions.
+#[derive(Clone, Default)]
+pub struct HistoryUpdater {
+    pub history: Arc<Mutex<TxHistory>>,
+    pub utxos: Arc<Mutex<HashMap<OutPoint, DiscoveredOutput>>>,
+}
+
+impl Updater for HistoryUpdater {
+    fn record_block_scan_result(
+        &mut self,
+        blkheight: Height,
+        _blkhash: BlockHash,
+        discovered_inputs: HashSet<OutPoint>,
+        discovered_outputs: HashMap<OutPoint, DiscoveredOutput>,
+    ) -> Result<()> {
+        self.history.lock().unwrap().record_block_scan_result(
+            blkheight,
+            &discovered_inputs,
+            &discovered_outputs,
+        );
+
+        let mut utxos = self.utxos.lock().unwrap();
+        utxos.retain(|outpoint, _| !discovered_inputs.contains(outpoint));
+        utxos.extend(discovered_outputs);
+
+        Ok(())
+    }
+
+    fn rollback_to_block(&mut self, blkheight: Height, _blkhash: BlockHash) -> Result<()> {
+        self.history.lock().unwrap().rollback_to_block(blkheight);
+
+        Ok(())
+    }
+
+    fn record_spending_txs(
+        &mut self,
+        blkheight: Height,
+        spending_txs: HashMap<OutPoint, SpendingTx>,
+    ) -> Result<()> {
+        self.history
+            .lock()
+            .unwrap()
+            .record_spending_txs(blkheight, &spending_txs);
+
+        Ok(())
+    }
+
+    fn record_mempool_scan_result(
+        &mut self,
+        new_outputs: HashMap<OutPoint, DiscoveredOutput>,
+        dropped_outputs: HashSet<OutPoint>,
+    ) -> Result<()> {
+        self.history
+            .lock()
+            .unwrap()
+            .record_mempool_scan_result(&new_outputs, &dropped_outputs);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,309 @@
+use bitcoin::hex::FromHex;
+use bitcoin::psbt::{Psbt, raw};
+use bitcoin::secp256k1::{Scalar, Secp256k1, SecretKey};
+use bitcoin::{Amount, Network, OutPoint, ScriptBuf};
+use silentpayments::SilentPaymentAddressDisplay;
+use spdk_core::constants::{
+    PSBT_GLOBAL_SP_DLEQ, PSBT_GLOBAL_SP_ECDH_SHARE, PSBT_IN_SP_DLEQ, PSBT_IN_SP_ECDH_SHARE,
+    PSBT_OUT_SP_V0_INFO, PSBT_OUT_SP_V0_LABEL,
+};
+use spdk_core::updater::DiscoveredOutput;
+use spdk_wallet::client::{
+    FeeRate, Recipient, RecipientAddress, SilentPaymentUnsignedTransaction, SpClient, SpendKey,
+};
+
+fn create_client() -> SpClient {
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+
+    SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap()
+}
+
+// the output found in block 295125 for the client above
+fn owned_output() -> (OutPoint, DiscoveredOutput) {
+    let outpoint: OutPoint = "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+        .parse()
+        .unwrap();
+
+    let tweak = Scalar::from_be_bytes(
+        Vec::from_hex("78258954dccdba6597729dab70068bc4353ebd046f7156d9a3f8db8438b62aa5")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let script_pubkey =
+        ScriptBuf::from_hex("5120dbd93fdd869e3522405749a594c2e3f4833ac98d0f4e70da6e7294f6623258c3")
+            .unwrap();
+
+    let output = DiscoveredOutput {
+        tweak,
+        value: Amount::from_sat(10000),
+        script_pubkey,
+        label: None,
+    };
+
+    (outpoint, output)
+}
+
+fn create_unsigned_transaction(client: &SpClient) -> SilentPaymentUnsignedTransaction {
+    let secp = Secp256k1::signing_only();
+    let other_scan = SecretKey::from_slice(&[0x03; 32]).unwrap();
+    let other_spend = SecretKey::from_slice(&[0x04; 32]).unwrap();
+
+    let recipient_address = SilentPaymentAddressDisplay::new_v0(
+        other_scan.public_key(&secp),
+        other_spend.public_key(&secp),
+        silentpayments::Network::Testnet,
+    );
+
+    let recipients = vec![
+        Recipient {
+            address: RecipientAddress::SpAddress(recipient_address),
+            amount: Amount::from_sat(5000),
+        },
+        Recipient {
+            address: RecipientAddress::Data(b"hello".to_vec()),
+            amount: Amount::ZERO,
+        },
+    ];
+
+    client
+        .create_new_transaction(
+            vec![owned_output()],
+            recipients,
+            FeeRate::from_sat_per_vb(1.0),
+            Network::Signet,
+        )
+        .unwrap()
+}
+
+#[test]
+fn psbt_roundtrip() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    // recipient, data and change outputs
+    assert_eq!(psbt.outputs.len(), 3);
+
+    // one share for the recipient, one for our own change address
+    let shares = psbt
+        .unknown
+        .keys()
+        .filter(|k| k.type_value == PSBT_GLOBAL_SP_ECDH_SHARE)
+        .count();
+    assert_eq!(shares, 2);
+
+    let proofs = psbt
+        .unknown
+        .keys()
+        .filter(|k| k.type_value == PSBT_GLOBAL_SP_DLEQ)
+        .count();
+    assert_eq!(proofs, 2);
+
+    let sp_info_key = raw::Key {
+        type_value: PSBT_OUT_SP_V0_INFO,
+        key: vec![],
+    };
+    assert!(psbt.outputs[0].unknown.contains_key(&sp_info_key));
+    assert!(!psbt.outputs[1].unknown.contains_key(&sp_info_key));
+    assert!(psbt.outputs[2].unknown.contains_key(&sp_info_key));
+
+    // the input has a share and a proof for both scan keys too
+    for type_value in [PSBT_IN_SP_ECDH_SHARE, PSBT_IN_SP_DLEQ] {
+        let count = psbt.inputs[0]
+            .unknown
+            .keys()
+            .filter(|k| k.type_value == type_value)
+            .count();
+        assert_eq!(count, 2);
+    }
+
+    // the change goes to the change label
+    let label_key = raw::Key {
+        type_value: PSBT_OUT_SP_V0_LABEL,
+        key: vec![],
+    };
+    assert!(!psbt.outputs[0].unknown.contains_key(&label_key));
+    assert_eq!(psbt.outputs[2].unknown[&label_key], 0u32.to_le_bytes());
+
+    // go through the serialized format
+    let psbt = Psbt::deserialize(&psbt.serialize()).unwrap();
+
+    let parsed = client.unsigned_transaction_from_psbt(&psbt).unwrap();
+
+    let finalized = SpClient::finalize_transaction(unsigned_transaction).unwrap();
+
+    assert_eq!(parsed.recipients, finalized.recipients);
+    assert_eq!(parsed.unsigned_tx, finalized.unsigned_tx);
+    assert_eq!(parsed.selected_utxos.len(), 1);
+
+    let (outpoint, output) = owned_output();
+    assert_eq!(parsed.selected_utxos[0].0, outpoint);
+    assert_eq!(parsed.selected_utxos[0].1.tweak, output.tweak);
+    assert_eq!(parsed.selected_utxos[0].1.value, output.value);
+    assert_eq!(
+        parsed.selected_utxos[0].1.script_pubkey,
+        output.script_pubkey
+    );
+
+    // the parsed transaction can be signed
+    client.sign_transaction(parsed, &[0xaa; 32]).unwrap();
+}
+
+#[test]
+fn psbt_with_empty_sp_output_script() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let mut psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    let expected_script = psbt.unsigned_tx.output[0].script_pubkey.clone();
+    psbt.unsigned_tx.output[0].script_pubkey = ScriptBuf::new();
+
+    let parsed = client.unsigned_transaction_from_psbt(&psbt).unwrap();
+
+    assert_eq!(
+        parsed.unsigned_tx.unwrap().output[0].script_pubkey,
+        expected_script
+    );
+}
+
+#[test]
+fn psbt_with_wrong_ecdh_share() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let mut psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    let secp = Secp256k1::signing_only();
+    let wrong_share = SecretKey::from_slice(&[0x05; 32])
+        .unwrap()
+        .public_key(&secp);
+
+    for (key, value) in psbt.unknown.iter_mut() {
+        if key.type_value == PSBT_GLOBAL_SP_ECDH_SHARE {
+            *value = wrong_share.serialize().to_vec();
+        }
+    }
+
+    assert!(client.unsigned_transaction_from_psbt(&psbt).is_err());
+}
+
+#[test]
+fn psbt_with_wrong_sp_output_script() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let mut psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    // replace the recipient output with our change output
+    psbt.unsigned_tx.output[0].script_pubkey = psbt.unsigned_tx.output[2].script_pubkey.clone();
+
+    assert!(client.unsigned_transaction_from_psbt(&psbt).is_err());
+}
+
+#[test]
+fn psbt_with_wrong_dleq_proof() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let mut psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    for (key, value) in psbt.unknown.iter_mut() {
+        if key.type_value == PSBT_GLOBAL_SP_DLEQ {
+            value[63] ^= 1;
+        }
+    }
+
+    assert!(client.unsigned_transaction_from_psbt(&psbt).is_err());
+}
+
+#[test]
+fn psbt_without_dleq_proof() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    let mut global = psbt.clone();
+    global
+        .unknown
+        .retain(|key, _| key.type_value != PSBT_GLOBAL_SP_DLEQ);
+    assert!(client.unsigned_transaction_from_psbt(&global).is_err());
+
+    let mut per_input = psbt;
+    per_input.inputs[0]
+        .unknown
+        .retain(|key, _| key.type_value != PSBT_IN_SP_DLEQ);
+    assert!(client.unsigned_transaction_from_psbt(&per_input).is_err());
+}
+
+#[test]
+fn psbt_with_wrong_label() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let mut psbt = client
+        .unsigned_transaction_to_psbt(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    // the recipient isn't one of our addresses
+    psbt.outputs[0].unknown.insert(
+        raw::Key {
+            type_value: PSBT_OUT_SP_V0_LABEL,
+            key: vec![],
+        },
+        0u32.to_le_bytes().to_vec(),
+    );
+
+    assert!(client.unsigned_transaction_from_psbt(&psbt).is_err());
+}
+
+#[test]
+fn psbt_v2_roundtrip() {
+    let client = create_client();
+    let unsigned_transaction = create_unsigned_transaction(&client);
+
+    let psbt = client
+        .unsigned_transaction_to_psbt_v2(&unsigned_transaction, &[0xaa; 32])
+        .unwrap();
+
+    // rust-bitcoin only parses version 0
+    assert!(Psbt::deserialize(&psbt).is_err());
+
+    let parsed = client.unsigned_transaction_from_psbt_v2(&psbt).unwrap();
+
+    let finalized = SpClient::finalize_transaction(unsigned_transaction).unwrap();
+
+    assert_eq!(parsed.recipients, finalized.recipients);
+    assert_eq!(parsed.unsigned_tx, finalized.unsigned_tx);
+    assert_eq!(parsed.selected_utxos[0].0, owned_output().0);
+
+    client.sign_transaction(parsed, &[0xaa; 32]).unwrap();
+
+    // a version 0 PSBT is rejected
+    let v0 = client
+        .unsigned_transaction_to_psbt(&finalized, &[0xaa; 32])
+        .unwrap();
+    assert!(
+        client
+            .unsigned_transaction_from_psbt_v2(&v0.serialize())
+            .is_err()
+    );
+}
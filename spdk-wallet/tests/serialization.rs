@@ -0,0 +1,77 @@
+use bitcoin::Network;
+use bitcoin::secp256k1::SecretKey;
+use serde_json::Value;
+use silentpayments::receiving::Receiver;
+use spdk_wallet::client::{SpClient, SpendKey};
+
+fn client(seed: u8) -> SpClient {
+    let scan_sk = SecretKey::from_slice(&[seed; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[seed + 1; 32]).unwrap();
+    SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap()
+}
+
+#[test]
+fn client_roundtrip() {
+    let client = client(1);
+
+    let json = serde_json::to_value(&client).unwrap();
+    assert_eq!(json["schema_version"], SpClient::SCHEMA_VERSION);
+
+    let deserialized: SpClient = serde_json::from_value(json).unwrap();
+    assert_eq!(client, deserialized);
+}
+
+#[test]
+fn legacy_client_is_migrated() {
+    let client = client(1);
+    let json = serde_json::to_value(&client).unwrap();
+
+    // the original format has no schema versions, for both the client and its receiver
+    let mut legacy = json.clone();
+    legacy.as_object_mut().unwrap().remove("schema_version");
+    legacy["sp_receiver"]
+        .as_object_mut()
+        .unwrap()
+        .remove("schema_version");
+
+    let migrated: SpClient = serde_json::from_value(legacy).unwrap();
+    assert_eq!(client, migrated);
+    assert_eq!(serde_json::to_value(&migrated).unwrap(), json);
+}
+
+#[test]
+fn corrupt_client_returns_error() {
+    let json = serde_json::to_value(client(1)).unwrap();
+    let modified = |f: &dyn Fn(&mut Value)| {
+        let mut json = json.clone();
+        f(&mut json);
+        serde_json::from_value::<SpClient>(json)
+    };
+
+    assert!(modified(&|_| ()).is_ok());
+
+    assert!(
+        modified(&|json| json["schema_version"] = (SpClient::SCHEMA_VERSION + 1).into()).is_err()
+    );
+    assert!(
+        modified(
+            &|json| json["sp_receiver"]["schema_version"] = (Receiver::SCHEMA_VERSION + 1).into()
+        )
+        .is_err()
+    );
+    assert!(modified(&|json| json["sp_receiver"]["version"] = 31.into()).is_err());
+    assert!(modified(&|json| json["sp_receiver"]["scan_pubkey"][0] = 0x05.into()).is_err());
+    assert!(modified(&|json| json["sp_receiver"]["change_label"] = "00".into()).is_err());
+    assert!(modified(&|json| json["network"] = "bitcoin".into()).is_err());
+    assert!(modified(&|json| json["scan_sk"] = json["spend_key"]["Secret"].clone()).is_err());
+
+    // the receiver of another wallet
+    let other = serde_json::to_value(client(3)).unwrap();
+    assert!(modified(&|json| json["sp_receiver"] = other["sp_receiver"].clone()).is_err());
+
+    // truncated files
+    let serialized = json.to_string();
+    for len in (0..serialized.len()).step_by(7) {
+        assert!(serde_json::from_str::<SpClient>(&serialized[..len]).is_err());
+    }
+}
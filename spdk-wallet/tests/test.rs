@@ -1,16 +1,25 @@
 use std::collections::HashSet;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
 use bitcoin::absolute::Height;
 use bitcoin::hex::FromHex;
-use bitcoin::secp256k1::{Scalar, SecretKey};
-use bitcoin::{Amount, BlockHash, Network, OutPoint, ScriptBuf};
+use bitcoin::secp256k1::{Scalar, Secp256k1, SecretKey};
+use bitcoin::{Amount, BlockHash, Network, OutPoint, ScriptBuf, SignedAmount};
+use futures::{StreamExt, stream};
+use silentpayments::SilentPaymentAddressDisplay;
 use silentpayments::receiving::Label;
-use spdk_wallet::client::{SpClient, SpendKey};
-use spdk_wallet::scanner::SpScanner;
+use spdk_core::chain::ChainBackend;
+use spdk_wallet::client::{FeeRate, Recipient, RecipientAddress, SpClient, SpendKey};
+use spdk_wallet::history::{TxDirection, TxHistory};
+use spdk_wallet::scanner::{CancellationToken, ScanWallet, SpMultiScanner, SpScanner};
+use tokio::sync::mpsc;
 
-use crate::mock::chain::MockChainBackend;
-use crate::mock::updater::MockUpdater;
+use crate::mock::chain::{FORK_HEIGHT, MockChainBackend, MockReorgBackend, TIP_HEIGHT};
+use crate::mock::scan_key::MockScanKeyProvider;
+use crate::mock::updater::{
+    ChannelUpdater, FailingUpdater, HistoryUpdater, MockUpdater, UpdateResult,
+};
 
 mod mock;
 
@@ -28,7 +37,7 @@ async fn simple_scan_single_block() {
     let spend_key = SpendKey::Secret(spend_sk);
 
     let network = Network::Bitcoin;
-    let keep_scanning = AtomicBool::new(true);
+    let cancel = CancellationToken::new();
     let owned_outpoints = HashSet::new();
 
     let client = SpClient::new(scan_sk, spend_key, network).unwrap();
@@ -38,7 +47,7 @@ async fn simple_scan_single_block() {
         Box::new(mock_update),
         Box::new(mock_backend),
         owned_outpoints,
-        &keep_scanning,
+        cancel.clone(),
     );
 
     let block_height: Height = Height::from_consensus(200000).unwrap();
@@ -76,7 +85,7 @@ async fn simple_scan_multiple_blocks() {
     let spend_key = SpendKey::Secret(spend_sk);
 
     let network = Network::Bitcoin;
-    let keep_scanning = AtomicBool::new(true);
+    let cancel = CancellationToken::new();
     let owned_outpoints = HashSet::new();
 
     let client = SpClient::new(scan_sk, spend_key, network).unwrap();
@@ -86,7 +95,7 @@ async fn simple_scan_multiple_blocks() {
         Box::new(mock_update),
         Box::new(mock_backend),
         owned_outpoints,
-        &keep_scanning,
+        cancel.clone(),
     );
 
     let first_block_height: Height = Height::from_consensus(200000).unwrap();
@@ -153,7 +162,7 @@ async fn scan_single_block_with_output() {
     let spend_key = SpendKey::Secret(spend_sk);
 
     let network = Network::Signet;
-    let keep_scanning = AtomicBool::new(true);
+    let cancel = CancellationToken::new();
     let owned_outpoints = HashSet::new();
 
     let client = SpClient::new(scan_sk, spend_key, network).unwrap();
@@ -163,7 +172,7 @@ async fn scan_single_block_with_output() {
         Box::new(mock_update),
         Box::new(mock_backend),
         owned_outpoints,
-        &keep_scanning,
+        cancel.clone(),
     );
 
     let block_height = Height::from_consensus(295125).unwrap();
@@ -192,6 +201,47 @@ async fn scan_single_block_with_output() {
     assert_eq!(discovered_output.unwrap().label, expected_label);
 }
 
+#[tokio::test]
+async fn outputs_with_invalid_keys_are_skipped() {
+    let expected_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+    mock_backend.add_invalid_utxo();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend),
+        HashSet::new(),
+        CancellationToken::new(),
+    );
+
+    let block_height = Height::from_consensus(295125).unwrap();
+
+    scanner
+        .scan_blocks(block_height..=block_height, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // the transaction of our output is still scanned
+    let updates = updates.lock().unwrap();
+    assert_eq!(updates.len(), 1);
+    assert_eq!(
+        updates[0].discovered_outputs.keys().collect::<Vec<_>>(),
+        vec![&expected_outpoint]
+    );
+}
+
 #[tokio::test]
 async fn scan_single_block_with_spent_input() {
     let owned_outpoint: OutPoint =
@@ -203,13 +253,14 @@ async fn scan_single_block_with_spent_input() {
 
     let mock_update = MockUpdater::default();
     let updates = mock_update.updates.clone();
+    let spending_txs = mock_update.spending_txs.clone();
 
     let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
     let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
     let spend_key = SpendKey::Secret(spend_sk);
 
     let network = Network::Signet;
-    let keep_scanning = AtomicBool::new(true);
+    let cancel = CancellationToken::new();
     let mut owned_outpoints = HashSet::new();
 
     owned_outpoints.insert(owned_outpoint);
@@ -221,7 +272,7 @@ async fn scan_single_block_with_spent_input() {
         Box::new(mock_update),
         Box::new(mock_backend),
         owned_outpoints,
-        &keep_scanning,
+        cancel.clone(),
     );
 
     let block_height = Height::from_consensus(295147).unwrap();
@@ -244,4 +295,1425 @@ async fn scan_single_block_with_spent_input() {
     let spent_outpoint = discovered_inputs.iter().next().unwrap();
 
     assert_eq!(*spent_outpoint, owned_outpoint);
+
+    // blindbit doesn't know the spending transaction
+    assert!(spending_txs.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn spending_txs_from_backend() {
+    let owned_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let spending_txs = mock_update.spending_txs.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+    let cancel = CancellationToken::new();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::from([owned_outpoint]),
+        cancel.clone(),
+    );
+
+    let block_height = Height::from_consensus(295147).unwrap();
+
+    scanner
+        .scan_blocks(block_height..=block_height, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let spent_index = mock_backend.spent_index(block_height).await.unwrap();
+
+    let updates = updates.lock().unwrap();
+    assert_eq!(
+        updates[0].discovered_inputs,
+        HashSet::from([owned_outpoint])
+    );
+
+    // the spend is linked to the transaction of the spent index
+    let spending_txs = spending_txs.lock().unwrap();
+    assert_eq!(spending_txs.len(), 1);
+    assert!(
+        spent_index
+            .spending_txs
+            .contains(&spending_txs[&owned_outpoint])
+    );
+}
+
+#[tokio::test]
+async fn misaligned_spending_txs_are_rejected() {
+    let owned_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+    mock_backend.misalign_spending_txs();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let spending_txs = mock_update.spending_txs.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+    let cancel = CancellationToken::new();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::from([owned_outpoint]),
+        cancel.clone(),
+    );
+
+    let block_height = Height::from_consensus(295147).unwrap();
+
+    let err = scanner
+        .scan_blocks(block_height..=block_height, DUST_LIMIT, true)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("spending transactions"));
+
+    // the block isn't recorded
+    assert!(updates.lock().unwrap().is_empty());
+    assert!(spending_txs.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn scan_single_block_with_scan_key_provider() {
+    let expected_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockChainBackend {};
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+    let owned_outpoints = HashSet::new();
+
+    let provider = MockScanKeyProvider::new(scan_sk);
+    let ecdh_calls = provider.ecdh_calls.clone();
+
+    // the client doesn't know the scan key
+    let client =
+        SpClient::new_with_scan_key_provider(&provider, spend_key.clone(), network).unwrap();
+    assert!(client.try_get_scan_key().is_err());
+
+    // same addresses as a client that holds the scan key
+    let local_client = SpClient::new(scan_sk, spend_key, network).unwrap();
+    assert_eq!(
+        client.get_receiving_address(),
+        local_client.get_receiving_address()
+    );
+    assert_eq!(
+        client.sp_receiver.get_change_address(),
+        local_client.sp_receiver.get_change_address()
+    );
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend),
+        owned_outpoints,
+        cancel.clone(),
+    )
+    .with_scan_key_provider(Box::new(provider))
+    .unwrap();
+
+    let block_height = Height::from_consensus(295125).unwrap();
+
+    scanner
+        .scan_blocks(block_height..=block_height, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // all shared secrets for the block are computed in a single batch
+    assert_eq!(ecdh_calls.load(Ordering::SeqCst), 1);
+
+    let updates = updates.lock().unwrap();
+    assert!(updates.len() == 1);
+
+    let discovered_outputs = &updates[0].discovered_outputs;
+    assert_eq!(discovered_outputs.len(), 1);
+    assert!(discovered_outputs.contains_key(&expected_outpoint));
+}
+
+#[tokio::test]
+async fn scan_key_provider_must_match_client() {
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let other_sk = SecretKey::from_slice(&[0x03; 32]).unwrap();
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), network).unwrap();
+
+    let scanner = SpScanner::new(
+        client,
+        Box::new(MockUpdater::default()),
+        Box::new(MockChainBackend {}),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    assert!(
+        scanner
+            .with_scan_key_provider(Box::new(MockScanKeyProvider::new(other_sk)))
+            .is_err()
+    );
+
+    // without a provider, a client that doesn't know the scan key can't scan
+    let provider = MockScanKeyProvider::new(scan_sk);
+    let client =
+        SpClient::new_with_scan_key_provider(&provider, SpendKey::Secret(spend_sk), network)
+            .unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(MockUpdater::default()),
+        Box::new(MockChainBackend {}),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let block_height = Height::from_consensus(295125).unwrap();
+    assert!(
+        scanner
+            .scan_blocks(block_height..=block_height, DUST_LIMIT, true)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn reorg_between_scans() {
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let rollbacks = mock_update.rollbacks.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+    let fork_height = Height::from_consensus(FORK_HEIGHT).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let orphaned_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    // the output in block 295125 is found
+    {
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.len(), 11);
+        assert!(
+            updates[5]
+                .discovered_outputs
+                .contains_key(&orphaned_outpoint)
+        );
+    }
+
+    // block 295125 is replaced by a block without the output
+    mock_backend.reorg();
+
+    let start = Height::from_consensus(295131).unwrap();
+    let end = Height::from_consensus(295147).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let expected_fork_hash = mock_backend.block_hash(fork_height).await.unwrap();
+    assert_eq!(
+        *rollbacks.lock().unwrap(),
+        vec![(fork_height, expected_fork_hash)]
+    );
+
+    // the blocks above the fork are scanned again, on the new chain
+    let updates: Vec<_> = updates.lock().unwrap().drain(..).collect();
+    let heights: Vec<u32> = updates
+        .iter()
+        .map(|update| update.blkheight.to_consensus_u32())
+        .collect();
+    assert_eq!(heights, (295120..=295147).collect::<Vec<_>>());
+
+    for update in updates.iter() {
+        let blkhash = mock_backend.block_hash(update.blkheight).await.unwrap();
+        assert_eq!(update.blkhash, blkhash);
+
+        // the output was rolled back, so its spend in block 295147 is not found either
+        assert!(!update.discovered_outputs.contains_key(&orphaned_outpoint));
+        assert!(update.discovered_inputs.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn reorg_during_scan() {
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let rollbacks = mock_update.rollbacks.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+    let fork_height = Height::from_consensus(FORK_HEIGHT).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // scan the same blocks again, the chain switches after 3 blocks
+    mock_backend.reorg_after(3);
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    assert_eq!(rollbacks.lock().unwrap().len(), 1);
+    assert_eq!(rollbacks.lock().unwrap()[0].0, fork_height);
+
+    // the replaced block is found while scanning, and the scan continues from the fork
+    let updates: Vec<_> = updates.lock().unwrap().drain(..).collect();
+    let heights: Vec<u32> = updates
+        .iter()
+        .map(|update| update.blkheight.to_consensus_u32())
+        .collect();
+    let expected: Vec<u32> = (295120..=295124).chain(295120..=295130).collect();
+    assert_eq!(heights, expected);
+
+    for update in updates.iter() {
+        let blkhash = mock_backend.block_hash(update.blkheight).await.unwrap();
+        assert_eq!(update.blkhash, blkhash);
+        assert!(update.discovered_outputs.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn scan_with_async_updater() {
+    let mock_backend = MockReorgBackend::default();
+
+    // the scanner waits for each result to be received
+    let (sender, mut receiver) = mpsc::channel(1);
+    let updater = ChannelUpdater::new(sender);
+    let rollbacks = updater.rollbacks.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new_with_async_updater(
+        client,
+        Box::new(updater),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    async fn receive(receiver: &mut mpsc::Receiver<UpdateResult>, n: usize) -> Vec<UpdateResult> {
+        let mut res = vec![];
+        for _ in 0..n {
+            res.push(receiver.recv().await.unwrap());
+        }
+        res
+    }
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+
+    let (scanned, updates) = tokio::join!(
+        scanner.scan_blocks(start..=end, DUST_LIMIT, true),
+        receive(&mut receiver, 11)
+    );
+    scanned.unwrap();
+
+    assert_eq!(updates[0].blkheight, start);
+    assert_eq!(updates[5].discovered_outputs.len(), 1);
+
+    // the rollback is passed to the async updater too
+    mock_backend.reorg();
+
+    let height = Height::from_consensus(295131).unwrap();
+    let (scanned, updates) = tokio::join!(
+        scanner.scan_blocks(height..=height, DUST_LIMIT, true),
+        receive(&mut receiver, 7)
+    );
+    scanned.unwrap();
+
+    assert_eq!(rollbacks.lock().unwrap().len(), 1);
+    assert_eq!(
+        rollbacks.lock().unwrap()[0].0,
+        Height::from_consensus(FORK_HEIGHT).unwrap()
+    );
+    assert_eq!(updates[0].blkheight.to_consensus_u32(), FORK_HEIGHT + 1);
+    assert!(updates[0].discovered_outputs.is_empty());
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn progress_is_recorded_for_every_block() {
+    let mock_backend = MockChainBackend {};
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let progress = mock_update.progress.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Bitcoin;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    // there is nothing to resume from yet
+    assert!(scanner.scan_to_tip(DUST_LIMIT, true).await.is_err());
+
+    let start = Height::from_consensus(200000).unwrap();
+    let end = Height::from_consensus(200001).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // the blocks have no results, but the progress is recorded anyway
+    let expected: Vec<_> = updates
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|update| (update.blkheight, update.blkhash))
+        .collect();
+    assert_eq!(*progress.lock().unwrap(), expected);
+    assert_eq!(scanner.last_scanned_block(), expected.last().cloned());
+
+    // nothing is scanned after an interruption
+    cancel.cancel();
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+    assert_eq!(progress.lock().unwrap().len(), 2);
+
+    // until the token is replaced
+    scanner.set_cancellation_token(CancellationToken::new());
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+    assert_eq!(progress.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn resume_from_checkpoints() {
+    let mock_backend = MockReorgBackend::default();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mock_update = MockUpdater::default();
+    let progress = mock_update.progress.clone();
+
+    let mut scanner = SpScanner::new(
+        client.clone(),
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // the app is killed, and the chain has a reorg in the meantime
+    let checkpoints = progress.lock().unwrap().clone();
+    drop(scanner);
+    mock_backend.reorg();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let rollbacks = mock_update.rollbacks.clone();
+    let progress = mock_update.progress.clone();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    )
+    .with_checkpoints(checkpoints);
+
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+
+    // the scan resumes from the fork, up to the tip
+    let fork_height = Height::from_consensus(FORK_HEIGHT).unwrap();
+    assert_eq!(rollbacks.lock().unwrap().len(), 1);
+    assert_eq!(rollbacks.lock().unwrap()[0].0, fork_height);
+
+    let heights: Vec<u32> = updates
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|update| update.blkheight.to_consensus_u32())
+        .collect();
+    assert_eq!(heights, (FORK_HEIGHT + 1..=TIP_HEIGHT).collect::<Vec<_>>());
+
+    {
+        let progress = progress.lock().unwrap();
+        assert_eq!(progress[0].0, fork_height);
+        assert_eq!(progress.last().unwrap().0.to_consensus_u32(), TIP_HEIGHT);
+    }
+
+    // already at the tip
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+    assert_eq!(heights.len(), updates.lock().unwrap().len());
+}
+
+#[tokio::test]
+async fn reorg_of_every_checkpoint_fails() {
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let rollbacks = mock_update.rollbacks.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    // resume from a single checkpoint, which is replaced by a reorg
+    let last_scan = Height::from_consensus(295130).unwrap();
+    let last_scan_hash = mock_backend.block_hash(last_scan).await.unwrap();
+    mock_backend.reorg();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    )
+    .with_checkpoints([(last_scan, last_scan_hash)]);
+
+    // the fork can be anywhere below the checkpoint
+    let err = scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap_err();
+    assert!(err.to_string().contains("the fork is unknown"));
+
+    assert!(rollbacks.lock().unwrap().is_empty());
+    assert!(updates.lock().unwrap().is_empty());
+    assert_eq!(
+        scanner.last_scanned_block(),
+        Some((last_scan, last_scan_hash))
+    );
+}
+
+#[tokio::test]
+async fn follow_tip_with_new_blocks_and_reorg() {
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let rollbacks = mock_update.rollbacks.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let birthday = Height::from_consensus(295120).unwrap();
+    let birthday_hash = mock_backend.block_hash(birthday).await.unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    )
+    .with_checkpoints([(birthday, birthday_hash)]);
+
+    mock_backend.set_tip(295122);
+
+    // every tick changes the chain: new blocks, a reorg of the tip, and more new blocks
+    let backend = mock_backend.clone();
+    let ticks = stream::iter(0..3).map(move |n| match n {
+        0 => backend.set_tip(295130),
+        1 => backend.reorg(),
+        _ => backend.set_tip(TIP_HEIGHT),
+    });
+
+    scanner.follow_tip(ticks, DUST_LIMIT, true).await.unwrap();
+
+    let fork_height = Height::from_consensus(FORK_HEIGHT).unwrap();
+    assert_eq!(rollbacks.lock().unwrap().len(), 1);
+    assert_eq!(rollbacks.lock().unwrap()[0].0, fork_height);
+
+    // every block is scanned once on the final chain
+    let updates: Vec<_> = updates.lock().unwrap().drain(..).collect();
+    let heights: Vec<u32> = updates
+        .iter()
+        .map(|update| update.blkheight.to_consensus_u32())
+        .collect();
+    assert_eq!(heights, (295121..=TIP_HEIGHT).collect::<Vec<_>>());
+
+    for update in updates.iter() {
+        let blkhash = mock_backend.block_hash(update.blkheight).await.unwrap();
+        assert_eq!(update.blkhash, blkhash);
+    }
+
+    // an interrupted scanner stops following
+    cancel.cancel();
+    scanner
+        .follow_tip(stream::repeat(()), DUST_LIMIT, true)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn pending_outputs_from_mempool() {
+    let pending_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let mempool = mock_update.mempool.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let birthday = Height::from_consensus(295124).unwrap();
+    let birthday_hash = mock_backend.block_hash(birthday).await.unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    )
+    .with_checkpoints([(birthday, birthday_hash)]);
+
+    // the payment of block 295125 is still in the mempool
+    mock_backend.set_tip(295124);
+    mock_backend.set_mempool(vec![295125]);
+
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+
+    {
+        let mempool: Vec<_> = mempool.lock().unwrap().drain(..).collect();
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool[0].new_outputs.len(), 1);
+        assert!(mempool[0].new_outputs.contains_key(&pending_outpoint));
+        assert!(mempool[0].dropped_outputs.is_empty());
+
+        // still pending, it's not reported again
+        assert!(mempool[1].new_outputs.is_empty());
+        assert!(mempool[1].dropped_outputs.is_empty());
+    }
+
+    // the payment is dropped from the mempool, and comes back later
+    mock_backend.set_mempool(vec![]);
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+    mock_backend.set_mempool(vec![295125]);
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+
+    {
+        let mempool: Vec<_> = mempool.lock().unwrap().drain(..).collect();
+        assert_eq!(mempool.len(), 2);
+        assert!(mempool[0].new_outputs.is_empty());
+        assert_eq!(
+            mempool[0].dropped_outputs,
+            HashSet::from([pending_outpoint])
+        );
+        assert!(mempool[1].new_outputs.contains_key(&pending_outpoint));
+    }
+
+    // the payment is confirmed, it's no longer pending
+    mock_backend.set_tip(295125);
+    mock_backend.set_mempool(vec![]);
+
+    // the new block isn't scanned yet, so the payment isn't reported as dropped
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+
+    {
+        let updates = updates.lock().unwrap();
+        assert!(
+            updates
+                .last()
+                .unwrap()
+                .discovered_outputs
+                .contains_key(&pending_outpoint)
+        );
+    }
+
+    let mempool: Vec<_> = mempool.lock().unwrap().drain(..).collect();
+    assert_eq!(mempool.len(), 2);
+    assert!(
+        mempool
+            .iter()
+            .all(|scan| scan.new_outputs.is_empty() && scan.dropped_outputs.is_empty())
+    );
+}
+
+#[tokio::test]
+async fn mempool_is_optional() {
+    let mock_update = MockUpdater::default();
+    let mempool = mock_update.mempool.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(MockChainBackend {}),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let err = scanner.scan_mempool(DUST_LIMIT).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The mempool is not supported by this backend"
+    );
+    assert!(mempool.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn scan_multiple_wallets() {
+    let outpoint: OutPoint = "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+        .parse()
+        .unwrap();
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    // the wallet of the fixtures, and a wallet without any outputs
+    let client = |scan: u8, spend: u8| {
+        let scan_sk = SecretKey::from_slice(&[scan; 32]).unwrap();
+        let spend_sk = SecretKey::from_slice(&[spend; 32]).unwrap();
+        SpClient::new(scan_sk, SpendKey::Secret(spend_sk), network).unwrap()
+    };
+    let clients = [client(0x01, 0x02), client(0x03, 0x04), client(0x01, 0x02)];
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(TIP_HEIGHT).unwrap();
+
+    // the requests of scanning every wallet on its own
+    let mut single_requests = vec![];
+    for client in clients.iter().take(2) {
+        let mock_backend = MockReorgBackend::default();
+        let mut scanner = SpScanner::new(
+            client.clone(),
+            Box::new(MockUpdater::default()),
+            Box::new(mock_backend.clone()),
+            HashSet::new(),
+            cancel.clone(),
+        );
+        scanner
+            .scan_blocks(start..=end, DUST_LIMIT, true)
+            .await
+            .unwrap();
+        single_requests.push(mock_backend.requests());
+    }
+
+    let mock_backend = MockReorgBackend::default();
+    let updaters = [
+        MockUpdater::default(),
+        MockUpdater::default(),
+        MockUpdater::default(),
+    ];
+
+    let wallets = clients
+        .into_iter()
+        .zip(updaters.iter())
+        .map(|(client, updater)| ScanWallet::new(client, Box::new(updater.clone()), HashSet::new()))
+        .collect();
+
+    let mut scanner = SpMultiScanner::new(wallets, Box::new(mock_backend.clone()), cancel.clone());
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // every block is fetched once
+    let requests = mock_backend.requests();
+    assert_eq!(
+        requests.block_data,
+        (295120..=TIP_HEIGHT).collect::<Vec<_>>()
+    );
+
+    // the utxos and spent index are fetched once for the blocks that match any wallet
+    let union = |heights: Vec<u32>, other: Vec<u32>| {
+        let mut heights: Vec<u32> = heights.into_iter().chain(other).collect();
+        heights.sort();
+        heights.dedup();
+        heights
+    };
+    let [first, second] = [single_requests[0].clone(), single_requests[1].clone()];
+    assert!(first.utxos.contains(&295125));
+    assert_eq!(requests.utxos, union(first.utxos, second.utxos));
+    assert_eq!(
+        requests.spent_index,
+        union(first.spent_index, second.spent_index)
+    );
+
+    // every wallet gets its own results
+    for (n, updater) in updaters.iter().enumerate() {
+        let updates = updater.updates.lock().unwrap();
+        assert_eq!(updates.len(), (295120..=TIP_HEIGHT).count());
+
+        let outputs: Vec<_> = updates
+            .iter()
+            .flat_map(|update| update.discovered_outputs.keys())
+            .collect();
+        let inputs: Vec<_> = updates
+            .iter()
+            .flat_map(|update| update.discovered_inputs.iter())
+            .collect();
+
+        if n == 1 {
+            assert!(outputs.is_empty());
+            assert!(inputs.is_empty());
+        } else {
+            assert!(outputs.contains(&&outpoint));
+            assert!(inputs.contains(&&outpoint));
+        }
+    }
+
+    assert!(scanner.wallets()[1].owned_outpoints().is_empty());
+    assert!(!scanner.wallets()[0].owned_outpoints().contains(&outpoint));
+}
+
+#[tokio::test]
+async fn updater_error_of_one_wallet() {
+    let outpoint: OutPoint = "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+        .parse()
+        .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+    let cancel = CancellationToken::new();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+
+    // the second wallet fails to record the output of block 295125
+    let updater = MockUpdater::default();
+    let wallets = vec![
+        ScanWallet::new(client.clone(), Box::new(updater.clone()), HashSet::new()),
+        ScanWallet::new(client, Box::new(FailingUpdater), HashSet::new()),
+    ];
+
+    let mut scanner = SpMultiScanner::new(wallets, Box::new(mock_backend.clone()), cancel.clone());
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+    let err = scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Failed to record block 295125");
+
+    // the first wallet recorded the block, the second didn't
+    let blkheight = Height::from_consensus(295125).unwrap();
+    assert_eq!(
+        updater.updates.lock().unwrap().last().unwrap().blkheight,
+        blkheight
+    );
+    assert!(scanner.wallets()[0].owned_outpoints().contains(&outpoint));
+    assert!(scanner.wallets()[1].owned_outpoints().is_empty());
+    assert_eq!(scanner.last_scanned_block().unwrap().0, blkheight);
+
+    // the block is rolled back for both wallets
+    mock_backend.reorg();
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let fork_height = Height::from_consensus(FORK_HEIGHT).unwrap();
+    assert_eq!(updater.rollbacks.lock().unwrap()[0].0, fork_height);
+    assert!(scanner.wallets()[0].owned_outpoints().is_empty());
+    assert!(scanner.wallets()[1].owned_outpoints().is_empty());
+}
+
+#[tokio::test]
+async fn progress_is_reported_for_every_block() {
+    let mock_backend = MockReorgBackend::default();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(MockUpdater::default()),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let progress = scanner.progress();
+    let dropped = scanner.progress();
+    drop(dropped);
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    // the stream ends when the scanner is dropped
+    drop(scanner);
+    let progress: Vec<_> = progress.collect().await;
+
+    let heights: Vec<u32> = progress
+        .iter()
+        .map(|progress| progress.blkheight.to_consensus_u32())
+        .collect();
+    assert_eq!(heights, (295120..=295130).collect::<Vec<_>>());
+
+    for (n, progress) in progress.iter().enumerate() {
+        assert_eq!(progress.blocks_done, n as u32 + 1);
+        assert_eq!(progress.blocks_total, 11);
+    }
+
+    let tweaks: Vec<usize> = mock_backend
+        .get_block_data_for_range(start..=end, DUST_LIMIT, true)
+        .map(|blockdata| blockdata.unwrap().tweaks.len())
+        .collect()
+        .await;
+    let last = progress.last().unwrap();
+    assert_eq!(last.tweaks_processed, tweaks.iter().sum::<usize>());
+    assert_eq!(last.eta(), Some(Duration::ZERO));
+
+    // the output in block 295125 matches the filter
+    let before = progress[4].matched_blocks;
+    assert_eq!(progress[5].matched_blocks, before + 1);
+}
+
+#[tokio::test]
+async fn cancel_aborts_pending_requests() {
+    let mock_backend = MockReorgBackend::default();
+    mock_backend.set_tip(295130);
+
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+    let progress = mock_update.progress.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let spend_key = SpendKey::Secret(spend_sk);
+
+    let network = Network::Signet;
+    let cancel = CancellationToken::new();
+
+    let client = SpClient::new(scan_sk, spend_key, network).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client.clone(),
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let start = Height::from_consensus(295120).unwrap();
+    let end = Height::from_consensus(295130).unwrap();
+
+    // the utxos of block 295125 are never returned
+    let stalled = mock_backend.stall_utxos();
+
+    let scan = tokio::spawn(async move {
+        scanner.scan_blocks(start..=end, DUST_LIMIT, true).await?;
+        anyhow::Ok(scanner)
+    });
+
+    stalled.await.unwrap();
+    cancel.cancel();
+    let scanner = scan.await.unwrap().unwrap();
+
+    // the updater is left at the last block before the stalled request
+    let last = Height::from_consensus(295124).unwrap();
+    assert_eq!(scanner.last_scanned_block().unwrap().0, last);
+    assert_eq!(progress.lock().unwrap().last().unwrap().0, last);
+    assert_eq!(updates.lock().unwrap().last().unwrap().blkheight, last);
+
+    // the scan is resumed with a new token
+    let checkpoints = progress.lock().unwrap().clone();
+    let mock_update = MockUpdater::default();
+    let updates = mock_update.updates.clone();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(mock_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        CancellationToken::new(),
+    )
+    .with_checkpoints(checkpoints);
+
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+
+    let updates = updates.lock().unwrap();
+    let heights: Vec<u32> = updates
+        .iter()
+        .map(|update| update.blkheight.to_consensus_u32())
+        .collect();
+    assert_eq!(heights, (295125..=295130).collect::<Vec<_>>());
+    assert_eq!(updates[0].discovered_outputs.len(), 1);
+}
+
+#[tokio::test]
+async fn history_from_scan_results() {
+    let payment_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+
+    let history_update = HistoryUpdater::default();
+    let history = history_update.history.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+    let cancel = CancellationToken::new();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(history_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        cancel.clone(),
+    );
+
+    let start = Height::from_consensus(295124).unwrap();
+    let end = Height::from_consensus(295147).unwrap();
+
+    scanner
+        .scan_blocks(start..=end, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let spent_index = mock_backend.spent_index(end).await.unwrap();
+
+    let history = history.lock().unwrap().clone();
+    let records = history.records();
+    assert_eq!(records.len(), 3);
+
+    // the payment of block 295125
+    let received = &records[0];
+    assert_eq!(received.txid, payment_outpoint.txid);
+    assert_eq!(received.direction(), TxDirection::Incoming);
+    assert_eq!(received.received(), Amount::from_sat(10_000));
+    assert_eq!(received.fee, None);
+    assert_eq!(received.labels().count(), 0);
+    assert_eq!(
+        received.confirmation_height,
+        Some(Height::from_consensus(295125).unwrap())
+    );
+
+    // block 295147 pays us again
+    let received = &records[1];
+    assert_eq!(received.direction(), TxDirection::Incoming);
+    assert_eq!(received.received(), Amount::from_sat(9_889));
+    assert_eq!(received.confirmation_height, Some(end));
+
+    // and spends the first payment, in a transaction of the spent index
+    let sent = &records[2];
+    assert!(
+        spent_index
+            .spending_txs
+            .iter()
+            .any(|tx| tx.txid == sent.txid)
+    );
+    assert_eq!(sent.direction(), TxDirection::Outgoing);
+    assert_eq!(sent.inputs[0].outpoint, payment_outpoint);
+    assert_eq!(sent.sent(), Amount::from_sat(10_000));
+    assert_eq!(sent.net(), SignedAmount::from_sat(-10_000));
+    assert_eq!(sent.fee, None);
+    assert_eq!(sent.confirmation_height, Some(end));
+    assert!(history.unlinked_spends().is_empty());
+
+    let json = serde_json::to_string(&history).unwrap();
+    let parsed: TxHistory = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, history);
+}
+
+#[tokio::test]
+async fn history_of_signed_transactions() {
+    let history_update = HistoryUpdater::default();
+    let history = history_update.history.clone();
+    let utxos = history_update.utxos.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+
+    let mut scanner = SpScanner::new(
+        client.clone(),
+        Box::new(history_update.clone()),
+        Box::new(MockChainBackend {}),
+        HashSet::new(),
+        CancellationToken::new(),
+    );
+
+    let payment_height = Height::from_consensus(295125).unwrap();
+    scanner
+        .scan_blocks(payment_height..=payment_height, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    let available_utxos: Vec<_> = utxos.lock().unwrap().clone().into_iter().collect();
+    assert_eq!(available_utxos.len(), 1);
+
+    // pay someone else, with change back to us
+    let secp = Secp256k1::signing_only();
+    let other_address = SilentPaymentAddressDisplay::new_v0(
+        SecretKey::from_slice(&[0x03; 32])
+            .unwrap()
+            .public_key(&secp),
+        SecretKey::from_slice(&[0x04; 32])
+            .unwrap()
+            .public_key(&secp),
+        silentpayments::Network::Testnet,
+    );
+    let recipients = vec![Recipient {
+        address: RecipientAddress::SpAddress(other_address),
+        amount: Amount::from_sat(5_000),
+    }];
+
+    let unsigned_tx = client
+        .create_new_transaction(
+            available_utxos.clone(),
+            recipients.clone(),
+            FeeRate::from_sat_per_vb(1.0),
+            Network::Signet,
+        )
+        .unwrap();
+    let unsigned_tx = SpClient::finalize_transaction(unsigned_tx).unwrap();
+    let signed_tx = client
+        .sign_transaction(unsigned_tx.clone(), &[0xaa; 32])
+        .unwrap();
+
+    let txid = history
+        .lock()
+        .unwrap()
+        .record_signed_transaction(&client, &unsigned_tx, &signed_tx)
+        .unwrap();
+
+    {
+        let history = history.lock().unwrap();
+        let sent = history.get(&txid).unwrap();
+        assert_eq!(sent.direction(), TxDirection::Outgoing);
+        assert_eq!(sent.recipients, recipients);
+        assert_eq!(sent.sent(), Amount::from_sat(10_000));
+        assert_eq!(sent.outputs.len(), 1);
+        assert_eq!(
+            sent.outputs[0].label,
+            Some(client.sp_receiver.list_labels().into_iter().next().unwrap())
+        );
+        let fee = sent.fee.unwrap();
+        assert!(fee > Amount::ZERO);
+        assert_eq!(
+            sent.received() + fee + Amount::from_sat(5_000),
+            Amount::from_sat(10_000)
+        );
+        assert_eq!(sent.confirmation_height, None);
+    }
+
+    // blindbit doesn't know the spending transaction, but the spent input is ours
+    let owned_outpoints = utxos.lock().unwrap().keys().cloned().collect();
+    let mut scanner = SpScanner::new(
+        client.clone(),
+        Box::new(history_update),
+        Box::new(MockChainBackend {}),
+        owned_outpoints,
+        CancellationToken::new(),
+    );
+
+    let spend_height = Height::from_consensus(295147).unwrap();
+    scanner
+        .scan_blocks(spend_height..=spend_height, DUST_LIMIT, true)
+        .await
+        .unwrap();
+
+    {
+        let history = history.lock().unwrap();
+        // the other payment in block 295147
+        assert_eq!(history.records().len(), 3);
+        assert_eq!(
+            history.get(&txid).unwrap().confirmation_height,
+            Some(spend_height)
+        );
+        assert!(history.unlinked_spends().is_empty());
+    }
+
+    // send everything to ourselves
+    let unsigned_tx = client
+        .create_drain_transaction(
+            available_utxos.clone(),
+            RecipientAddress::SpAddress(SilentPaymentAddressDisplay::from_sp_address(
+                client.get_receiving_address(),
+                silentpayments::Network::Testnet,
+            )),
+            FeeRate::from_sat_per_vb(1.0),
+            Network::Signet,
+        )
+        .unwrap();
+    let unsigned_tx = SpClient::finalize_transaction(unsigned_tx).unwrap();
+    let signed_tx = client
+        .sign_transaction(unsigned_tx.clone(), &[0xaa; 32])
+        .unwrap();
+
+    let mut history = TxHistory::new();
+    let txid = history
+        .record_signed_transaction(&client, &unsigned_tx, &signed_tx)
+        .unwrap();
+
+    let transfer = history.get(&txid).unwrap();
+    assert_eq!(transfer.direction(), TxDirection::SelfTransfer);
+    assert!(transfer.recipients.is_empty());
+    assert_eq!(transfer.outputs[0].label, None);
+    assert_eq!(transfer.net(), -transfer.fee.unwrap().to_signed().unwrap());
+
+    // a data output doesn't pay anyone
+    let data = Recipient {
+        address: RecipientAddress::Data(b"memo".to_vec()),
+        amount: Amount::ZERO,
+    };
+    let unsigned_memo = client
+        .create_new_transaction(
+            available_utxos,
+            vec![
+                Recipient {
+                    address: RecipientAddress::SpAddress(
+                        SilentPaymentAddressDisplay::from_sp_address(
+                            client.get_receiving_address(),
+                            silentpayments::Network::Testnet,
+                        ),
+                    ),
+                    amount: Amount::from_sat(5_000),
+                },
+                data.clone(),
+            ],
+            FeeRate::from_sat_per_vb(1.0),
+            Network::Signet,
+        )
+        .unwrap();
+    let unsigned_memo = SpClient::finalize_transaction(unsigned_memo).unwrap();
+    let signed_memo = client
+        .sign_transaction(unsigned_memo.clone(), &[0xaa; 32])
+        .unwrap();
+    let mut memo_history = TxHistory::new();
+    let memo_txid = memo_history
+        .record_signed_transaction(&client, &unsigned_memo, &signed_memo)
+        .unwrap();
+    let memo = memo_history.get(&memo_txid).unwrap();
+    assert_eq!(memo.direction(), TxDirection::SelfTransfer);
+    assert_eq!(memo.recipients, vec![data]);
+
+    // the unsigned transaction must match
+    let mut other_tx = signed_tx.clone();
+    other_tx.output[0].value = Amount::from_sat(1_000);
+    assert!(
+        history
+            .record_signed_transaction(&client, &unsigned_tx, &other_tx)
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn history_from_mempool_and_reorg() {
+    let payment_outpoint: OutPoint =
+        "93a9b81f81244f8e6be29d8d6b0a9dbe6d6de6d2d4b018001ebf855bc870be88:0"
+            .parse()
+            .unwrap();
+
+    let mock_backend = MockReorgBackend::default();
+
+    let history_update = HistoryUpdater::default();
+    let history = history_update.history.clone();
+
+    let scan_sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+    let spend_sk = SecretKey::from_slice(&[0x02; 32]).unwrap();
+    let client = SpClient::new(scan_sk, SpendKey::Secret(spend_sk), Network::Signet).unwrap();
+
+    let birthday = Height::from_consensus(295124).unwrap();
+    let birthday_hash = mock_backend.block_hash(birthday).await.unwrap();
+
+    let mut scanner = SpScanner::new(
+        client,
+        Box::new(history_update),
+        Box::new(mock_backend.clone()),
+        HashSet::new(),
+        CancellationToken::new(),
+    )
+    .with_checkpoints([(birthday, birthday_hash)]);
+
+    // the payment of block 295125 is in the mempool
+    mock_backend.set_tip(295124);
+    mock_backend.set_mempool(vec![295125]);
+
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+
+    {
+        let history = history.lock().unwrap();
+        assert_eq!(history.records().len(), 1);
+        let pending = history.get(&payment_outpoint.txid).unwrap();
+        assert_eq!(pending.direction(), TxDirection::Incoming);
+        assert_eq!(pending.received(), Amount::from_sat(10_000));
+        assert_eq!(pending.confirmation_height, None);
+    }
+
+    // it is confirmed
+    mock_backend.set_tip(295130);
+    mock_backend.set_mempool(vec![]);
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+    scanner.scan_mempool(DUST_LIMIT).await.unwrap();
+
+    assert_eq!(
+        history
+            .lock()
+            .unwrap()
+            .get(&payment_outpoint.txid)
+            .unwrap()
+            .confirmation_height,
+        Some(Height::from_consensus(295125).unwrap())
+    );
+
+    // and replaced by a block without the payment
+    mock_backend.reorg();
+    scanner.scan_to_tip(DUST_LIMIT, true).await.unwrap();
+
+    assert!(history.lock().unwrap().records().is_empty());
 }